@@ -0,0 +1,58 @@
+//! Chunk sizes that follow the link, for uploads racing an
+//! [`overall_timeout`](crate::StreamShareBuilder::overall_timeout).
+//!
+//! Each chunk should take a fixed share of the time left until the deadline.
+//! After every ACK the next chunk is scaled by how far the last one missed
+//! that target, limited per step so a single outlier can't swing it. A slow
+//! link thus gets small chunks and frequent ACKs, so a stall is noticed early
+//! and little is lost with it, while a fast one gets large chunks that waste
+//! fewer round trips.
+
+use std::time::{Duration, Instant};
+
+/// Smallest chunk the sizing goes down to.
+const MIN_CHUNK_SIZE: usize = 16 * 1024;
+/// A chunk should take this share of the time left.
+const TARGET_SHARE: u32 = 16;
+const MIN_TARGET: Duration = Duration::from_millis(50);
+const MAX_TARGET: Duration = Duration::from_secs(2);
+
+/// Picks the size of the next chunk of an upload with a deadline.
+pub(crate) struct ChunkSizer {
+    current: usize,
+    min: usize,
+    max: usize,
+    deadline: Instant,
+}
+
+impl ChunkSizer {
+    /// Starts at a quarter of `max`, the configured chunk size, leaving room
+    /// to grow on a fast link without ever needing a larger buffer.
+    pub(crate) fn new(max: usize, deadline: Instant) -> Self {
+        let min = MIN_CHUNK_SIZE.min(max);
+        Self {
+            current: (max / 4).max(min),
+            min,
+            max,
+            deadline,
+        }
+    }
+
+    pub(crate) fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Adjusts the size after a chunk of `len` bytes took `elapsed` from
+    /// being sent to its last ACK. A short chunk, like the last one of an
+    /// upload, says little about the link and is ignored.
+    pub(crate) fn record(&mut self, len: usize, elapsed: Duration) {
+        if len < self.current {
+            return;
+        }
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        let target = (remaining / TARGET_SHARE).clamp(MIN_TARGET, MAX_TARGET);
+        let scale = target.as_secs_f64() / elapsed.as_secs_f64().max(1e-6);
+        let next = (self.current as f64 * scale.clamp(0.5, 2.0)) as usize;
+        self.current = next.clamp(self.min, self.max);
+    }
+}
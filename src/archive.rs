@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio_util::compat::FuturesAsyncWriteCompatExt;
+
+use crate::{StreamShare, StreamShareError};
+
+const TAR_BLOCK: u64 = 512;
+
+/// Container format produced by [`StreamShare::download_archive`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+struct ArchiveEntry {
+    file_identifier: String,
+    name: String,
+    size: Option<u64>,
+}
+
+impl StreamShare {
+    /// Downloads several files into a single `.tar` or `.zip` archive.
+    ///
+    /// Each file is streamed straight into the archive, so nothing is staged
+    /// on disk. Entries are named after the server's filename, prefixed with
+    /// the file identifier when two files share a name. `download_path` is
+    /// resolved like in [`download`](Self::download), with `archive.tar` or
+    /// `archive.zip` as the filename for a directory. The callback receives
+    /// the bytes downloaded across all files and their combined size, or 0
+    /// if any size is unknown. Returns the path of the written archive.
+    pub async fn download_archive<F>(
+        &self,
+        identifiers: &[&str],
+        download_path: &str,
+        replace: bool,
+        format: ArchiveFormat,
+        mut callback: F,
+    ) -> Result<PathBuf, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let mut entries = Vec::with_capacity(identifiers.len());
+        let mut names = HashSet::new();
+        for file_identifier in identifiers {
+            let info = self.info(file_identifier).await?;
+            let name = info
+                .file_name
+                .unwrap_or_else(|| self.fallback_name(file_identifier));
+            let name = if names.insert(name.clone()) {
+                name
+            } else {
+                format!("{}-{}", file_identifier, name)
+            };
+            entries.push(ArchiveEntry {
+                file_identifier: file_identifier.to_string(),
+                name,
+                size: info.size,
+            });
+        }
+
+        let total = entries
+            .iter()
+            .map(|entry| entry.size)
+            .sum::<Option<u64>>()
+            .unwrap_or(0);
+        let mut downloaded = 0;
+        let mut progress = |bytes: u64| {
+            downloaded += bytes;
+            callback(downloaded, total);
+        };
+
+        let archive_name = format!("archive.{}", format.extension());
+        let archive_path = self.resolve_download_path(
+            download_path,
+            &archive_name,
+            &identifiers.join("-"),
+            replace,
+        )?;
+        if let Some(parent) = archive_path.parent() {
+            if !parent.as_os_str().is_empty() && !parent.exists() {
+                fs::create_dir_all(parent).await?;
+            }
+        }
+
+        let existed = archive_path.exists();
+        let file = File::create(&archive_path).await?;
+        let result = match format {
+            ArchiveFormat::Tar => self.write_tar(file, &entries, &mut progress).await,
+            ArchiveFormat::Zip => self.write_zip(file, &entries, &mut progress).await,
+        };
+
+        if let Err(e) = result {
+            if !existed {
+                let _ = fs::remove_file(&archive_path).await;
+            }
+            return Err(e);
+        }
+        Ok(archive_path)
+    }
+
+    async fn write_tar(
+        &self,
+        file: File,
+        entries: &[ArchiveEntry],
+        progress: &mut impl FnMut(u64),
+    ) -> Result<(), StreamShareError> {
+        let mut out = BufWriter::new(file);
+        let mut buffer = vec![0u8; self.chunk_size];
+        let mtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+
+        for entry in entries {
+            let mut reader = self.download_stream(&entry.file_identifier).await?;
+            let mut header = tar::Header::new_gnu();
+            header.set_path(&entry.name)?;
+            header.set_mode(0o644);
+            header.set_mtime(mtime);
+
+            // The header carries the size, so an entry without a known size
+            // has to be read into memory before it can be written.
+            let size = match entry.size {
+                Some(size) => {
+                    header.set_size(size);
+                    header.set_cksum();
+                    out.write_all(header.as_bytes()).await?;
+                    let written = copy_chunks(&mut reader, &mut out, &mut buffer, progress).await?;
+                    if written != size {
+                        return Err(StreamShareError::SizeMismatch {
+                            expected: size,
+                            actual: written,
+                        });
+                    }
+                    size
+                }
+                None => {
+                    let mut data = Vec::new();
+                    let size = copy_chunks(&mut reader, &mut data, &mut buffer, progress).await?;
+                    header.set_size(size);
+                    header.set_cksum();
+                    out.write_all(header.as_bytes()).await?;
+                    out.write_all(&data).await?;
+                    size
+                }
+            };
+
+            let padding = (TAR_BLOCK - size % TAR_BLOCK) % TAR_BLOCK;
+            out.write_all(&vec![0u8; padding as usize]).await?;
+        }
+
+        // Two empty blocks mark the end of the archive.
+        out.write_all(&[0u8; 2 * TAR_BLOCK as usize]).await?;
+        out.flush().await?;
+        Ok(())
+    }
+
+    async fn write_zip(
+        &self,
+        file: File,
+        entries: &[ArchiveEntry],
+        progress: &mut impl FnMut(u64),
+    ) -> Result<(), StreamShareError> {
+        let mut writer = ZipFileWriter::with_tokio(file);
+        let mut buffer = vec![0u8; self.chunk_size];
+
+        for entry in entries {
+            let mut reader = self.download_stream(&entry.file_identifier).await?;
+            let builder = ZipEntryBuilder::new(entry.name.clone().into(), Compression::Deflate);
+            let mut entry_writer = writer.write_entry_stream(builder).await?.compat_write();
+
+            let written =
+                copy_chunks(&mut reader, &mut entry_writer, &mut buffer, progress).await?;
+            if let Some(size) = entry.size.filter(|&size| size != written) {
+                return Err(StreamShareError::SizeMismatch {
+                    expected: size,
+                    actual: written,
+                });
+            }
+            entry_writer.into_inner().close().await?;
+        }
+
+        writer.close().await?.into_inner().flush().await?;
+        Ok(())
+    }
+}
+
+async fn copy_chunks<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    buffer: &mut [u8],
+    progress: &mut impl FnMut(u64),
+) -> std::io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut written = 0;
+    loop {
+        let read = reader.read(buffer).await?;
+        if read == 0 {
+            return Ok(written);
+        }
+        writer.write_all(&buffer[..read]).await?;
+        written += read as u64;
+        progress(read as u64);
+    }
+}
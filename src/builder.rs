@@ -0,0 +1,1292 @@
+use std::collections::HashMap;
+use std::env;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::redirect::Policy;
+use reqwest::Client;
+use tokio::sync::{OnceCell, Semaphore};
+
+use crate::connect::{http_local_address, ws_tls_config, HostLimits, IpPreference, TlsVersion};
+use crate::pool::BufferPool;
+#[cfg(feature = "middleware")]
+use crate::MiddlewareTransport;
+use crate::{
+    basic_header, BinaryAck, ChunkFraming, CloseCode, CompleteHook, ConflictDecision, ConflictHook,
+    CreatedHook, HashAlgorithm, HttpTransport, MessageHook, ProtocolVersion, ReqwestTransport,
+    RetryHook, Scheme, StreamShare, StreamShareError, TextAck, TransferMetrics, UploadResult,
+    VersionHook,
+};
+
+const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Most memory [`StreamShareBuilder::build`] accepts for the chunk buffers of
+/// all uploads that may run at once.
+const CHUNK_BUFFER_BUDGET: u64 = 4 * 1024 * 1024 * 1024;
+
+#[cfg(feature = "compression")]
+const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+
+/// Why [`StreamShareBuilder::compress`] and the checksum options exclude each
+/// other: a checksum of the uncompressed input wouldn't match what is stored.
+#[cfg(feature = "compression")]
+pub(crate) const COMPRESSED_CHECKSUM: &str =
+    "compress can't be combined with send_checksum or compute_checksum";
+
+/// Preset combinations of the client's timeouts, for
+/// [`StreamShareBuilder::timeout_profile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutProfile {
+    /// Fails fast so a UI can report problems quickly: 5s to connect, 15s
+    /// for reads and chunk acknowledgements, 30 minutes per upload.
+    Interactive,
+    /// Patient enough for unattended jobs on slow links: 30s to connect, 5
+    /// minutes for reads and chunk acknowledgements, no overall limit.
+    Batch,
+    /// No timeouts at all, the default.
+    Unlimited,
+}
+
+impl TimeoutProfile {
+    /// Connect, read, ack and overall timeouts.
+    fn timeouts(self) -> [Option<Duration>; 4] {
+        let secs = |secs| Some(Duration::from_secs(secs));
+        match self {
+            TimeoutProfile::Interactive => [secs(5), secs(15), secs(15), secs(30 * 60)],
+            TimeoutProfile::Batch => [secs(30), secs(5 * 60), secs(5 * 60), None],
+            TimeoutProfile::Unlimited => [None; 4],
+        }
+    }
+}
+
+/// Configures and creates a [`StreamShare`] client.
+///
+/// ```no_run
+/// use streamshare::StreamShare;
+///
+/// let client = StreamShare::builder()
+///     .server_url("streamshare.example.com")
+///     .chunk_size(512 * 1024)
+///     .build()?;
+/// # Ok::<(), streamshare::StreamShareError>(())
+/// ```
+pub struct StreamShareBuilder {
+    server_url: String,
+    chunk_size: usize,
+    max_concurrent_buffers: Option<usize>,
+    idempotency_keys: bool,
+    scheme: Scheme,
+    auth_token: Option<String>,
+    basic_auth: Option<(String, String)>,
+    completion_code: CloseCode,
+    completion_reason: String,
+    allow_non_regular: bool,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    ack_timeout: Option<Duration>,
+    overall_timeout: Option<Duration>,
+    max_concurrent_uploads: Option<usize>,
+    binary_ack: BinaryAck,
+    follow_symlinks: bool,
+    download_root: Option<PathBuf>,
+    local_address: Option<IpAddr>,
+    ip_preference: IpPreference,
+    download_precheck: bool,
+    expand_tilde: bool,
+    #[cfg(feature = "compression")]
+    compress: bool,
+    #[cfg(feature = "compression")]
+    compression_level: u32,
+    #[cfg(feature = "compression")]
+    auto_decompress: bool,
+    tcp_nodelay: bool,
+    tcp_send_buffer_size: Option<u32>,
+    tcp_recv_buffer_size: Option<u32>,
+    max_maintenance_wait: Duration,
+    verify_on_disk: bool,
+    on_created: Option<CreatedHook>,
+    on_retry: Option<RetryHook>,
+    protocol_version: ProtocolVersion,
+    send_checksum: bool,
+    write_buffer_size: usize,
+    sync_on_complete: bool,
+    ws_server_url: Option<String>,
+    ws_base_path: Option<String>,
+    deletion_token_header: String,
+    small_file_threshold: Option<u64>,
+    max_redirects: usize,
+    download_max_redirects: Option<usize>,
+    hash_algorithm: HashAlgorithm,
+    nack_token: String,
+    max_chunk_resends: u32,
+    max_frame_size: usize,
+    max_connections_per_host: Option<usize>,
+    pool_max_idle_per_host: Option<usize>,
+    http2_prior_knowledge: bool,
+    http2_adaptive_window: bool,
+    state_save_interval: u64,
+    progress_interval: Duration,
+    admin_key: Option<String>,
+    strict_version: bool,
+    on_version_mismatch: Option<VersionHook>,
+    origin: Option<String>,
+    availability_poll_interval: Duration,
+    reject_empty: bool,
+    compute_checksum: bool,
+    chunk_delay: Option<Duration>,
+    fallback_extension: Option<String>,
+    max_chunks: Option<u64>,
+    slow_down_token: Option<String>,
+    http_transport: Option<Arc<dyn HttpTransport>>,
+    keepalive_interval: Option<Duration>,
+    #[cfg(feature = "xattr")]
+    set_type_xattr: bool,
+    #[cfg(feature = "middleware")]
+    middleware: Option<reqwest_middleware::ClientWithMiddleware>,
+    min_tls_version: Option<TlsVersion>,
+    informational_prefixes: Vec<String>,
+    on_informational: Option<MessageHook>,
+    on_conflict: Option<ConflictHook>,
+    on_complete: Option<CompleteHook>,
+    allow_foreign_signed_urls: bool,
+    chunk_framing: ChunkFraming,
+    text_ack: TextAck,
+    adaptive_chunking: bool,
+    batch_retry_budget: Option<u32>,
+}
+
+impl StreamShareBuilder {
+    pub fn new() -> Self {
+        Self {
+            server_url: "streamshare.wireway.ch".to_string(),
+            chunk_size: 1024 * 1024,
+            max_concurrent_buffers: None,
+            idempotency_keys: false,
+            scheme: Scheme::Https,
+            auth_token: None,
+            basic_auth: None,
+            completion_code: CloseCode::Normal,
+            completion_reason: "FILE_UPLOAD_DONE".to_string(),
+            allow_non_regular: false,
+            connect_timeout: None,
+            read_timeout: None,
+            ack_timeout: None,
+            overall_timeout: None,
+            max_concurrent_uploads: None,
+            binary_ack: BinaryAck::Reject,
+            follow_symlinks: true,
+            download_root: None,
+            local_address: None,
+            ip_preference: IpPreference::Any,
+            download_precheck: false,
+            expand_tilde: true,
+            #[cfg(feature = "compression")]
+            compress: false,
+            #[cfg(feature = "compression")]
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            #[cfg(feature = "compression")]
+            auto_decompress: false,
+            tcp_nodelay: false,
+            tcp_send_buffer_size: None,
+            tcp_recv_buffer_size: None,
+            max_maintenance_wait: Duration::ZERO,
+            verify_on_disk: false,
+            on_created: None,
+            on_retry: None,
+            protocol_version: ProtocolVersion::V1,
+            send_checksum: false,
+            write_buffer_size: 8 * 1024,
+            sync_on_complete: false,
+            ws_server_url: None,
+            ws_base_path: None,
+            deletion_token_header: "x-deletion-token".to_string(),
+            small_file_threshold: None,
+            max_redirects: 10,
+            download_max_redirects: None,
+            hash_algorithm: HashAlgorithm::Sha256,
+            nack_token: "NACK".to_string(),
+            max_chunk_resends: 3,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_connections_per_host: None,
+            pool_max_idle_per_host: None,
+            http2_prior_knowledge: false,
+            http2_adaptive_window: false,
+            state_save_interval: 16,
+            progress_interval: Duration::ZERO,
+            admin_key: None,
+            strict_version: false,
+            on_version_mismatch: None,
+            origin: None,
+            availability_poll_interval: Duration::from_millis(250),
+            reject_empty: false,
+            compute_checksum: false,
+            chunk_delay: None,
+            fallback_extension: Some("unknown".into()),
+            max_chunks: None,
+            slow_down_token: None,
+            http_transport: None,
+            keepalive_interval: None,
+            #[cfg(feature = "xattr")]
+            set_type_xattr: false,
+            #[cfg(feature = "middleware")]
+            middleware: None,
+            min_tls_version: None,
+            informational_prefixes: Vec::new(),
+            on_informational: None,
+            on_conflict: None,
+            on_complete: None,
+            allow_foreign_signed_urls: false,
+            chunk_framing: ChunkFraming::Raw,
+            text_ack: TextAck::Plain,
+            adaptive_chunking: false,
+            batch_retry_budget: None,
+        }
+    }
+
+    /// Starts from the defaults and applies any of these environment variables
+    /// that are set:
+    ///
+    /// - `STREAMSHARE_SERVER`: server host, e.g. `streamshare.example.com`
+    /// - `STREAMSHARE_CHUNK_SIZE`: upload chunk size in bytes
+    /// - `STREAMSHARE_SECURE`: `true`/`false`, whether to use `https`/`wss`
+    /// - `STREAMSHARE_AUTH_TOKEN`: bearer token sent with every request
+    pub fn from_env() -> Result<Self, StreamShareError> {
+        let mut builder = Self::new();
+
+        if let Ok(server_url) = env::var("STREAMSHARE_SERVER") {
+            builder = builder.server_url(server_url);
+        }
+
+        if let Ok(chunk_size) = env::var("STREAMSHARE_CHUNK_SIZE") {
+            let chunk_size = chunk_size
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&size| size > 0)
+                .ok_or_else(|| {
+                    StreamShareError::InvalidConfig(format!(
+                        "Invalid STREAMSHARE_CHUNK_SIZE: {}",
+                        chunk_size
+                    ))
+                })?;
+            builder = builder.chunk_size(chunk_size);
+        }
+
+        if let Ok(secure) = env::var("STREAMSHARE_SECURE") {
+            let secure = match secure.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => true,
+                "0" | "false" | "no" | "off" => false,
+                _ => {
+                    return Err(StreamShareError::InvalidConfig(format!(
+                        "Invalid STREAMSHARE_SECURE: {}",
+                        secure
+                    )))
+                }
+            };
+            builder = builder.secure(secure);
+        }
+
+        if let Ok(auth_token) = env::var("STREAMSHARE_AUTH_TOKEN") {
+            builder = builder.auth_token(auth_token);
+        }
+
+        Ok(builder)
+    }
+
+    pub fn server_url(mut self, server_url: impl Into<String>) -> Self {
+        self.server_url = server_url.into();
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Limits how many chunk buffers may be allocated at once across all
+    /// concurrent uploads on this client.
+    ///
+    /// Buffers are reused between uploads; when all of them are in use, new
+    /// uploads wait until one is returned. By default every upload allocates
+    /// its own buffer.
+    pub fn max_concurrent_buffers(mut self, max_concurrent_buffers: usize) -> Self {
+        self.max_concurrent_buffers = Some(max_concurrent_buffers);
+        self
+    }
+
+    /// Sends a freshly generated UUID as the `Idempotency-Key` of every create
+    /// request that doesn't set [`UploadOptions::idempotency_key`] itself.
+    ///
+    /// The server has to support the header for this to prevent duplicates;
+    /// otherwise it is just an extra header.
+    ///
+    /// [`UploadOptions::idempotency_key`]: crate::UploadOptions::idempotency_key
+    pub fn idempotency_keys(mut self, enabled: bool) -> Self {
+        self.idempotency_keys = enabled;
+        self
+    }
+
+    /// Whether to talk to the server over `https`/`wss` (the default) or plain
+    /// `http`/`ws`.
+    pub fn scheme(mut self, scheme: Scheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Shorthand for [`scheme`](Self::scheme): `true` is [`Scheme::Https`],
+    /// `false` is [`Scheme::Http`].
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.scheme = secure.into();
+        self
+    }
+
+    /// Bearer token sent as `Authorization` header with every HTTP request and
+    /// the WebSocket handshake.
+    pub fn auth_token(mut self, auth_token: impl Into<String>) -> Self {
+        self.auth_token = Some(auth_token.into());
+        self
+    }
+
+    /// Credentials sent as `Authorization: Basic` header with every HTTP
+    /// request and the WebSocket handshake, e.g. for a reverse proxy in front
+    /// of the server.
+    ///
+    /// They can also be given as part of the server URL, like
+    /// `user:pass@streamshare.example.com`; credentials set here take
+    /// precedence. If an [`auth_token`](Self::auth_token) is set as well, the
+    /// bearer token is sent instead.
+    pub fn basic_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.basic_auth = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Close code sent once all chunks were acknowledged. Defaults to
+    /// [`CloseCode::Normal`].
+    pub fn completion_code(mut self, completion_code: CloseCode) -> Self {
+        self.completion_code = completion_code;
+        self
+    }
+
+    /// Close reason sent once all chunks were acknowledged. Defaults to
+    /// `FILE_UPLOAD_DONE`, which is what the StreamShare server expects.
+    pub fn completion_reason(mut self, completion_reason: impl Into<String>) -> Self {
+        self.completion_reason = completion_reason.into();
+        self
+    }
+
+    /// Allows `upload` to read from non-regular files such as named pipes or
+    /// `/dev/stdin`. Their size is unknown, so progress totals are reported as `0`.
+    pub fn allow_non_regular(mut self, allow_non_regular: bool) -> Self {
+        self.allow_non_regular = allow_non_regular;
+        self
+    }
+
+    /// Sets all timeouts at once from a [`TimeoutProfile`]. Setters called
+    /// afterwards override single values.
+    pub fn timeout_profile(mut self, profile: TimeoutProfile) -> Self {
+        let [connect, read, ack, overall] = profile.timeouts();
+        self.connect_timeout = connect;
+        self.read_timeout = read;
+        self.ack_timeout = ack;
+        self.overall_timeout = overall;
+        self
+    }
+
+    /// How long to wait for a TCP connection to the server, for both HTTP
+    /// requests and the upload WebSocket.
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// How long an HTTP response may go without sending data, e.g. while a
+    /// download is stalled.
+    pub fn read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = Some(read_timeout);
+        self
+    }
+
+    /// How long to wait for the server to acknowledge a chunk before failing
+    /// with [`StreamShareError::AckTimeout`].
+    pub fn ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.ack_timeout = Some(ack_timeout);
+        self
+    }
+
+    /// Upper bound for streaming an upload, starting when its WebSocket is
+    /// opened. Exceeding it fails with [`StreamShareError::Timeout`].
+    pub fn overall_timeout(mut self, overall_timeout: Duration) -> Self {
+        self.overall_timeout = Some(overall_timeout);
+        self
+    }
+
+    /// Resizes the chunks of an upload as it goes, aiming to finish within
+    /// the [`overall_timeout`](Self::overall_timeout): chunks shrink while
+    /// ACKs come back slowly and grow while they come back fast, up to the
+    /// [`chunk_size`](Self::chunk_size). The upload starts at a quarter of
+    /// it. Without an `overall_timeout` this has no effect. Defaults to
+    /// `false`.
+    ///
+    /// Each chunk is sized to take a sixteenth of the time left, between
+    /// 50 ms and 2 s, changing by at most half or double from one chunk to
+    /// the next. Frames never exceed the
+    /// [`max_frame_size`](Self::max_frame_size) either way.
+    pub fn adaptive_chunking(mut self, adaptive_chunking: bool) -> Self {
+        self.adaptive_chunking = adaptive_chunking;
+        self
+    }
+
+    /// Caps how many uploads may run at once on this client, even when they
+    /// are started from separate tasks. Further uploads wait for a slot.
+    pub fn max_concurrent_uploads(mut self, max_concurrent_uploads: usize) -> Self {
+        self.max_concurrent_uploads = Some(max_concurrent_uploads);
+        self
+    }
+
+    /// Accepts binary chunk acknowledgements in the given format, in addition
+    /// to the text `ACK`. Acknowledged offsets larger than the bytes sent fail
+    /// the upload.
+    pub fn binary_ack(mut self, binary_ack: BinaryAck) -> Self {
+        self.binary_ack = binary_ack;
+        self
+    }
+
+    /// Whether downloads may be written through symlinks. Defaults to `true`.
+    ///
+    /// When disabled, the destination must not be a symlink and its resolved
+    /// parent directory must lie within the [download root](Self::download_root),
+    /// so neither symlinked directories nor `..` in a server-provided filename
+    /// can redirect the write elsewhere.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Directory downloads have to stay within when symlinks aren't followed.
+    /// Defaults to the current working directory.
+    pub fn download_root(mut self, download_root: impl Into<PathBuf>) -> Self {
+        self.download_root = Some(download_root.into());
+        self
+    }
+
+    /// Local IP address to bind outgoing HTTP and WebSocket connections to.
+    pub fn local_address(mut self, local_address: IpAddr) -> Self {
+        self.local_address = Some(local_address);
+        self
+    }
+
+    /// Restricts HTTP and WebSocket connections to one IP version, e.g. to
+    /// force IPv4 on networks with broken IPv6.
+    pub fn ip_preference(mut self, ip_preference: IpPreference) -> Self {
+        self.ip_preference = ip_preference;
+        self
+    }
+
+    /// Issues a `HEAD` request before each download so a missing file or an
+    /// unusable destination path fails before any body is transferred.
+    pub fn download_precheck(mut self, download_precheck: bool) -> Self {
+        self.download_precheck = download_precheck;
+        self
+    }
+
+    /// Whether a leading `~` in download paths is expanded to the home directory.
+    /// Enabled by default; disable it when paths are already expanded or may
+    /// legitimately start with `~`, which is then taken literally.
+    pub fn expand_tilde(mut self, expand_tilde: bool) -> Self {
+        self.expand_tilde = expand_tilde;
+        self
+    }
+
+    /// Gzip-compresses uploads on the fly. The file is stored on the server
+    /// compressed, with `.gz` appended to its name. Progress then counts
+    /// compressed bytes against an unknown (`0`) total. Can't be combined with
+    /// [`send_checksum`](Self::send_checksum) or
+    /// [`compute_checksum`](Self::compute_checksum), as the compressed bytes
+    /// are only known once they are sent.
+    #[cfg(feature = "compression")]
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Gzip level used when [`compress`](Self::compress) is enabled, from `0`
+    /// (no compression) to `9` (smallest output, most CPU). Higher levels
+    /// help on slow links; lower levels keep fast links from being limited
+    /// by the CPU. Defaults to `6`; [`build`](Self::build) rejects anything
+    /// above `9`.
+    #[cfg(feature = "compression")]
+    pub fn compression_level(mut self, compression_level: u32) -> Self {
+        self.compression_level = compression_level;
+        self
+    }
+
+    /// Decompresses downloads whose server filename ends in `.gz` while
+    /// writing them, saving them without the extension. A file that isn't
+    /// actually gzip fails with [`StreamShareError::NotGzip`] before anything
+    /// is written. Progress counts compressed bytes.
+    #[cfg(feature = "compression")]
+    pub fn auto_decompress(mut self, auto_decompress: bool) -> Self {
+        self.auto_decompress = auto_decompress;
+        self
+    }
+
+    /// Sets `TCP_NODELAY` on the upload WebSocket, so small chunks are sent
+    /// immediately instead of waiting to be coalesced while the previous
+    /// acknowledgement is outstanding.
+    pub fn tcp_nodelay(mut self, tcp_nodelay: bool) -> Self {
+        self.tcp_nodelay = tcp_nodelay;
+        self
+    }
+
+    /// Sets `SO_SNDBUF` on the upload WebSocket. On links with a large
+    /// bandwidth-delay product, e.g. 1 Gbit/s at 100 ms needs about 12 MiB,
+    /// a small buffer caps throughput no matter the chunk size.
+    ///
+    /// Setting it turns off the OS's own buffer autotuning for the socket on
+    /// Linux, and the kernel clamps the value to `net.core.wmem_max` (and
+    /// doubles it for bookkeeping). Leave it unset unless autotuning falls
+    /// short. Defaults to the OS default.
+    pub fn tcp_send_buffer_size(mut self, tcp_send_buffer_size: u32) -> Self {
+        self.tcp_send_buffer_size = Some(tcp_send_buffer_size);
+        self
+    }
+
+    /// Sets `SO_RCVBUF` on the upload WebSocket, which bounds the window the
+    /// client advertises for ACKs and server messages. Like
+    /// [`tcp_send_buffer_size`](Self::tcp_send_buffer_size) it disables
+    /// autotuning on Linux, where it's clamped to `net.core.rmem_max`. Uploads
+    /// mostly send, so this rarely matters. Defaults to the OS default.
+    pub fn tcp_recv_buffer_size(mut self, tcp_recv_buffer_size: u32) -> Self {
+        self.tcp_recv_buffer_size = Some(tcp_recv_buffer_size);
+        self
+    }
+
+    /// How long create, download and delete keep retrying while the server
+    /// answers `503 Service Unavailable`, waiting as long as its `Retry-After`
+    /// header asks each time. Once waiting longer would exceed this, or the
+    /// server gives no `Retry-After`, [`StreamShareError::ServerUnavailable`]
+    /// is returned. Each retry counts as at least a second, even after a
+    /// `Retry-After: 0`. Defaults to zero, which doesn't retry at all.
+    pub fn max_maintenance_wait(mut self, max_maintenance_wait: Duration) -> Self {
+        self.max_maintenance_wait = max_maintenance_wait;
+        self
+    }
+
+    /// Caps the retries of one batch operation, like
+    /// [`download_many`](StreamShare::download_many), in total across all of
+    /// its items, so an outage doesn't turn into a retry for every item. Once
+    /// it is used up, the retrying item and all items not started yet fail
+    /// with [`StreamShareError::RetryBudgetExhausted`]. Defaults to no cap,
+    /// leaving each request to [`max_maintenance_wait`](Self::max_maintenance_wait).
+    pub fn batch_retry_budget(mut self, batch_retry_budget: u32) -> Self {
+        self.batch_retry_budget = Some(batch_retry_budget);
+        self
+    }
+
+    /// After a download is written, syncs the file, reads it back and compares
+    /// its [`hash_algorithm`](Self::hash_algorithm) hash with the hash of the
+    /// received data, to catch corruption between the network and the disk.
+    /// This reads every file a second time, so it roughly doubles the disk I/O
+    /// of a download.
+    pub fn verify_on_disk(mut self, verify_on_disk: bool) -> Self {
+        self.verify_on_disk = verify_on_disk;
+        self
+    }
+
+    /// Called as soon as the server has created a file, before any data is
+    /// sent. Persisting the deletion token here means a file left behind by
+    /// a failed upload can still be deleted.
+    pub fn on_created<F>(mut self, on_created: F) -> Self
+    where
+        F: Fn(&UploadResult) + Send + Sync + 'static,
+    {
+        self.on_created = Some(Arc::new(on_created));
+        self
+    }
+
+    /// Called before a request is retried, with the number of the retry
+    /// starting at 1 and the error that caused it, e.g. to log "retrying
+    /// (attempt 2)". It runs before waiting for the next attempt, so a
+    /// retrying request doesn't just look like a hang. Requests are retried
+    /// after a `503` within [`max_maintenance_wait`](Self::max_maintenance_wait).
+    pub fn on_retry<F>(mut self, on_retry: F) -> Self
+    where
+        F: Fn(u32, &StreamShareError) + Send + Sync + 'static,
+    {
+        self.on_retry = Some(Arc::new(on_retry));
+        self
+    }
+
+    /// Which shape of the `/api/create` request and response to use. Defaults
+    /// to [`ProtocolVersion::V1`], the format of the current server.
+    pub fn protocol_version(mut self, protocol_version: ProtocolVersion) -> Self {
+        self.protocol_version = protocol_version;
+        self
+    }
+
+    /// Sends a hex checksum of the file in the create request, in a field named
+    /// after the [`hash_algorithm`](Self::hash_algorithm) (`sha256` by
+    /// default), so a server that supports it can verify or deduplicate the
+    /// upload. Servers that don't know the field ignore it.
+    ///
+    /// File uploads are read an extra time to compute it before any data is
+    /// sent, which can take a while for large files. Data passed to
+    /// [`upload_bytes`](StreamShare::upload_bytes) is hashed in memory, and
+    /// uploads from readers never send a checksum.
+    pub fn send_checksum(mut self, send_checksum: bool) -> Self {
+        self.send_checksum = send_checksum;
+        self
+    }
+
+    /// Capacity of the buffer downloads are written through, in bytes. Defaults to
+    /// 8 KiB.
+    ///
+    /// Small network chunks are collected until the buffer is full, saving a
+    /// write per chunk; chunks at least this large are written directly.
+    pub fn write_buffer_size(mut self, write_buffer_size: usize) -> Self {
+        self.write_buffer_size = write_buffer_size;
+        self
+    }
+
+    /// Calls `fsync` on a downloaded file before the download returns, so its
+    /// contents survive a crash right afterwards. Off by default, as it can be
+    /// slow.
+    pub fn sync_on_complete(mut self, sync_on_complete: bool) -> Self {
+        self.sync_on_complete = sync_on_complete;
+        self
+    }
+
+    /// Host the upload WebSocket connects to, for deployments that serve it
+    /// separately from the HTTP API. Defaults to the [`server_url`](Self::server_url).
+    pub fn ws_server_url(mut self, ws_server_url: impl Into<String>) -> Self {
+        self.ws_server_url = Some(ws_server_url.into());
+        self
+    }
+
+    /// Path prefix for the upload WebSocket, e.g. `/gateway` to connect to
+    /// `/gateway/api/upload/{id}`. Unset by default.
+    pub fn ws_base_path(mut self, ws_base_path: impl Into<String>) -> Self {
+        self.ws_base_path = Some(ws_base_path.into());
+        self
+    }
+
+    /// Response header checked for the deletion token when the create response
+    /// body doesn't contain one, first on the create response and then on the
+    /// upload WebSocket handshake. Defaults to `X-Deletion-Token`.
+    pub fn deletion_token_header(mut self, deletion_token_header: impl Into<String>) -> Self {
+        self.deletion_token_header = deletion_token_header.into();
+        self
+    }
+
+    /// Uploads files of at most this many bytes in a single HTTP request instead
+    /// of over the WebSocket, if the server reports
+    /// [`direct_upload`](crate::ServerCapabilities::direct_upload) support.
+    ///
+    /// This saves the handshake and close for e.g. screenshots. The file is sent
+    /// as the body of `POST /api/upload?name={name}`, answered like
+    /// `/api/create`. Uploads with a description, tags or password always use the
+    /// WebSocket. Off by default.
+    pub fn small_file_threshold(mut self, small_file_threshold: u64) -> Self {
+        self.small_file_threshold = Some(small_file_threshold);
+        self
+    }
+
+    /// How many redirects HTTP requests follow before failing. Defaults to 10.
+    ///
+    /// Downloads use [`download_max_redirects`](Self::download_max_redirects)
+    /// instead, so this can be tightened for create and delete without breaking
+    /// downloads served from a CDN.
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// How many redirects downloads follow, e.g. to a CDN, before failing.
+    /// Defaults to [`max_redirects`](Self::max_redirects).
+    pub fn download_max_redirects(mut self, download_max_redirects: usize) -> Self {
+        self.download_max_redirects = Some(download_max_redirects);
+        self
+    }
+
+    /// Hash used by [`send_checksum`](Self::send_checksum) and
+    /// [`verify_on_disk`](Self::verify_on_disk). Defaults to SHA-256.
+    pub fn hash_algorithm(mut self, hash_algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    /// Text message with which the server asks for the last chunk again.
+    /// Defaults to `NACK`. Any other text than `ACK` fails the upload with
+    /// [`StreamShareError::ChunkRejected`].
+    pub fn nack_token(mut self, nack_token: impl Into<String>) -> Self {
+        self.nack_token = nack_token.into();
+        self
+    }
+
+    /// How often a chunk is sent again after a [`nack_token`](Self::nack_token)
+    /// before the upload fails. Defaults to 3.
+    pub fn max_chunk_resends(mut self, max_chunk_resends: u32) -> Self {
+        self.max_chunk_resends = max_chunk_resends;
+        self
+    }
+
+    /// Largest WebSocket message sent during an upload. Chunks bigger than this
+    /// are split into several messages, each acknowledged separately, so a
+    /// large [`chunk_size`](Self::chunk_size) only affects how much is read
+    /// at once. Defaults to 16 MiB, the usual server-side limit.
+    pub fn max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size.max(1);
+        self
+    }
+
+    /// Caps how many connections this client keeps busy on one host at a time:
+    /// requests count until their response body has been read or dropped,
+    /// uploads for as long as their WebSocket is open. Others wait for a free
+    /// slot. Around 4 is a polite value for a shared server; combine with
+    /// [`max_concurrent_uploads`](Self::max_concurrent_uploads) to bound a
+    /// large batch.
+    pub fn max_connections_per_host(mut self, max_connections_per_host: usize) -> Self {
+        self.max_connections_per_host = Some(max_connections_per_host.max(1));
+        self
+    }
+
+    /// How many idle keep-alive connections to keep per host. Defaults to
+    /// `reqwest`'s unlimited pool.
+    pub fn pool_max_idle_per_host(mut self, pool_max_idle_per_host: usize) -> Self {
+        self.pool_max_idle_per_host = Some(pool_max_idle_per_host);
+        self
+    }
+
+    /// Talks HTTP/2 to the server without negotiating it first, which plain
+    /// `http` servers need for HTTP/2 at all. Over `https` HTTP/2 is already
+    /// picked through ALPN when the server offers it. With HTTP/2 concurrent
+    /// requests, e.g. of [`download_many`](StreamShare::download_many), share
+    /// one connection instead of opening one each. A server that only speaks
+    /// HTTP/1.1 fails every request. Uploads keep their own WebSocket
+    /// connections. Defaults to `false`.
+    pub fn http2_prior_knowledge(mut self, http2_prior_knowledge: bool) -> Self {
+        self.http2_prior_knowledge = http2_prior_knowledge;
+        self
+    }
+
+    /// Grows HTTP/2 flow-control windows with the measured bandwidth instead
+    /// of keeping `hyper`'s fixed defaults, which helps large downloads over
+    /// links with a high round-trip time. Defaults to `false`.
+    pub fn http2_adaptive_window(mut self, http2_adaptive_window: bool) -> Self {
+        self.http2_adaptive_window = http2_adaptive_window;
+        self
+    }
+
+    /// How many chunks [`upload_with_state`](StreamShare::upload_with_state)
+    /// sends between writes of its state file. Defaults to 16.
+    pub fn state_save_interval(mut self, state_save_interval: u64) -> Self {
+        self.state_save_interval = state_save_interval.max(1);
+        self
+    }
+
+    /// Calls upload progress callbacks at most once per interval. The final
+    /// call for a finished upload always happens. Defaults to no throttling.
+    pub fn progress_interval(mut self, progress_interval: Duration) -> Self {
+        self.progress_interval = progress_interval;
+        self
+    }
+
+    /// Key for [`admin_delete`](StreamShare::admin_delete), sent only with admin
+    /// requests. Falls back to the [`auth_token`](Self::auth_token).
+    pub fn admin_key(mut self, admin_key: impl Into<String>) -> Self {
+        self.admin_key = Some(admin_key.into());
+        self
+    }
+
+    /// Fails requests with [`StreamShareError::IncompatibleServer`] when the
+    /// server's `X-StreamShare-Version` has an unsupported major version,
+    /// instead of only reporting it to
+    /// [`on_version_mismatch`](Self::on_version_mismatch). Defaults to `false`.
+    pub fn strict_version(mut self, strict_version: bool) -> Self {
+        self.strict_version = strict_version;
+        self
+    }
+
+    /// Called with the server's version the first time it turns out to be
+    /// outside the range this crate supports, e.g. to log a warning that
+    /// explains otherwise confusing protocol errors. Only the
+    /// `X-StreamShare-Version` header of create and info responses is checked.
+    pub fn on_version_mismatch<F>(mut self, on_version_mismatch: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.on_version_mismatch = Some(Arc::new(on_version_mismatch));
+        self
+    }
+
+    /// `Origin` header sent with the upload WebSocket handshake, for servers
+    /// that check it, e.g. `https://streamshare.example.com`. None is sent by
+    /// default.
+    pub fn origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = Some(origin.into());
+        self
+    }
+
+    /// How often [`wait_until_available`](StreamShare::wait_until_available)
+    /// checks whether a file can be downloaded yet. Defaults to 250ms.
+    pub fn availability_poll_interval(mut self, availability_poll_interval: Duration) -> Self {
+        self.availability_poll_interval = availability_poll_interval;
+        self
+    }
+
+    /// Fails uploads of zero-byte files with [`StreamShareError::EmptyFile`]
+    /// before anything is sent, e.g. to catch a failed export. Defaults to
+    /// `false`, so empty files can still be shared on purpose.
+    pub fn reject_empty(mut self, reject_empty: bool) -> Self {
+        self.reject_empty = reject_empty;
+        self
+    }
+
+    /// Hashes file uploads with the [`hash_algorithm`](Self::hash_algorithm)
+    /// while they are sent and returns the result in
+    /// [`UploadResult::checksum`](crate::UploadResult::checksum), so the file
+    /// is read only once. The checksum isn't sent to the server; that needs
+    /// [`send_checksum`](Self::send_checksum), which has to hash before the
+    /// upload starts and takes precedence. Can't be combined with
+    /// [`compress`](Self::compress).
+    pub fn compute_checksum(mut self, compute_checksum: bool) -> Self {
+        self.compute_checksum = compute_checksum;
+        self
+    }
+
+    /// Waits this long after each acknowledged chunk, e.g. to watch progress
+    /// bars or test cancellation against a local server that would otherwise
+    /// finish instantly. For limiting throughput, a fixed delay is only a
+    /// crude tool.
+    pub fn chunk_delay(mut self, chunk_delay: Duration) -> Self {
+        self.chunk_delay = Some(chunk_delay);
+        self
+    }
+
+    /// Extension for downloads whose file name the server doesn't send, saved as
+    /// `{id}.{extension}`. Defaults to `unknown`; `None` saves them as just `{id}`.
+    pub fn fallback_extension(mut self, fallback_extension: Option<&str>) -> Self {
+        self.fallback_extension = fallback_extension.map(str::to_string);
+        self
+    }
+
+    /// Refuses uploads of a known size that would take more than `max_chunks`
+    /// chunks with [`StreamShareError::TooManyChunks`], before anything is sent.
+    /// Guards against a tiny [`chunk_size`](Self::chunk_size) on a huge file.
+    pub fn max_chunks(mut self, max_chunks: u64) -> Self {
+        self.max_chunks = Some(max_chunks);
+        self
+    }
+
+    /// Text message with which the server asks for a pause before the next
+    /// chunk, e.g. `THROTTLE`. `THROTTLE:500` pauses for 500 ms, the bare token
+    /// for one second. Unset by default, so such a message fails the upload with
+    /// [`StreamShareError::ChunkRejected`] like any other unexpected text.
+    pub fn slow_down_token(mut self, slow_down_token: impl Into<String>) -> Self {
+        self.slow_down_token = Some(slow_down_token.into());
+        self
+    }
+
+    /// Sends create, update, delete and info requests through `http_transport`
+    /// instead of the built-in `reqwest` client, e.g. to record or fake them.
+    /// Uploads and downloads are not affected; see [`HttpTransport`].
+    pub fn http_transport(mut self, http_transport: impl HttpTransport + 'static) -> Self {
+        self.http_transport = Some(Arc::new(http_transport));
+        self
+    }
+
+    /// Sends a WebSocket ping every `keepalive_interval` while an upload is idle:
+    /// paused, waiting for data to read, e.g. in
+    /// [`upload_growing`](StreamShare::upload_growing), or sleeping for
+    /// [`chunk_delay`](Self::chunk_delay) or a slow-down request. Keeps proxies
+    /// from closing a quiet connection. Off by default.
+    pub fn keepalive_interval(mut self, keepalive_interval: Duration) -> Self {
+        self.keepalive_interval = Some(keepalive_interval);
+        self
+    }
+
+    /// Stores the server's `Content-Type` of a download in the file's
+    /// `user.mime_type` extended attribute, so desktop environments can pick an app
+    /// for files without a meaningful extension. Skipped silently where extended
+    /// attributes aren't supported. Needs the `xattr` feature.
+    #[cfg(feature = "xattr")]
+    pub fn set_type_xattr(mut self, set_type_xattr: bool) -> Self {
+        self.set_type_xattr = set_type_xattr;
+        self
+    }
+
+    /// Sends create, update, delete, info, download and warm-up requests
+    /// through a `reqwest-middleware` stack, e.g. to add retries, tracing or
+    /// caching. Requests are still built by the client, so auth headers are
+    /// set, but sent by `middleware`'s own `reqwest` client, whose timeouts and
+    /// redirect policy apply instead of the ones set here. The upload WebSocket
+    /// doesn't go through middleware, and an
+    /// [`http_transport`](Self::http_transport) takes precedence for the
+    /// requests other than downloads and warm-ups. Needs the `middleware`
+    /// feature.
+    #[cfg(feature = "middleware")]
+    pub fn middleware(mut self, middleware: reqwest_middleware::ClientWithMiddleware) -> Self {
+        self.middleware = Some(middleware);
+        self
+    }
+
+    /// Refuses TLS versions below `min_tls_version` on both HTTP and `wss://`
+    /// connections, e.g. [`TlsVersion::Tls13`] to satisfy a security policy.
+    /// Setting it moves HTTP requests from the platform's TLS library to `rustls`,
+    /// which the WebSocket already uses and which never speaks TLS 1.0 or 1.1.
+    /// Unset by default, leaving the versions to each backend.
+    pub fn min_tls_version(mut self, min_tls_version: TlsVersion) -> Self {
+        self.min_tls_version = Some(min_tls_version);
+        self
+    }
+
+    /// Text frames starting with one of `informational_prefixes`, e.g. `PROGRESS`,
+    /// are skipped while waiting for a chunk acknowledgement instead of failing the
+    /// upload with [`StreamShareError::ChunkRejected`], for servers that send status
+    /// messages between `ACK`s. They are passed to
+    /// [`on_informational`](Self::on_informational) if set. `ACK` and the
+    /// [`nack_token`](Self::nack_token) are never skipped. Empty by default, so any
+    /// other text fails the upload.
+    pub fn informational_prefixes(
+        mut self,
+        informational_prefixes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.informational_prefixes = informational_prefixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Called with each text frame skipped because of
+    /// [`informational_prefixes`](Self::informational_prefixes), e.g. to show a
+    /// server's status messages. It runs on the upload task, so it should return
+    /// quickly.
+    pub fn on_informational<F>(mut self, on_informational: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.on_informational = Some(Arc::new(on_informational));
+        self
+    }
+
+    /// Decides what happens when a download's destination already exists and
+    /// `replace` is `false`, e.g. to ask "file exists, overwrite? [y/N]". The
+    /// callback gets the existing path and may replace it, skip the download or
+    /// pick another path. Without it such a download fails with
+    /// [`StreamShareError::FileExists`]. `replace: true` still overwrites without
+    /// asking.
+    pub fn on_conflict<F>(mut self, on_conflict: F) -> Self
+    where
+        F: Fn(&Path) -> ConflictDecision + Send + Sync + 'static,
+    {
+        self.on_conflict = Some(Arc::new(on_conflict));
+        self
+    }
+
+    /// Called once at the end of every upload and every download into a
+    /// file, successful or not, with its [`TransferMetrics`], e.g. to export
+    /// them to a metrics system. Measuring starts before the create or
+    /// download request is sent, so a failed create, a refused download and
+    /// a dropped future are all reported; only checks of the local file
+    /// before that, like a missing path, aren't. Streaming downloads like
+    /// [`download_stream`](StreamShare::download_stream) leave the reading to
+    /// the caller and aren't reported.
+    pub fn on_complete<F>(mut self, on_complete: F) -> Self
+    where
+        F: Fn(&TransferMetrics) + Send + Sync + 'static,
+    {
+        self.on_complete = Some(Arc::new(on_complete));
+        self
+    }
+
+    /// Lets [`download_signed`](StreamShare::download_signed) fetch URLs on any
+    /// host, e.g. a CDN serving signed links, instead of only the configured server.
+    /// Auth credentials are still only sent to the server. Defaults to `false`.
+    pub fn allow_foreign_signed_urls(mut self, allow_foreign_signed_urls: bool) -> Self {
+        self.allow_foreign_signed_urls = allow_foreign_signed_urls;
+        self
+    }
+
+    /// How each binary upload message is laid out, for servers that want a length or
+    /// index header before the data. Defaults to [`ChunkFraming::Raw`].
+    pub fn chunk_framing(mut self, chunk_framing: ChunkFraming) -> Self {
+        self.chunk_framing = chunk_framing;
+        self
+    }
+
+    /// How text acknowledgements are read. [`TextAck::Offset`] accepts `ACK:<bytes>`
+    /// and checks the count against what was sent. Defaults to [`TextAck::Plain`].
+    pub fn text_ack(mut self, text_ack: TextAck) -> Self {
+        self.text_ack = text_ack;
+        self
+    }
+
+    /// Checks the configuration and creates the client.
+    ///
+    /// Fails with [`StreamShareError::InvalidConfig`] for a zero chunk size, an
+    /// empty server URL, a server URL whose `http://` or `https://` contradicts
+    /// [`scheme`](Self::scheme), chunk buffers for
+    /// [`max_concurrent_buffers`](Self::max_concurrent_buffers) or else
+    /// [`max_concurrent_uploads`](Self::max_concurrent_uploads) uploads adding up
+    /// to more than 4 GiB, a compression level above `9`, or
+    /// [`compress`](Self::compress) together with a checksum option. Use
+    /// [`build_unchecked`](Self::build_unchecked) to skip the checks.
+    pub fn build(self) -> Result<StreamShare, StreamShareError> {
+        self.validate()?;
+        Ok(self.build_unchecked())
+    }
+
+    fn validate(&self) -> Result<(), StreamShareError> {
+        let invalid = |message: String| Err(StreamShareError::InvalidConfig(message));
+        if self.chunk_size == 0 {
+            return invalid("chunk_size must not be zero".into());
+        }
+        #[cfg(feature = "compression")]
+        if self.compression_level > 9 {
+            return invalid(format!(
+                "compression_level must be between 0 and 9, got {}",
+                self.compression_level
+            ));
+        }
+        #[cfg(feature = "compression")]
+        if self.compress && (self.send_checksum || self.compute_checksum) {
+            return invalid(COMPRESSED_CHECKSUM.into());
+        }
+
+        let server_urls = [Some(&self.server_url), self.ws_server_url.as_ref()];
+        for server_url in server_urls.into_iter().flatten() {
+            if server_url.trim().is_empty() {
+                return invalid("Server URL is empty".into());
+            }
+            let Some((scheme, _)) = server_url.split_once("://") else {
+                continue;
+            };
+            let expected = match self.scheme {
+                Scheme::Https => ["https", "wss"],
+                Scheme::Http => ["http", "ws"],
+            };
+            if !expected.contains(&scheme.to_ascii_lowercase().as_str()) {
+                return invalid(format!(
+                    "Server URL {} conflicts with scheme {:?}",
+                    server_url, self.scheme
+                ));
+            }
+            return invalid(format!(
+                "Server URL {} must be a host without a scheme; set the scheme with scheme()",
+                server_url
+            ));
+        }
+
+        let uploads = self
+            .max_concurrent_buffers
+            .or(self.max_concurrent_uploads)
+            .unwrap_or(1);
+        let buffers = (self.chunk_size as u64).saturating_mul(uploads as u64);
+        if buffers > CHUNK_BUFFER_BUDGET {
+            return invalid(format!(
+                "{} concurrent chunk buffers of {} bytes exceed the {} byte budget",
+                uploads, self.chunk_size, CHUNK_BUFFER_BUDGET
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creates the client without the checks of [`build`](Self::build). A bad
+    /// configuration then only fails once it is used, e.g. a zero chunk size on
+    /// the first upload.
+    pub fn build_unchecked(self) -> StreamShare {
+        let (max_redirects, download_max_redirects) = (
+            self.max_redirects,
+            self.download_max_redirects.unwrap_or(self.max_redirects),
+        );
+        // reqwest only has a per-client policy, so the limit is picked by
+        // whether the redirect chain started at a download URL.
+        let redirect = Policy::custom(move |attempt| {
+            let is_download = attempt
+                .previous()
+                .first()
+                .is_some_and(|url| url.path().starts_with("/download/"));
+            let limit = if is_download {
+                download_max_redirects
+            } else {
+                max_redirects
+            };
+            if attempt.previous().len() > limit {
+                attempt.error(format!("too many redirects (limit {})", limit))
+            } else {
+                attempt.follow()
+            }
+        });
+
+        let mut client = Client::builder()
+            .redirect(redirect)
+            .local_address(http_local_address(self.local_address, self.ip_preference));
+        if let Some(connect_timeout) = self.connect_timeout {
+            client = client.connect_timeout(connect_timeout);
+        }
+        if let Some(read_timeout) = self.read_timeout {
+            client = client.read_timeout(read_timeout);
+        }
+        if let Some(pool_max_idle_per_host) = self.pool_max_idle_per_host {
+            client = client.pool_max_idle_per_host(pool_max_idle_per_host);
+        }
+        if self.http2_prior_knowledge {
+            client = client.http2_prior_knowledge();
+        }
+        if self.http2_adaptive_window {
+            client = client.http2_adaptive_window(true);
+        }
+        if let Some(min_tls_version) = self.min_tls_version {
+            client = client
+                .use_rustls_tls()
+                .min_tls_version(min_tls_version.into());
+        }
+        let client = client.build().expect("failed to build HTTP client");
+        #[cfg(feature = "middleware")]
+        let transport = match (self.http_transport, &self.middleware) {
+            (Some(transport), _) => transport,
+            (None, Some(middleware)) => Arc::new(MiddlewareTransport::new(middleware.clone())),
+            (None, None) => Arc::new(ReqwestTransport::new(client.clone())),
+        };
+        #[cfg(not(feature = "middleware"))]
+        let transport = self
+            .http_transport
+            .unwrap_or_else(|| Arc::new(ReqwestTransport::new(client.clone())));
+
+        let (server_url, url_credentials) = split_credentials(&self.server_url);
+        let basic_auth = self
+            .basic_auth
+            .or(url_credentials)
+            .map(|(username, password)| basic_header(&username, &password));
+
+        StreamShare {
+            server_url,
+            chunk_size: self.chunk_size,
+            client,
+            buffer_pool: self
+                .max_concurrent_buffers
+                .map(|max| BufferPool::new(max, self.chunk_size)),
+            last_handshake: Mutex::new(None),
+            capabilities: OnceCell::new(),
+            on_created: self.on_created,
+            on_retry: self.on_retry,
+            active_uploads: Mutex::new(HashMap::new()),
+            idempotency_keys: self.idempotency_keys,
+            scheme: self.scheme,
+            auth_token: self.auth_token,
+            basic_auth,
+            completion_code: self.completion_code,
+            completion_reason: self.completion_reason,
+            allow_non_regular: self.allow_non_regular,
+            connect_timeout: self.connect_timeout,
+            ack_timeout: self.ack_timeout,
+            overall_timeout: self.overall_timeout,
+            upload_permits: self.max_concurrent_uploads.map(Semaphore::new),
+            binary_ack: self.binary_ack,
+            follow_symlinks: self.follow_symlinks,
+            download_root: self.download_root,
+            local_address: self.local_address,
+            ip_preference: self.ip_preference,
+            download_precheck: self.download_precheck,
+            expand_tilde: self.expand_tilde,
+            #[cfg(feature = "compression")]
+            compression: self.compress.then_some(self.compression_level),
+            #[cfg(feature = "compression")]
+            auto_decompress: self.auto_decompress,
+            tcp_nodelay: self.tcp_nodelay,
+            tcp_send_buffer_size: self.tcp_send_buffer_size,
+            tcp_recv_buffer_size: self.tcp_recv_buffer_size,
+            max_maintenance_wait: self.max_maintenance_wait,
+            verify_on_disk: self.verify_on_disk,
+            protocol_version: self.protocol_version,
+            send_checksum: self.send_checksum,
+            write_buffer_size: self.write_buffer_size,
+            sync_on_complete: self.sync_on_complete,
+            ws_server_url: self.ws_server_url,
+            ws_base_path: self.ws_base_path,
+            deletion_token_header: self.deletion_token_header,
+            small_file_threshold: self.small_file_threshold,
+            hash_algorithm: self.hash_algorithm,
+            nack_token: self.nack_token,
+            max_chunk_resends: self.max_chunk_resends,
+            max_frame_size: self.max_frame_size,
+            host_limits: self.max_connections_per_host.map(HostLimits::new),
+            state_save_interval: self.state_save_interval,
+            progress_interval: self.progress_interval,
+            admin_key: self.admin_key,
+            strict_version: self.strict_version,
+            on_version_mismatch: self.on_version_mismatch,
+            version_warned: AtomicBool::new(false),
+            origin: self.origin,
+            availability_poll_interval: self.availability_poll_interval,
+            reject_empty: self.reject_empty,
+            compute_checksum: self.compute_checksum,
+            chunk_delay: self.chunk_delay,
+            fallback_extension: self.fallback_extension,
+            max_chunks: self.max_chunks,
+            slow_down_token: self.slow_down_token,
+            transport,
+            keepalive_interval: self.keepalive_interval,
+            #[cfg(feature = "xattr")]
+            set_type_xattr: self.set_type_xattr,
+            #[cfg(feature = "middleware")]
+            middleware: self.middleware,
+            ws_tls_config: ws_tls_config(self.min_tls_version),
+            informational_prefixes: self.informational_prefixes,
+            on_informational: self.on_informational,
+            on_conflict: self.on_conflict,
+            on_complete: self.on_complete,
+            allow_foreign_signed_urls: self.allow_foreign_signed_urls,
+            chunk_framing: self.chunk_framing,
+            text_ack: self.text_ack,
+            adaptive_chunking: self.adaptive_chunking,
+            batch_retry_budget: self.batch_retry_budget,
+        }
+    }
+}
+
+impl Default for StreamShareBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Splits `user:pass@` credentials off the host part of a server URL.
+fn split_credentials(server_url: &str) -> (String, Option<(String, String)>) {
+    let authority_end = server_url.find('/').unwrap_or(server_url.len());
+    let Some(at) = server_url[..authority_end].rfind('@') else {
+        return (server_url.to_string(), None);
+    };
+
+    let (username, password) = server_url[..at]
+        .split_once(':')
+        .unwrap_or((&server_url[..at], ""));
+    let credentials = (percent_decode(username), percent_decode(password));
+    (server_url[at + 1..].to_string(), Some(credentials))
+}
+
+/// Decodes `%XX` escapes, leaving malformed ones as they are.
+pub(crate) fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,179 @@
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{ready, Context, Poll};
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use sha2::{Digest, Sha256, Sha512};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+use crate::StreamShareError;
+
+/// Hash used for [`send_checksum`](crate::StreamShareBuilder::send_checksum)
+/// and [`verify_on_disk`](crate::StreamShareBuilder::verify_on_disk).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    /// Much faster than SHA-2 on large files. Needs the `blake3` feature.
+    #[cfg(feature = "blake3")]
+    Blake3,
+    /// CRC-32 (IEEE), much cheaper still, as 8 hex digits. It catches
+    /// accidental corruption only: matching data is easy to forge, so don't
+    /// rely on it against tampering. Needs the `crc32` feature.
+    #[cfg(feature = "crc32")]
+    Crc32,
+}
+
+impl HashAlgorithm {
+    /// Lowercase name, also used as the field name in create requests.
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => "blake3",
+            #[cfg(feature = "crc32")]
+            HashAlgorithm::Crc32 => "crc32",
+        }
+    }
+}
+
+/// A hex digest together with the algorithm that produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: HashAlgorithm,
+    pub digest: String,
+}
+
+impl Checksum {
+    pub(crate) fn of(algorithm: HashAlgorithm, data: &[u8]) -> Self {
+        let mut hasher = Hasher::new(algorithm);
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    pub(crate) async fn of_file(algorithm: HashAlgorithm, path: &Path) -> std::io::Result<Self> {
+        let mut file = File::open(path).await?;
+        let mut hasher = Hasher::new(algorithm);
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buffer).await?;
+            if n == 0 {
+                return Ok(hasher.finish());
+            }
+            hasher.update(&buffer[..n]);
+        }
+    }
+}
+
+/// Hashes a local file without uploading it and returns the hex digest, the
+/// same one [`send_checksum`](crate::StreamShareBuilder::send_checksum) sends.
+/// Useful to check for duplicates before starting an upload.
+pub async fn hash_file(
+    file_path: &str,
+    algorithm: HashAlgorithm,
+) -> Result<String, StreamShareError> {
+    Ok(Checksum::of_file(algorithm, Path::new(file_path))
+        .await?
+        .digest)
+}
+
+/// Serializes as `{"<algorithm>": "<digest>"}`, to be flattened into a
+/// create request.
+impl Serialize for Checksum {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(self.algorithm.name(), &self.digest)?;
+        map.end()
+    }
+}
+
+/// Incremental hashing with any [`HashAlgorithm`].
+#[derive(Clone)]
+pub(crate) enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3::Hasher>),
+    #[cfg(feature = "crc32")]
+    Crc32(crc32fast::Hasher),
+}
+
+impl Hasher {
+    pub(crate) fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+            #[cfg(feature = "crc32")]
+            HashAlgorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            #[cfg(feature = "crc32")]
+            Hasher::Crc32(hasher) => hasher.update(data),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Checksum {
+        let (algorithm, digest) = match self {
+            Hasher::Sha256(hasher) => (HashAlgorithm::Sha256, hasher.finalize().to_vec()),
+            Hasher::Sha512(hasher) => (HashAlgorithm::Sha512, hasher.finalize().to_vec()),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(hasher) => {
+                (HashAlgorithm::Blake3, hasher.finalize().as_bytes().to_vec())
+            }
+            #[cfg(feature = "crc32")]
+            Hasher::Crc32(hasher) => (
+                HashAlgorithm::Crc32,
+                hasher.finalize().to_be_bytes().to_vec(),
+            ),
+        };
+        Checksum {
+            algorithm,
+            digest: digest.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        }
+    }
+}
+
+/// Bytes read so far and their hash.
+pub(crate) struct HashedPrefix {
+    pub(crate) hasher: Hasher,
+    pub(crate) len: u64,
+}
+
+/// Hashes everything read through it into a shared [`HashedPrefix`], so a
+/// file is hashed in the same pass that uploads it.
+pub(crate) struct HashingReader<'a, R> {
+    pub(crate) inner: R,
+    pub(crate) prefix: &'a Mutex<HashedPrefix>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<'_, R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let read = &buf.filled()[before..];
+        let mut prefix = self.prefix.lock().unwrap();
+        prefix.hasher.update(read);
+        prefix.len += read.len() as u64;
+        Poll::Ready(Ok(()))
+    }
+}
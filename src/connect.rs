@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use bytes::Bytes;
+use http_body::{Frame, SizeHint};
+use reqwest::ResponseBuilderExt;
+use tokio::net::{lookup_host, TcpSocket, TcpStream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::handshake::client::{Request, Response};
+use tokio_tungstenite::{
+    client_async_tls_with_config, tungstenite, Connector, MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{StreamShare, StreamShareError};
+
+/// The upload WebSocket returned by [`StreamShare::open_upload_stream`].
+pub type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Which IP version to use when a host resolves to both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpPreference {
+    /// Try every resolved address in order.
+    #[default]
+    Any,
+    /// Only connect over IPv4.
+    V4,
+    /// Only connect over IPv6.
+    V6,
+}
+
+impl IpPreference {
+    fn allows(self, addr: &SocketAddr) -> bool {
+        match self {
+            IpPreference::Any => true,
+            IpPreference::V4 => addr.is_ipv4(),
+            IpPreference::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+/// The lowest TLS version a connection may use, see
+/// [`min_tls_version`](crate::StreamShareBuilder::min_tls_version).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+impl From<TlsVersion> for reqwest::tls::Version {
+    fn from(version: TlsVersion) -> Self {
+        match version {
+            TlsVersion::Tls12 => reqwest::tls::Version::TLS_1_2,
+            TlsVersion::Tls13 => reqwest::tls::Version::TLS_1_3,
+        }
+    }
+}
+
+impl StreamShare {
+    /// Opens the upload WebSocket, honouring the configured socket options.
+    pub(crate) async fn connect_ws(
+        &self,
+        request: Request,
+    ) -> Result<(WsStream, Response), StreamShareError> {
+        let uri = request.uri();
+        let host = uri
+            .host()
+            .ok_or_else(|| StreamShareError::InvalidConfig(format!("No host in {}", uri)))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = uri
+            .port_u16()
+            .unwrap_or(if uri.scheme_str() == Some("wss") {
+                443
+            } else {
+                80
+            });
+
+        let stream = self.connect_tcp(&host, port).await?;
+        let connector = self.ws_tls_config.clone().map(Connector::Rustls);
+        client_async_tls_with_config(request, stream, None, connector)
+            .await
+            .map_err(|e| match e {
+                tungstenite::Error::Http(response) => StreamShareError::UploadSessionRejected {
+                    status: response.status(),
+                },
+                e => e.into(),
+            })
+    }
+
+    async fn connect_tcp(&self, host: &str, port: u16) -> Result<TcpStream, StreamShareError> {
+        let mut last_error = None;
+
+        for addr in lookup_host((host, port)).await? {
+            if !self.ip_preference.allows(&addr) {
+                continue;
+            }
+            if let Some(local) = self.local_address {
+                if local.is_ipv4() != addr.is_ipv4() {
+                    continue;
+                }
+            }
+
+            match self.connect_addr(addr).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::AddrNotAvailable,
+                    format!("No usable address for {}:{}", host, port),
+                )
+            })
+            .into())
+    }
+
+    async fn connect_addr(&self, addr: SocketAddr) -> std::io::Result<TcpStream> {
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+
+        if let Some(local) = self.local_address {
+            socket.bind(SocketAddr::new(local, 0))?;
+        }
+
+        socket.set_nodelay(self.tcp_nodelay)?;
+        if let Some(size) = self.tcp_send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.tcp_recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        match self.connect_timeout {
+            Some(limit) => timeout(limit, socket.connect(addr))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("Connecting to {} timed out", addr),
+                    ))
+                }),
+            None => socket.connect(addr).await,
+        }
+    }
+}
+
+/// One semaphore per host, for
+/// [`max_connections_per_host`](crate::StreamShareBuilder::max_connections_per_host).
+pub(crate) struct HostLimits {
+    max_per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimits {
+    pub(crate) fn new(max_per_host: usize) -> Self {
+        Self {
+            max_per_host,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) async fn acquire(&self, host: &str) -> OwnedSemaphorePermit {
+        let semaphore = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_ascii_lowercase())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host)))
+            .clone();
+        semaphore
+            .acquire_owned()
+            .await
+            .expect("host semaphore is never closed")
+    }
+}
+
+/// Hands the host `permit` of a request over to the body of its response, so
+/// the connection keeps counting against the limit until the body has been
+/// read in full or dropped, not just until the headers arrived.
+pub(crate) fn hold_permit(
+    res: reqwest::Response,
+    permit: Option<OwnedSemaphorePermit>,
+) -> reqwest::Response {
+    let Some(permit) = permit else {
+        return res;
+    };
+    let url = res.url().clone();
+    let (mut parts, body) = http::Response::<reqwest::Body>::from(res).into_parts();
+    // The conversion drops the URL, which error messages still need.
+    let (tagged, ()) = http::Response::builder()
+        .url(url)
+        .body(())
+        .expect("an empty response is always valid")
+        .into_parts();
+    parts.extensions.extend(tagged.extensions);
+    let body = PermitBody {
+        inner: body,
+        permit: Some(permit),
+    };
+    http::Response::from_parts(parts, reqwest::Body::wrap(body)).into()
+}
+
+/// A response body holding a host permit until it ends.
+struct PermitBody {
+    inner: reqwest::Body,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl http_body::Body for PermitBody {
+    type Data = Bytes;
+    type Error = reqwest::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, reqwest::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        if frame.is_none() {
+            self.permit = None;
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// The TLS setup for `wss://` connections with `min_tls_version`, or `None` for
+/// `tokio-tungstenite`'s default. Uses the same roots as that default.
+pub(crate) fn ws_tls_config(
+    min_tls_version: Option<TlsVersion>,
+) -> Option<Arc<rustls::ClientConfig>> {
+    let versions: &[&rustls::SupportedProtocolVersion] = match min_tls_version? {
+        TlsVersion::Tls12 => rustls::ALL_VERSIONS,
+        TlsVersion::Tls13 => &[&rustls::version::TLS13],
+    };
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = rustls::ClientConfig::builder_with_protocol_versions(versions)
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Some(Arc::new(config))
+}
+
+/// The local address `reqwest` should bind to for the given settings.
+pub(crate) fn http_local_address(
+    local_address: Option<IpAddr>,
+    ip_preference: IpPreference,
+) -> Option<IpAddr> {
+    local_address.or(match ip_preference {
+        IpPreference::Any => None,
+        IpPreference::V4 => Some(IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED)),
+        IpPreference::V6 => Some(IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED)),
+    })
+}
@@ -0,0 +1,310 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use tokio_tungstenite::tungstenite;
+
+/// Everything that can go wrong while talking to a StreamShare server.
+#[derive(Debug, thiserror::Error)]
+pub enum StreamShareError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("HTTP error: {0}")]
+    Http(reqwest::Error),
+
+    /// The server's host name could not be resolved.
+    #[error("DNS lookup failed: {0}")]
+    DnsError(reqwest::Error),
+
+    /// No connection could be established, e.g. because it was refused.
+    #[error("Failed to connect: {0}")]
+    ConnectError(reqwest::Error),
+
+    /// The TLS handshake failed, e.g. because of an invalid certificate.
+    #[error("TLS error: {0}")]
+    TlsError(reqwest::Error),
+
+    /// An HTTP request timed out.
+    #[error("HTTP request timed out: {0}")]
+    HttpTimeout(reqwest::Error),
+
+    /// A response body could not be decoded.
+    #[error("Failed to decode response: {0}")]
+    Decode(reqwest::Error),
+
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+
+    /// Reading the upload source failed after `offset` bytes had been sent.
+    #[error("Failed to read upload data at offset {offset}: {source}")]
+    ReadError {
+        offset: u64,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The file to upload can't be read, e.g. because read access was
+    /// revoked during the upload.
+    #[error("Permission denied reading {path}")]
+    PermissionDenied { path: PathBuf },
+
+    #[error("Selected item is not a file: {path}")]
+    NotAFile { path: String },
+
+    /// The file to upload is empty and
+    /// [`reject_empty`](crate::StreamShareBuilder::reject_empty) is set.
+    #[error("Refusing to upload empty file: {}", path.display())]
+    EmptyFile { path: PathBuf },
+
+    /// The upload would take more chunks than
+    /// [`max_chunks`](crate::StreamShareBuilder::max_chunks) allows.
+    #[error(
+        "Upload would take {count} chunks, more than the limit of {limit}; use a larger chunk size"
+    )]
+    TooManyChunks { count: u64, limit: u64 },
+
+    #[error("Failed to create upload: {status}")]
+    CreateFailed { status: StatusCode },
+
+    #[error("Upload session rejected by server: {status}")]
+    UploadSessionRejected { status: StatusCode },
+
+    #[error("Failed to update file: {status}")]
+    UpdateFailed { status: StatusCode },
+
+    #[error("Failed to delete file: {status}")]
+    DeleteFailed { status: StatusCode },
+
+    #[error("Failed to verify deletion token: {status}")]
+    TokenCheckFailed { status: StatusCode },
+
+    #[error("Failed to fetch file info: {status}")]
+    InfoFailed { status: StatusCode },
+
+    #[error("Failed to check whether the file exists: {status}")]
+    ExistenceCheckFailed { status: StatusCode },
+
+    /// The server answered `503 Service Unavailable` for longer than
+    /// `max_maintenance_wait` allows.
+    #[error("Server unavailable (retry after {retry_after:?})")]
+    ServerUnavailable { retry_after: Option<Duration> },
+
+    /// A batch operation used up its
+    /// [`batch_retry_budget`](crate::StreamShareBuilder::batch_retry_budget).
+    #[error("Retry budget of the batch exhausted")]
+    RetryBudgetExhausted,
+
+    /// The server reported a version this crate doesn't support and
+    /// [`strict_version`](crate::StreamShareBuilder::strict_version) is set.
+    #[error("Incompatible server version: {version}")]
+    IncompatibleServer { version: String },
+
+    #[error("Unexpected message: {0}")]
+    UnexpectedMessage(String),
+
+    /// An `ACK:<bytes>` reported another total than the client sent, see
+    /// [`TextAck::Offset`](crate::TextAck::Offset).
+    #[error("Server acknowledged {reported} bytes, expected {expected}")]
+    AckDesync { expected: u64, reported: u64 },
+
+    #[error("WebSocket closed unexpectedly")]
+    ConnectionClosed,
+
+    #[error("Upload cancelled")]
+    Cancelled,
+
+    /// The server answered a chunk with a text other than `ACK` or the
+    /// configured NACK token, or kept rejecting it.
+    #[error("Chunk rejected by server: {reason}")]
+    ChunkRejected { reason: String },
+
+    /// The upload exceeded its overall timeout.
+    #[error(
+        "Upload timed out after {elapsed:?} with {uploaded_bytes} bytes sent ({average_bps} B/s)"
+    )]
+    Timeout {
+        uploaded_bytes: u64,
+        elapsed: Duration,
+        average_bps: u64,
+    },
+
+    /// The server didn't acknowledge a chunk within the ack timeout.
+    #[error("No chunk acknowledgement after {elapsed:?} with {uploaded_bytes} bytes sent ({average_bps} B/s)")]
+    AckTimeout {
+        uploaded_bytes: u64,
+        elapsed: Duration,
+        average_bps: u64,
+    },
+
+    #[error("File {file_identifier} did not become available within {timeout:?}")]
+    NotAvailable {
+        file_identifier: String,
+        timeout: Duration,
+    },
+
+    /// [`resume_from_state`](crate::StreamShare::resume_from_state) needs a
+    /// server that reports `resume` in its capabilities.
+    #[error("Server does not support resuming uploads")]
+    ResumeUnsupported,
+
+    /// The server has no `/api/usage` endpoint.
+    #[error("Server does not report usage")]
+    UsageUnsupported,
+
+    /// The file named in an [`UploadState`](crate::UploadState) changed
+    /// since the state was saved, so its upload can't be continued.
+    #[error("File changed since its upload state was saved: {}", path.display())]
+    ResumeStateMismatch { path: PathBuf },
+
+    /// The SHA-256 the server reported for an upload differs from the local
+    /// file's, see [`Transfer`](crate::Transfer).
+    #[error("Server stored SHA-256 {actual}, but the local file has {expected}")]
+    ChecksumMismatch { expected: String, actual: String },
+
+    /// A file identifier or deletion token that can't be a URL path segment,
+    /// like `..`.
+    #[error("Invalid identifier for a URL path: {segment:?}")]
+    InvalidSegment { segment: String },
+
+    #[error("Invalid download path: {0}")]
+    InvalidPath(String),
+
+    /// A signed URL points at another host than the configured server, see
+    /// [`allow_foreign_signed_urls`](crate::StreamShareBuilder::allow_foreign_signed_urls).
+    #[error("Signed URL is not on the configured server: {url}")]
+    ForeignHost { url: String },
+
+    /// The download destination resolves outside of the allowed directory.
+    #[error("Download path {} escapes {}", path.display(), root.display())]
+    PathEscapesRoot { path: PathBuf, root: PathBuf },
+
+    /// The server's filename isn't the one the download expected, see
+    /// [`download_expecting`](crate::StreamShare::download_expecting).
+    #[error(
+        "Expected file {expected}, server sent {}",
+        actual.as_deref().unwrap_or("no name")
+    )]
+    UnexpectedFilename {
+        expected: String,
+        actual: Option<String>,
+    },
+
+    #[error("File already exists: {}", path.display())]
+    FileExists { path: PathBuf },
+
+    /// [`on_conflict`](crate::StreamShareBuilder::on_conflict) chose to keep
+    /// the existing file, so nothing was downloaded.
+    #[error("Skipped existing file: {}", path.display())]
+    Skipped { path: PathBuf },
+
+    #[error("Destination is not writable: {path}")]
+    DestinationNotWritable { path: PathBuf },
+
+    /// The destination ran out of space, or doesn't have room for the
+    /// download's `Content-Length`. No partial file is left behind.
+    #[error("Not enough disk space for {}", path.display())]
+    DiskFull { path: PathBuf },
+
+    /// The file read back from disk doesn't match the downloaded data.
+    #[error("Written file does not match the downloaded data: {path}")]
+    DiskVerificationFailed { path: PathBuf },
+
+    /// [`auto_decompress`](crate::StreamShareBuilder::auto_decompress) was
+    /// enabled, but a `.gz` file doesn't start with the gzip magic bytes.
+    #[cfg(feature = "compression")]
+    #[error("Downloaded file is not gzip-compressed: {path}")]
+    NotGzip { path: PathBuf },
+
+    #[error("Server ignored range request: {status}")]
+    RangeNotSupported { status: StatusCode },
+
+    /// A range request was answered with a different range, which would put
+    /// bytes at the wrong offset.
+    #[error("Server answered range {expected} with {actual}")]
+    RangeMismatch { expected: String, actual: String },
+
+    #[error("Download size mismatch: expected {expected} bytes, received {actual}")]
+    SizeMismatch { expected: u64, actual: u64 },
+
+    #[cfg(feature = "archive")]
+    #[error("Failed to write archive: {0}")]
+    Archive(#[from] async_zip::error::ZipError),
+
+    /// A layer of the [`middleware`](crate::StreamShareBuilder::middleware)
+    /// stack failed the request.
+    #[cfg(feature = "middleware")]
+    #[error("Middleware error: {0}")]
+    Middleware(reqwest_middleware::Error),
+
+    /// The progress callback panicked during an upload. The WebSocket was
+    /// closed with an error code before this was returned.
+    #[error("Progress callback panicked")]
+    CallbackPanicked,
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+}
+
+impl From<reqwest::Error> for StreamShareError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            StreamShareError::HttpTimeout(error)
+        } else if error.is_decode() {
+            StreamShareError::Decode(error)
+        } else if error.is_connect() {
+            if caused_by_tls(&error) {
+                StreamShareError::TlsError(error)
+            } else if caused_by_dns(&error) {
+                StreamShareError::DnsError(error)
+            } else {
+                StreamShareError::ConnectError(error)
+            }
+        } else {
+            StreamShareError::Http(error)
+        }
+    }
+}
+
+/// Errors in the chain of `error`, looking inside `io::Error`s whose
+/// `source` skips the error they wrap.
+fn causes(error: &reqwest::Error) -> impl Iterator<Item = &(dyn Error + 'static)> {
+    let mut next = error.source();
+    std::iter::from_fn(move || {
+        let current = next?;
+        next = match current.downcast_ref::<std::io::Error>() {
+            Some(io) => io.get_ref().map(|inner| inner as &(dyn Error + 'static)),
+            None => current.source(),
+        };
+        Some(current)
+    })
+}
+
+fn caused_by_tls(error: &reqwest::Error) -> bool {
+    // reqwest's default backend is native-tls; rustls is only picked for a
+    // `min_tls_version`.
+    causes(error).any(|cause| cause.is::<native_tls::Error>() || cause.is::<rustls::Error>())
+}
+
+fn caused_by_dns(error: &reqwest::Error) -> bool {
+    // hyper reports failed lookups as a connect error with this message.
+    causes(error).any(|cause| cause.to_string() == "dns error")
+}
+
+/// Errors of `reqwest` itself are classified like those of a plain client.
+#[cfg(feature = "middleware")]
+impl From<reqwest_middleware::Error> for StreamShareError {
+    fn from(err: reqwest_middleware::Error) -> Self {
+        match err {
+            reqwest_middleware::Error::Reqwest(err) => err.into(),
+            err => StreamShareError::Middleware(err),
+        }
+    }
+}
+
+impl From<tungstenite::Error> for StreamShareError {
+    fn from(error: tungstenite::Error) -> Self {
+        StreamShareError::WebSocket(Box::new(error))
+    }
+}
@@ -0,0 +1,160 @@
+//! Uploads of files that are still being written, like `tail -f`.
+
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::stream;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+use tokio_util::io::StreamReader;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    read_failed, Meter, StreamShare, StreamShareError, TransferKind, UploadOptions, UploadResult,
+    UploadSource,
+};
+
+/// How often a file at its end is checked for new data.
+const GROWTH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Follows a file by path, like `tail -F`.
+struct Tail {
+    path: PathBuf,
+    file: File,
+    position: u64,
+    stop: CancellationToken,
+    buffer: Vec<u8>,
+}
+
+impl Tail {
+    /// The next bytes written to the file, or `None` once `stop` fired and
+    /// everything written before it was read.
+    async fn next(&mut self) -> io::Result<Option<Bytes>> {
+        loop {
+            let n = self.file.read(&mut self.buffer).await?;
+            if n > 0 {
+                self.position += n as u64;
+                return Ok(Some(Bytes::copy_from_slice(&self.buffer[..n])));
+            }
+            if self.stop.is_cancelled() {
+                return Ok(None);
+            }
+            if self.follow().await? {
+                continue;
+            }
+            tokio::select! {
+                _ = self.stop.cancelled() => {}
+                _ = tokio::time::sleep(GROWTH_POLL_INTERVAL) => {}
+            }
+        }
+    }
+
+    /// Starts over if the file was truncated, or reopens `path` if another
+    /// file took its place. Returns whether there may be new data to read.
+    async fn follow(&mut self) -> io::Result<bool> {
+        let current = match fs::metadata(&self.path).await {
+            Ok(metadata) => metadata,
+            // Rotated away and not recreated yet; keep the old file.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if !same_file(&current, &self.file.metadata().await?) {
+            self.file = File::open(&self.path).await?;
+            self.position = 0;
+            return Ok(true);
+        }
+        if current.len() < self.position {
+            self.file.seek(SeekFrom::Start(0)).await?;
+            self.position = 0;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+/// Rotation can't be told apart from growth here; only truncation is noticed.
+#[cfg(not(unix))]
+fn same_file(_: &Metadata, _: &Metadata) -> bool {
+    true
+}
+
+impl StreamShare {
+    /// Uploads a file that is still being written, e.g. a growing log.
+    ///
+    /// Reads to the end of the file, then waits for more data and sends new
+    /// bytes as they appear, all over one WebSocket. Once `stop` is cancelled
+    /// the rest of the file is sent and the upload is closed normally.
+    ///
+    /// A truncated file is read again from the start, and a file replaced by
+    /// rotation is reopened by path; either way the upload keeps growing. On
+    /// non-Unix systems only truncation is noticed.
+    ///
+    /// The size isn't known up front, so progress totals are `0` and the
+    /// server must accept an upload without a declared size that stays open
+    /// for as long as the file grows. An
+    /// [`overall_timeout`](crate::StreamShareBuilder::overall_timeout) also
+    /// ends the upload, usually too early for this.
+    pub async fn upload_growing<F>(
+        &self,
+        file_path: &str,
+        stop: CancellationToken,
+        mut callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let mut meter = Meter::start(self, TransferKind::Upload);
+        let path = Path::new(file_path);
+        let metadata = fs::metadata(path).await?;
+        if !metadata.is_file() {
+            return Err(StreamShareError::NotAFile {
+                path: file_path.to_string(),
+            });
+        }
+        let file_name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("unknown");
+
+        let _permit = self.acquire_upload_permit().await;
+        let file = File::open(path)
+            .await
+            .map_err(|e| read_failed(e.into(), path))?;
+        let options = UploadOptions::default();
+        let result = self.create(file_name, None, None, &options).await?;
+
+        let tail = Tail {
+            path: path.to_path_buf(),
+            file,
+            position: 0,
+            stop,
+            buffer: vec![0u8; self.chunk_size],
+        };
+        let reader = StreamReader::new(Box::pin(stream::try_unfold(tail, |mut tail| async move {
+            Ok::<_, io::Error>(tail.next().await?.map(|bytes| (bytes, tail)))
+        })));
+
+        let sent = self
+            .send_chunks(
+                &result.file_identifier,
+                UploadSource::Live(reader),
+                None,
+                0,
+                &mut meter,
+                &options,
+                |_, uploaded, total| callback(uploaded, total),
+            )
+            .await?;
+        meter.success = true;
+        Ok(result.with_sent(sent))
+    }
+}
@@ -1,229 +1,4478 @@
-use futures::{SinkExt, StreamExt};
-use reqwest::Client;
-use serde::Deserialize;
+mod adaptive;
+#[cfg(feature = "archive")]
+mod archive;
+mod builder;
+mod checksum;
+mod connect;
+mod error;
+mod growing;
+mod pool;
+pub mod prelude;
+#[cfg(feature = "indicatif")]
+mod progress;
+mod progress_reader;
+mod resume;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod transfer;
+mod transform;
+mod transport;
+mod urls;
+
+#[cfg(feature = "archive")]
+pub use archive::ArchiveFormat;
+pub use builder::{StreamShareBuilder, TimeoutProfile};
+pub use checksum::{hash_file, Checksum, HashAlgorithm};
+pub use connect::{IpPreference, TlsVersion, WsStream};
+pub use error::StreamShareError;
+pub use progress_reader::ProgressReader;
+pub use resume::UploadState;
+pub use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+pub use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+pub use tokio_tungstenite::tungstenite::Message;
+pub use tokio_util::sync::CancellationToken;
+pub use transfer::Transfer;
+#[cfg(feature = "encryption")]
+pub use transform::EncryptTransform;
+#[cfg(feature = "compression")]
+pub use transform::GzipTransform;
+pub use transform::{ChunkTransform, HashTransform};
+#[cfg(feature = "middleware")]
+pub use transport::MiddlewareTransport;
+pub use transport::{HttpRequest, HttpResponse, HttpTransport, ReqwestTransport};
+pub use urls::Scheme;
+
+use adaptive::ChunkSizer;
+#[cfg(feature = "compression")]
+use async_compression::tokio::bufread::{GzipDecoder, GzipEncoder};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use bytes::Bytes;
+use checksum::{HashedPrefix, Hasher, HashingReader};
+use connect::HostLimits;
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use pool::{BufferPool, ChunkBuffer};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, ORIGIN};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::net::IpAddr;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::fs::File;
-use tokio::io::AsyncReadExt;
-use tokio::{fs, io::AsyncWriteExt};
-use tokio_tungstenite::{
-    connect_async,
-    tungstenite::{self, Message},
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, BufWriter};
+use tokio::sync::{
+    mpsc, watch, Notify, OnceCell, OwnedSemaphorePermit, Semaphore, SemaphorePermit,
 };
+use tokio::{fs, io::AsyncWriteExt};
+use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+use tokio_util::either::Either;
+use tokio_util::io::StreamReader;
+use urls::Endpoint;
+
+const SERVER_VERSION_HEADER: &str = "x-streamshare-version";
+/// Major versions of the server this crate is known to work with.
+const SUPPORTED_SERVER_MAJORS: std::ops::RangeInclusive<u64> = 0..=1;
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const MAX_FILE_NAME_LEN: usize = 255;
+const ABORT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Pause for a `slow_down_token` message that doesn't give one.
+const DEFAULT_SLOW_DOWN_PAUSE: Duration = Duration::from_secs(1);
+/// How long to wait for more frames after the close frame was sent.
+const TRAILING_FRAME_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize)]
+struct CreateRequest<'a> {
+    name: &'a str,
+    /// Lets the server preallocate or reject oversized uploads up front.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    checksum: Option<&'a Checksum>,
+    #[serde(flatten)]
+    options: &'a UploadOptions,
+}
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct CreateResponse {
     file_identifier: String,
-    deletion_token: String,
+    #[serde(default)]
+    deletion_token: Option<String>,
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    file_name: Option<String>,
+    #[serde(default, deserialize_with = "expires_at")]
+    expires_at: Option<String>,
 }
 
-pub struct StreamShare {
-    server_url: String,
-    chunk_size: usize,
-    client: Client,
+#[derive(Serialize)]
+struct CreateRequestV2<'a> {
+    version: u8,
+    file: CreateFileV2<'a>,
+    metadata: &'a UploadOptions,
 }
 
-impl StreamShare {
-    pub fn new(server_url: String, chunk_size: usize) -> Self {
+#[derive(Serialize)]
+struct CreateFileV2<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    checksum: Option<&'a Checksum>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateResponseV2 {
+    id: String,
+    #[serde(default)]
+    deletion_token: Option<String>,
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default, deserialize_with = "expires_at")]
+    expires_at: Option<String>,
+}
+
+/// How a download names its local file.
+#[derive(Clone, Copy)]
+enum LocalName<'a> {
+    /// The server's filename.
+    Server,
+    /// This name, whatever the server sends.
+    Rename(&'a str),
+    /// The server's filename, which has to be this one.
+    Expect(&'a str),
+}
+
+/// Accepts a timestamp string as well as a number, e.g. Unix seconds.
+fn expires_at<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Ok(
+        match Option::<serde_json::Value>::deserialize(deserializer)? {
+            Some(serde_json::Value::String(text)) => Some(text),
+            Some(serde_json::Value::Number(number)) => Some(number.to_string()),
+            _ => None,
+        },
+    )
+}
+
+/// Whole seconds, rounded up so a short expiry never becomes `0`.
+fn serialize_secs<S: serde::Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => serializer
+            .serialize_some(&(duration.as_secs() + u64::from(duration.subsec_nanos() > 0))),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Shape of the `/api/create` exchange, so one client can talk to both the
+/// current server and a later revision.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProtocolVersion {
+    /// A flat `{ name, size, ...metadata }` body, answered with
+    /// `{ fileIdentifier, deletionToken }`.
+    #[default]
+    V1,
+    /// `{ version: 2, file: { name, size }, metadata: { ... } }`, answered
+    /// with `{ id, deletionToken }`.
+    V2,
+}
+
+/// Optional metadata sent along with the `/api/create` request.
+///
+/// Fields left as `None` are omitted from the request body, so servers that
+/// don't know about them are unaffected.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UploadOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// MIME type of the file, e.g. from [`guess_content_type`].
+    #[serde(skip_serializing_if = "Option::is_none", rename = "contentType")]
+    pub content_type: Option<String>,
+    /// Asks the server to delete the file after this long, sent in the
+    /// create request as `expiresIn` in seconds. Servers without expiry
+    /// ignore it, so check [`UploadResult::expires_at`] to know it applies.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        rename = "expiresIn",
+        serialize_with = "serialize_secs"
+    )]
+    pub expires_in: Option<Duration>,
+    /// Sent as an `Idempotency-Key` header so a server that supports it can
+    /// deduplicate a retried upload. Servers that ignore the header are unaffected.
+    #[serde(skip)]
+    pub idempotency_key: Option<String>,
+    /// Stops the upload when set to `true`, e.g. from a Ctrl-C handler. The
+    /// flag is watched while the upload is created and connected, and checked
+    /// before each chunk; the WebSocket is then closed and
+    /// [`StreamShareError::Cancelled`] returned.
+    #[serde(skip)]
+    pub abort: Option<Arc<AtomicBool>>,
+    /// Lets the upload be paused and resumed on the same connection.
+    #[serde(skip)]
+    pub handle: Option<UploadHandle>,
+}
+
+/// Pauses and resumes an upload it was passed to via
+/// [`UploadOptions::handle`].
+///
+/// While paused, no further chunks are read or sent, but the WebSocket stays
+/// open, so the upload continues where it left off once resumed. Clones
+/// control the same upload.
+#[derive(Debug, Clone)]
+pub struct UploadHandle {
+    paused: Arc<watch::Sender<bool>>,
+}
+
+impl UploadHandle {
+    pub fn new() -> Self {
         Self {
-            server_url: server_url,
-            chunk_size: chunk_size,
-            client: Client::new(),
+            paused: Arc::new(watch::Sender::new(false)),
         }
     }
 
-    pub async fn upload<F>(
-        &self,
-        file_path: &str,
-        mut callback: F,
-    ) -> Result<(String, String), Box<dyn std::error::Error>>
-    where
-        F: FnMut(u64, u64),
-    {
-        let path = Path::new(file_path);
-        let metadata = fs::metadata(path).await?;
-        if !metadata.is_file() {
-            return Err(format!("Selected item is not a file: {}", file_path).into());
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    async fn wait_until_resumed(&self) {
+        let mut paused = self.paused.subscribe();
+        // The sender lives in `self`, so the channel can't close while waiting.
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+}
+
+impl Default for UploadHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A file created by an upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadResult {
+    pub file_identifier: String,
+    /// Needed to [`delete`](StreamShare::delete) the file again. Empty if the
+    /// server sent none, neither in the create response nor in a
+    /// [`deletion_token_header`](crate::StreamShareBuilder::deletion_token_header).
+    pub deletion_token: String,
+    /// The checksum sent to the server, if
+    /// [`send_checksum`](crate::StreamShareBuilder::send_checksum) is enabled.
+    pub checksum: Option<Checksum>,
+    /// What the server reported once the last chunk was acknowledged, if it
+    /// sent a summary before closing.
+    pub summary: Option<UploadSummary>,
+    /// Hex SHA-256 of the uploaded file.
+    ///
+    /// Taken from the server when it reports one, in the completion
+    /// [`summary`](Self::summary) or else the create response, so no local
+    /// hashing is needed. Otherwise it is the client's own hash from
+    /// [`send_checksum`](crate::StreamShareBuilder::send_checksum) or
+    /// [`compute_checksum`](crate::StreamShareBuilder::compute_checksum) with
+    /// [`HashAlgorithm::Sha256`], which is then also in
+    /// [`checksum`](Self::checksum). `None` if neither side hashed it.
+    pub sha256: Option<String>,
+    /// The name the file is stored and downloaded under. Servers that strip
+    /// characters or deduplicate names report theirs in the create response;
+    /// otherwise it is the name the client sent, e.g. the local basename.
+    pub stored_file_name: Option<String>,
+    /// When the server will delete the file, as it reported it in the create
+    /// response, e.g. an RFC 3339 timestamp; a number is kept as its decimal
+    /// text. `None` if the server sent none, e.g. because it
+    /// ignored [`UploadOptions::expires_in`].
+    pub expires_at: Option<String>,
+    /// How long the server took to acknowledge each chunk. `None` if no chunk
+    /// was sent. Needs the `metrics` feature.
+    #[cfg(feature = "metrics")]
+    pub stats: Option<LatencyStats>,
+}
+
+impl UploadResult {
+    /// Fills in a deletion token that only arrived with the upload WebSocket.
+    fn with_fallback_token(mut self, token: Option<String>) -> Self {
+        if self.deletion_token.is_empty() {
+            self.deletion_token = token.unwrap_or_default();
         }
+        self
+    }
 
-        let file_size = metadata.len();
-        let file_name = path
-            .file_name()
-            .and_then(|s| s.to_str())
-            .unwrap_or("unknown");
+    /// Fills in what only arrived with the upload WebSocket.
+    fn with_sent(mut self, sent: ChunksSent) -> Self {
+        let server_sha256 = sent
+            .summary
+            .as_ref()
+            .and_then(|summary| summary.checksum.as_ref())
+            .filter(|checksum| checksum.algorithm == HashAlgorithm::Sha256);
+        if let Some(checksum) = server_sha256 {
+            self.sha256 = Some(checksum.digest.clone());
+        }
+        self.summary = sent.summary;
+        #[cfg(feature = "metrics")]
+        {
+            self.stats = LatencyStats::from_samples(sent.latencies);
+        }
+        self.with_fallback_token(sent.header_token)
+    }
 
-        let create_url = format!("https://{}/api/create", self.server_url);
+    /// Records a hash the client computed, unless the server reported one.
+    fn with_client_checksum(mut self, checksum: Checksum) -> Self {
+        if self.sha256.is_none() && checksum.algorithm == HashAlgorithm::Sha256 {
+            self.sha256 = Some(checksum.digest.clone());
+        }
+        self.checksum = Some(checksum);
+        self
+    }
+}
 
-        let res = self
-            .client
-            .post(&create_url)
-            .json(&serde_json::json!({ "name": file_name }))
-            .send()
-            .await?;
+/// A completion summary sent by the server after the final ACK, e.g.
+/// `{"size": 1048576, "sha256": "9f86d081..."}`.
+///
+/// Fields the server left out or that don't parse are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UploadSummary {
+    /// Bytes the server stored.
+    pub size: Option<u64>,
+    /// The hash the server computed, under the first known algorithm name.
+    pub checksum: Option<Checksum>,
+}
 
-        if !res.status().is_success() {
-            return Err(format!("Failed to create upload: {}", res.status()).into());
+impl UploadSummary {
+    /// Parses a trailing text frame, or returns `None` if it isn't a JSON
+    /// object with any known field.
+    fn parse(text: &str) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(text).ok()?;
+        let object = value.as_object()?;
+        let algorithms = [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3,
+            #[cfg(feature = "crc32")]
+            HashAlgorithm::Crc32,
+        ];
+        let summary = UploadSummary {
+            size: object.get("size").and_then(|size| size.as_u64()),
+            checksum: algorithms.into_iter().find_map(|algorithm| {
+                let digest = object.get(algorithm.name())?.as_str()?;
+                Some(Checksum {
+                    algorithm,
+                    digest: digest.to_ascii_lowercase(),
+                })
+            }),
+        };
+        (summary != UploadSummary::default()).then_some(summary)
+    }
+}
+
+/// Send-to-ACK latencies of the chunks of an upload, in
+/// [`UploadResult::stats`]. A resent chunk counts from its first send.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Number of acknowledged chunks.
+    pub samples: u64,
+    pub min: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+#[cfg(feature = "metrics")]
+impl LatencyStats {
+    /// Nearest-rank percentiles over `samples`, or `None` if there are none.
+    fn from_samples(mut samples: Vec<Duration>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
         }
+        samples.sort_unstable();
+        let percentile = |p: usize| samples[(samples.len() * p).div_ceil(100) - 1];
+        Some(Self {
+            samples: samples.len() as u64,
+            min: samples[0],
+            p50: percentile(50),
+            p99: percentile(99),
+            max: samples[samples.len() - 1],
+        })
+    }
+}
 
-        let create_response: CreateResponse = res.json().await?;
-        let ws_url = format!(
-            "wss://{}/api/upload/{}",
-            self.server_url, create_response.file_identifier
-        );
-        let (mut ws_stream, _) = connect_async(ws_url).await?;
+/// Latest upload progress, as published by [`StreamShare::upload_watched`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub uploaded: u64,
+    /// `0` if the size isn't known.
+    pub total: u64,
+}
 
-        let mut file = File::open(path).await?;
-        let mut buffer = vec![0u8; self.chunk_size];
-        let mut uploaded: u64 = 0;
+/// Whether [`TransferMetrics`] describe an upload or a download.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferKind {
+    Upload,
+    Download,
+}
 
-        loop {
-            let n = file.read(&mut buffer).await?;
-            if n == 0 {
-                break;
-            }
+/// Numbers of a finished transfer, as passed to
+/// [`on_complete`](StreamShareBuilder::on_complete).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferMetrics {
+    pub kind: TransferKind,
+    /// Whether the transfer ended without an error.
+    pub success: bool,
+    /// Bytes the server acknowledged, or wrote to disk for a download. A
+    /// resumed upload only counts what was sent after resuming.
+    pub bytes: u64,
+    /// From the start of the call, including any wait for an upload slot.
+    pub duration: Duration,
+    /// `bytes` per second over `duration`.
+    pub average_throughput: u64,
+    /// Chunks sent again after the server rejected them with the
+    /// [`nack_token`](StreamShareBuilder::nack_token). Always `0` for
+    /// downloads.
+    pub retries: u32,
+    /// WebSocket frames sent, not counting resends, or pieces of the
+    /// response body written for a download.
+    pub chunks: u64,
+}
 
-            let chunk = &buffer[..n];
-            ws_stream.send(Message::Binary(chunk.to_vec())).await?;
-            uploaded += n as u64;
-            callback(uploaded, file_size);
+/// Tallies a transfer and reports it to
+/// [`on_complete`](StreamShareBuilder::on_complete) when dropped, so every
+/// early return and a cancelled future count as a failure.
+struct Meter<'a> {
+    client: &'a StreamShare,
+    kind: TransferKind,
+    started: Instant,
+    success: bool,
+    bytes: u64,
+    retries: u32,
+    chunks: u64,
+}
 
-            match ws_stream.next().await {
-                Some(Ok(Message::Text(text))) if text == "ACK" => (),
-                Some(Ok(msg)) => {
-                    return Err(format!("Unexpected message: {:?}", msg).into());
-                }
-                Some(Err(e)) => return Err(format!("WebSocket error: {}", e).into()),
-                None => return Err("WebSocket closed unexpectedly".into()),
+impl<'a> Meter<'a> {
+    fn start(client: &'a StreamShare, kind: TransferKind) -> Self {
+        Self {
+            client,
+            kind,
+            started: Instant::now(),
+            success: false,
+            bytes: 0,
+            retries: 0,
+            chunks: 0,
+        }
+    }
+}
+
+impl Drop for Meter<'_> {
+    fn drop(&mut self) {
+        let Some(on_complete) = &self.client.on_complete else {
+            return;
+        };
+        let duration = self.started.elapsed();
+        let average_throughput = if duration.is_zero() {
+            0
+        } else {
+            (self.bytes as f64 / duration.as_secs_f64()) as u64
+        };
+        on_complete(&TransferMetrics {
+            kind: self.kind,
+            success: self.success,
+            bytes: self.bytes,
+            duration,
+            average_throughput,
+            retries: self.retries,
+            chunks: self.chunks,
+        });
+    }
+}
+
+/// The links of an upload, as built by [`StreamShare::share_links`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareLinks {
+    /// See [`StreamShare::download_url`].
+    pub download: String,
+    /// See [`StreamShare::deletion_url`].
+    pub delete: String,
+}
+
+/// Details about a file stored on the server, as reported by [`StreamShare::info`].
+#[derive(Debug, Clone)]
+pub struct FileInfo {
+    pub file_name: Option<String>,
+    pub size: Option<u64>,
+    pub accepts_ranges: bool,
+}
+
+/// How binary frames from the server are treated while waiting for a chunk
+/// acknowledgement. The text frame `ACK` is always accepted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BinaryAck {
+    /// Binary frames are unexpected and fail the upload.
+    #[default]
+    Reject,
+    /// Any binary frame counts as an acknowledgement.
+    Any,
+    /// An 8 byte big-endian count of the bytes received so far.
+    OffsetBe,
+    /// An 8 byte little-endian count of the bytes received so far.
+    OffsetLe,
+}
+
+impl BinaryAck {
+    /// Parses a binary ACK, returning the acknowledged offset if the format carries one.
+    ///
+    /// Returns `None` if the frame doesn't match the format.
+    fn offset(self, data: &[u8]) -> Option<Option<u64>> {
+        match self {
+            BinaryAck::Reject => None,
+            BinaryAck::Any => Some(None),
+            BinaryAck::OffsetBe => Some(Some(u64::from_be_bytes(data.try_into().ok()?))),
+            BinaryAck::OffsetLe => Some(Some(u64::from_le_bytes(data.try_into().ok()?))),
+        }
+    }
+}
+
+/// How text frames from the server acknowledge a chunk, see
+/// [`text_ack`](StreamShareBuilder::text_ack).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextAck {
+    /// `ACK`, confirming everything sent so far.
+    #[default]
+    Plain,
+    /// `ACK:<bytes>` with the total bytes the server holds, e.g.
+    /// `ACK:1048576`. It has to match the bytes sent, otherwise the upload
+    /// fails with [`StreamShareError::AckDesync`]. A plain `ACK` is still
+    /// accepted.
+    Offset,
+}
+
+/// Byte order of a [`ChunkFraming::LengthPrefixed`] header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+/// How the data of an upload is put into binary WebSocket messages, see
+/// [`chunk_framing`](StreamShareBuilder::chunk_framing).
+///
+/// Headers count towards
+/// [`max_frame_size`](StreamShareBuilder::max_frame_size), so a message
+/// carries that many bytes less data. Progress and acknowledged offsets
+/// count data bytes only. A resent message is sent unchanged, header
+/// included.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkFraming {
+    /// The data as is, the format of the current server.
+    #[default]
+    Raw,
+    /// A 4 byte unsigned length of the data that follows, in `endian` byte
+    /// order.
+    LengthPrefixed { endian: Endian },
+    /// An 8 byte big-endian index of the message, starting at `0` for the
+    /// first message of the connection, then the data.
+    IndexPrefixed,
+}
+
+impl ChunkFraming {
+    fn header_len(self) -> usize {
+        match self {
+            ChunkFraming::Raw => 0,
+            ChunkFraming::LengthPrefixed { .. } => 4,
+            ChunkFraming::IndexPrefixed => 8,
+        }
+    }
+
+    /// The message for `data`, the `index`th of the connection.
+    fn frame(self, index: u64, data: Bytes) -> Bytes {
+        if self == ChunkFraming::Raw {
+            return data;
+        }
+        let mut message = Vec::with_capacity(self.header_len() + data.len());
+        match self {
+            ChunkFraming::Raw => {}
+            ChunkFraming::LengthPrefixed { endian } => {
+                let len = data.len() as u32;
+                message.extend_from_slice(&match endian {
+                    Endian::Big => len.to_be_bytes(),
+                    Endian::Little => len.to_le_bytes(),
+                });
             }
+            ChunkFraming::IndexPrefixed => message.extend_from_slice(&index.to_be_bytes()),
         }
+        message.extend_from_slice(&data);
+        message.into()
+    }
+}
 
-        ws_stream
-            .close(Some(tungstenite::protocol::CloseFrame {
-                code: tungstenite::protocol::frame::coding::CloseCode::Normal,
-                reason: "FILE_UPLOAD_DONE".into(),
-            }))
-            .await?;
+/// Details of the most recent WebSocket upgrade, kept for diagnostics.
+#[derive(Debug, Clone)]
+pub struct HandshakeInfo {
+    pub status: u16,
+    pub protocol: Option<String>,
+    pub server_version: Option<String>,
+}
 
-        Ok((
-            create_response.file_identifier,
-            create_response.deletion_token,
-        ))
+impl HandshakeInfo {
+    fn from_response(response: &tungstenite::handshake::client::Response) -> Self {
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        Self {
+            status: response.status().as_u16(),
+            protocol: header("sec-websocket-protocol"),
+            server_version: header(SERVER_VERSION_HEADER).or_else(|| header("server")),
+        }
     }
+}
 
-    pub async fn delete(
+/// What a server supports, as reported by [`StreamShare::capabilities`].
+///
+/// Anything the server doesn't report is assumed unsupported.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ServerCapabilities {
+    pub server_version: Option<String>,
+    pub max_file_size: Option<u64>,
+    pub ranges: bool,
+    pub compression: bool,
+    pub resume: bool,
+    /// Whether small files can be uploaded in a single `POST /api/upload`,
+    /// see [`StreamShareBuilder::small_file_threshold`].
+    pub direct_upload: bool,
+}
+
+/// Storage used by the authenticated user, as reported by [`StreamShare::usage`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub used_bytes: u64,
+    /// `None` if there is no limit.
+    #[serde(default)]
+    pub quota_bytes: Option<u64>,
+    pub file_count: u64,
+}
+
+/// Identifies the version of a file a caller already has, for
+/// [`StreamShare::download_conditional`].
+#[derive(Debug, Clone)]
+pub enum Validator {
+    /// An `ETag` as returned by the server, including quotes. Weak tags
+    /// (`W/"..."`) match regardless of the `W/` prefix, as `If-None-Match`
+    /// uses weak comparison. Unquoted values are quoted before sending.
+    ETag(String),
+    /// A `Last-Modified` date as returned by the server.
+    LastModified(String),
+}
+
+/// Result of [`StreamShare::download_conditional`].
+#[derive(Debug, Clone)]
+pub enum DownloadOutcome {
+    Downloaded {
+        path: PathBuf,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+    NotModified,
+}
+
+/// What to do about a download whose destination already exists, decided by
+/// [`on_conflict`](StreamShareBuilder::on_conflict).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConflictDecision {
+    /// Overwrite the existing file.
+    Replace,
+    /// Keep the existing file and fail with [`StreamShareError::Skipped`].
+    Skip,
+    /// Download to this path instead. It is checked like the original, so
+    /// `on_conflict` is asked again if it exists too.
+    Rename(PathBuf),
+}
+
+/// Result of [`StreamShare::delete`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteOutcome {
+    Deleted,
+    /// The server reported `404 Not Found`, so the file is already gone.
+    AlreadyAbsent,
+}
+
+pub(crate) type CreatedHook = Arc<dyn Fn(&UploadResult) + Send + Sync>;
+pub(crate) type RetryHook = Arc<dyn Fn(u32, &StreamShareError) + Send + Sync>;
+pub(crate) type VersionHook = Arc<dyn Fn(&str) + Send + Sync>;
+pub(crate) type MessageHook = Arc<dyn Fn(&str) + Send + Sync>;
+pub(crate) type ConflictHook = Arc<dyn Fn(&Path) -> ConflictDecision + Send + Sync>;
+pub(crate) type CompleteHook = Arc<dyn Fn(&TransferMetrics) + Send + Sync>;
+
+pub struct StreamShare {
+    server_url: String,
+    chunk_size: usize,
+    client: Client,
+    buffer_pool: Option<BufferPool>,
+    last_handshake: Mutex<Option<HandshakeInfo>>,
+    on_created: Option<CreatedHook>,
+    on_retry: Option<RetryHook>,
+    active_uploads: Mutex<HashMap<String, Arc<ActiveUpload>>>,
+    capabilities: OnceCell<ServerCapabilities>,
+    idempotency_keys: bool,
+    scheme: Scheme,
+    auth_token: Option<String>,
+    /// Precomputed `Authorization: Basic` value, marked sensitive.
+    basic_auth: Option<HeaderValue>,
+    completion_code: CloseCode,
+    completion_reason: String,
+    allow_non_regular: bool,
+    connect_timeout: Option<Duration>,
+    ack_timeout: Option<Duration>,
+    overall_timeout: Option<Duration>,
+    upload_permits: Option<Semaphore>,
+    binary_ack: BinaryAck,
+    follow_symlinks: bool,
+    download_root: Option<PathBuf>,
+    local_address: Option<IpAddr>,
+    ip_preference: IpPreference,
+    download_precheck: bool,
+    expand_tilde: bool,
+    #[cfg(feature = "compression")]
+    compression: Option<u32>,
+    #[cfg(feature = "compression")]
+    auto_decompress: bool,
+    tcp_nodelay: bool,
+    tcp_send_buffer_size: Option<u32>,
+    tcp_recv_buffer_size: Option<u32>,
+    max_maintenance_wait: Duration,
+    verify_on_disk: bool,
+    protocol_version: ProtocolVersion,
+    send_checksum: bool,
+    write_buffer_size: usize,
+    sync_on_complete: bool,
+    ws_server_url: Option<String>,
+    ws_base_path: Option<String>,
+    deletion_token_header: String,
+    small_file_threshold: Option<u64>,
+    hash_algorithm: HashAlgorithm,
+    nack_token: String,
+    max_chunk_resends: u32,
+    max_frame_size: usize,
+    host_limits: Option<HostLimits>,
+    state_save_interval: u64,
+    progress_interval: Duration,
+    admin_key: Option<String>,
+    strict_version: bool,
+    on_version_mismatch: Option<VersionHook>,
+    version_warned: AtomicBool,
+    origin: Option<String>,
+    availability_poll_interval: Duration,
+    reject_empty: bool,
+    compute_checksum: bool,
+    chunk_delay: Option<Duration>,
+    fallback_extension: Option<String>,
+    max_chunks: Option<u64>,
+    slow_down_token: Option<String>,
+    transport: Arc<dyn HttpTransport>,
+    keepalive_interval: Option<Duration>,
+    #[cfg(feature = "xattr")]
+    set_type_xattr: bool,
+    #[cfg(feature = "middleware")]
+    middleware: Option<reqwest_middleware::ClientWithMiddleware>,
+    ws_tls_config: Option<Arc<rustls::ClientConfig>>,
+    informational_prefixes: Vec<String>,
+    on_informational: Option<MessageHook>,
+    on_conflict: Option<ConflictHook>,
+    on_complete: Option<CompleteHook>,
+    allow_foreign_signed_urls: bool,
+    chunk_framing: ChunkFraming,
+    text_ack: TextAck,
+    adaptive_chunking: bool,
+    batch_retry_budget: Option<u32>,
+}
+
+/// Retries a batch operation may still make, shared by all of its items.
+struct RetryBudget {
+    remaining: AtomicU32,
+    exhausted: AtomicBool,
+}
+
+impl RetryBudget {
+    /// Takes one retry, or marks the budget as exhausted if none is left.
+    fn take(&self) -> bool {
+        let taken = self
+            .remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok();
+        if !taken {
+            self.exhausted.store(true, Ordering::Relaxed);
+        }
+        taken
+    }
+}
+
+tokio::task_local! {
+    /// The budget of the batch whose items the current task runs. The items
+    /// are polled on one task, so every request they make sees it.
+    static BATCH_RETRIES: Arc<RetryBudget>;
+}
+
+impl StreamShare {
+    pub fn new(server_url: impl Into<String>, chunk_size: usize) -> Self {
+        Self::builder()
+            .server_url(server_url)
+            .chunk_size(chunk_size)
+            .build_unchecked()
+    }
+
+    pub fn builder() -> StreamShareBuilder {
+        StreamShareBuilder::new()
+    }
+
+    /// Reads the configuration from environment variables, falling back to the
+    /// defaults for anything unset. See [`StreamShareBuilder::from_env`].
+    pub fn from_env() -> Result<Self, StreamShareError> {
+        StreamShareBuilder::from_env()?.build()
+    }
+
+    /// Whether requests go over `https`/`wss` or plain `http`/`ws`.
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    fn api_endpoint(&self) -> Endpoint<'_> {
+        self.api_endpoint_with(self.scheme)
+    }
+
+    fn api_endpoint_with(&self, scheme: Scheme) -> Endpoint<'_> {
+        Endpoint {
+            scheme: scheme.http(),
+            host: &self.server_url,
+            base_path: "",
+        }
+    }
+
+    fn ws_endpoint(&self) -> Endpoint<'_> {
+        Endpoint {
+            scheme: self.scheme.ws(),
+            host: self.ws_server_url.as_ref().unwrap_or(&self.server_url),
+            base_path: self.ws_base_path.as_deref().unwrap_or(""),
+        }
+    }
+
+    fn http_url(&self, path: &str) -> String {
+        self.api_endpoint().url(path)
+    }
+
+    fn create_url(&self) -> String {
+        urls::create_url(&self.api_endpoint())
+    }
+
+    /// The link under which a file can be downloaded, e.g.
+    /// `https://streamshare.wireway.ch/download/{file_identifier}`.
+    ///
+    /// Fails with [`StreamShareError::InvalidSegment`] for an empty or
+    /// dot-only identifier, which would point somewhere else once the URL is
+    /// parsed. The same goes for every other URL and request taking an
+    /// identifier or token.
+    pub fn download_url(&self, file_identifier: &str) -> Result<String, StreamShareError> {
+        urls::download_url(&self.api_endpoint(), file_identifier)
+    }
+
+    /// Like [`download_url`](Self::download_url), but with `scheme` instead of
+    /// the client's.
+    pub fn download_url_with_scheme(
+        &self,
+        file_identifier: &str,
+        scheme: Scheme,
+    ) -> Result<String, StreamShareError> {
+        urls::download_url(&self.api_endpoint_with(scheme), file_identifier)
+    }
+
+    /// The upload WebSocket URL of a created file, e.g.
+    /// `wss://streamshare.wireway.ch/api/upload/{file_identifier}`, for
+    /// driving the upload without this client.
+    pub fn upload_url(&self, file_identifier: &str) -> Result<String, StreamShareError> {
+        urls::upload_ws_url(&self.ws_endpoint(), file_identifier)
+    }
+
+    /// The link that deletes a file when sent a `DELETE`, e.g.
+    /// `https://streamshare.wireway.ch/api/delete/{file_identifier}/{deletion_token}`.
+    pub fn deletion_url(
         &self,
         file_identifier: &str,
         deletion_token: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let delete_url = format!(
-            "https://{}/api/delete/{}/{}",
-            self.server_url, file_identifier, deletion_token
-        );
-
-        let res = self.client.delete(&delete_url).send().await?;
-        if res.status().is_success() {
-            Ok(())
-        } else {
-            Err(format!("Failed to delete file: {}", res.status()).into())
-        }
+    ) -> Result<String, StreamShareError> {
+        urls::delete_url(&self.api_endpoint(), file_identifier, deletion_token)
     }
 
-    pub async fn download(
+    /// Like [`deletion_url`](Self::deletion_url), but with `scheme` instead of
+    /// the client's.
+    pub fn deletion_url_with_scheme(
         &self,
         file_identifier: &str,
-        download_path: &str,
-        replace: bool,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let res = self
-            .client
-            .get(format!(
-                "https://{}/download/{}",
-                self.server_url, file_identifier
-            ))
-            .send()
-            .await?
-            .error_for_status()?;
+        deletion_token: &str,
+        scheme: Scheme,
+    ) -> Result<String, StreamShareError> {
+        urls::delete_url(
+            &self.api_endpoint_with(scheme),
+            file_identifier,
+            deletion_token,
+        )
+    }
 
-        let unknown = format!("{}.unknown", file_identifier);
+    /// Both links of an upload, e.g. for a CLI to print after uploading.
+    pub fn share_links(&self, result: &UploadResult) -> Result<ShareLinks, StreamShareError> {
+        Ok(ShareLinks {
+            download: self.download_url(&result.file_identifier)?,
+            delete: self.deletion_url(&result.file_identifier, &result.deletion_token)?,
+        })
+    }
 
-        let file_name = res
-            .headers()
-            .get("content-disposition")
-            .and_then(|header| header.to_str().ok())
-            .and_then(|header_value| {
-                header_value.split(';').find_map(|part| {
-                    let trimmed = part.trim();
-                    if trimmed.starts_with("filename=") {
-                        Some(
-                            trimmed
-                                .trim_start_matches("filename=")
-                                .trim_matches('"')
-                                .to_string(),
-                        )
-                    } else {
-                        None
-                    }
-                })
-            })
-            .unwrap_or_else(|| unknown.clone());
+    /// Starts a request against the server, attaching the auth token or basic
+    /// auth credentials if set.
+    fn request(&self, method: Method, url: &str) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, url);
+        match (&self.auth_token, &self.basic_auth) {
+            (Some(token), _) => request.bearer_auth(token),
+            (None, Some(basic)) => request.header(AUTHORIZATION, basic.clone()),
+            (None, None) => request,
+        }
+    }
 
-        let expanded_path = shellexpand::tilde(download_path);
-        let path = Path::new(&*expanded_path);
+    /// Compares the server's `X-StreamShare-Version` against the supported
+    /// majors. A mismatch fails with `strict_version` and otherwise is passed
+    /// to `on_version_mismatch`, once per client.
+    fn check_server_version(&self, headers: &HeaderMap) -> Result<(), StreamShareError> {
+        let Some(version) = headers
+            .get(SERVER_VERSION_HEADER)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return Ok(());
+        };
+        if server_version_supported(version) {
+            return Ok(());
+        }
+        if self.strict_version {
+            return Err(StreamShareError::IncompatibleServer {
+                version: version.to_string(),
+            });
+        }
+        if let Some(on_version_mismatch) = &self.on_version_mismatch {
+            if !self.version_warned.swap(true, Ordering::Relaxed) {
+                on_version_mismatch(version);
+            }
+        }
+        Ok(())
+    }
 
-        let file_path = if path.as_os_str().is_empty() {
-            PathBuf::from(&file_name)
-        } else if path.exists() {
-            if path.is_dir() {
-                path.join(&file_name)
-            } else if path.is_file() {
-                path.to_path_buf()
-            } else {
-                return Err(format!(
-                    "Path exists but is neither a file nor a directory: {}",
-                    path.display()
+    /// The deletion token in the `deletion_token_header` of a response, if any.
+    fn header_token(&self, headers: &HeaderMap) -> Option<String> {
+        headers
+            .get(&self.deletion_token_header)?
+            .to_str()
+            .ok()
+            .map(str::to_string)
+    }
+
+    /// Sends `request`, waiting out `503 Service Unavailable` responses for up
+    /// to `max_maintenance_wait` in total.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, StreamShareError> {
+        let request = request.build()?;
+        let mut waited = Duration::ZERO;
+        let mut attempt = 0;
+        loop {
+            let permit = self.host_permit(request.url().host_str()).await;
+            let res = self
+                .execute(
+                    request
+                        .try_clone()
+                        .expect("request bodies are never streamed"),
                 )
-                .into());
-            }
-        } else {
-            if let Some(parent) = path.parent() {
-                if parent.exists() && parent.is_dir() {
-                    path.to_path_buf()
-                } else {
-                    return Err(format!(
-                        "Parent directory does not exist or is not a directory: {}",
-                        parent.display()
-                    )
-                    .into());
-                }
-            } else {
-                path.to_path_buf()
+                .await?;
+            if res.status() != StatusCode::SERVICE_UNAVAILABLE {
+                return Ok(connect::hold_permit(res, permit));
             }
-        };
+            drop(permit);
 
-        if file_path.exists() && !replace {
-            return Err(format!("File already exists: {}", file_path.display()).into());
+            let wait = self.maintenance_wait(res.headers(), &mut waited, &mut attempt);
+            drain(res).await;
+            tokio::time::sleep(wait?).await;
         }
+    }
 
-        if let Some(parent) = file_path.parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent).await?;
+    /// Sends `request` once, through the
+    /// [`middleware`](StreamShareBuilder::middleware) stack if one is set. The
+    /// future doesn't borrow the client, so it can outlive the call.
+    fn execute(
+        &self,
+        request: reqwest::Request,
+    ) -> impl Future<Output = Result<Response, StreamShareError>> + 'static {
+        let client = self.client.clone();
+        #[cfg(feature = "middleware")]
+        let middleware = self.middleware.clone();
+        async move {
+            #[cfg(feature = "middleware")]
+            if let Some(middleware) = middleware {
+                return Ok(middleware.execute(request).await?);
             }
+            Ok(client.execute(request).await?)
         }
+    }
 
-        let mut file = File::create(&file_path).await?;
-        let content = res.bytes().await?;
+    /// Like [`send`](Self::send), but through the
+    /// [`HttpTransport`] and with the body read in full.
+    async fn exchange(&self, request: RequestBuilder) -> Result<HttpResponse, StreamShareError> {
+        let request = request.build()?;
+        let host = request.url().host_str().map(str::to_string);
+        let request = HttpRequest::from_reqwest(&request);
+        let mut waited = Duration::ZERO;
+        let mut attempt = 0;
+        loop {
+            let permit = self.host_permit(host.as_deref()).await;
+            // The transport reads the body in full, so the permit covers it.
+            let res = self.transport.execute(request.clone()).await?;
+            drop(permit);
+            if res.status != StatusCode::SERVICE_UNAVAILABLE {
+                return Ok(res);
+            }
 
-        file.write_all(&content).await?;
-        Ok(())
+            let wait = self.maintenance_wait(&res.headers, &mut waited, &mut attempt)?;
+            tokio::time::sleep(wait).await;
+        }
     }
-}
 
-impl Default for StreamShare {
-    fn default() -> Self {
-        Self {
-            server_url: "streamshare.wireway.ch".to_string(),
-            chunk_size: 1024 * 1024,
-            client: Client::new(),
+    /// How long to wait before retrying after a `503` with these headers, or
+    /// the error once that would exceed `max_maintenance_wait` in total.
+    fn maintenance_wait(
+        &self,
+        headers: &HeaderMap,
+        waited: &mut Duration,
+        attempt: &mut u32,
+    ) -> Result<Duration, StreamShareError> {
+        let retry_after = headers
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+        // `Retry-After` has whole seconds, so each retry waits and counts at
+        // least one and a server answering `0` can't keep this going forever.
+        let charge = |wait: Duration| wait.max(Duration::from_secs(1));
+        match retry_after {
+            Some(wait) if *waited + charge(wait) <= self.max_maintenance_wait => {
+                if !BATCH_RETRIES
+                    .try_with(|budget| budget.take())
+                    .unwrap_or(true)
+                {
+                    return Err(StreamShareError::RetryBudgetExhausted);
+                }
+                *attempt += 1;
+                if let Some(on_retry) = &self.on_retry {
+                    on_retry(
+                        *attempt,
+                        &StreamShareError::ServerUnavailable { retry_after },
+                    );
+                }
+                *waited += charge(wait);
+                Ok(charge(wait))
+            }
+            _ => Err(StreamShareError::ServerUnavailable { retry_after }),
         }
     }
+
+    /// Opens a connection to the server ahead of time, so the first upload or
+    /// download doesn't pay for DNS and the TLS handshake.
+    ///
+    /// The connection is kept in the HTTP pool until it idles out. Any status
+    /// the server answers with counts as success. A failure is returned for
+    /// logging, but leaves the client fully usable, so it is safe to ignore.
+    pub async fn warm_up(&self) -> Result<(), StreamShareError> {
+        let request = self.request(Method::HEAD, &self.http_url("/")).build()?;
+        let _permit = self.host_permit(request.url().host_str()).await;
+        self.execute(request).await?;
+        Ok(())
+    }
+
+    /// Consumes the client and asks its pooled connections to close.
+    ///
+    /// This is best effort: idle keep-alive connections live in background
+    /// tasks that only notice the client is gone on their next poll, and
+    /// `reqwest` offers no way to wait for them. Dropping the client and
+    /// yielding once gives those tasks a chance to run, which usually closes
+    /// the sockets on a multi-threaded runtime, but nothing guarantees they
+    /// are closed when this returns.
+    pub async fn shutdown(self) {
+        drop(self);
+        tokio::task::yield_now().await;
+    }
+
+    /// Returns the handshake details of the last upload WebSocket connection.
+    ///
+    /// Useful when an upload misbehaves against an unexpected server version,
+    /// since the upgrade response headers are often the only clue.
+    pub fn last_handshake(&self) -> Option<HandshakeInfo> {
+        self.last_handshake.lock().unwrap().clone()
+    }
+
+    /// Fetches how much storage the authenticated user has used from
+    /// `GET /api/usage`, sent with the configured auth token.
+    ///
+    /// Servers without that endpoint (`404`, `405` or `501`) fail with
+    /// [`StreamShareError::UsageUnsupported`].
+    pub async fn usage(&self) -> Result<Usage, StreamShareError> {
+        let res = self
+            .send(self.request(Method::GET, &self.http_url("/api/usage")))
+            .await?;
+        match res.status() {
+            StatusCode::NOT_FOUND
+            | StatusCode::METHOD_NOT_ALLOWED
+            | StatusCode::NOT_IMPLEMENTED => {
+                drain(res).await;
+                Err(StreamShareError::UsageUnsupported)
+            }
+            _ => Ok(res.error_for_status()?.json().await?),
+        }
+    }
+
+    /// Fetches what the server supports from `/api/capabilities`, caching the
+    /// result for the lifetime of the client.
+    ///
+    /// Servers without that endpoint, or with one that can't be parsed, get
+    /// the conservative [`ServerCapabilities::default`], with the version
+    /// filled in from the response headers where possible. Connection errors
+    /// are returned and not cached.
+    pub async fn capabilities(&self) -> Result<ServerCapabilities, StreamShareError> {
+        self.capabilities
+            .get_or_try_init(|| async {
+                let res = self
+                    .send(self.request(Method::GET, &self.http_url("/api/capabilities")))
+                    .await?;
+                let header_version = res
+                    .headers()
+                    .get(SERVER_VERSION_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string);
+
+                let mut capabilities = if res.status().is_success() {
+                    res.json::<ServerCapabilities>().await.unwrap_or_default()
+                } else {
+                    ServerCapabilities::default()
+                };
+                capabilities.server_version = capabilities.server_version.or(header_version);
+                Ok(capabilities)
+            })
+            .await
+            .cloned()
+    }
+
+    pub async fn upload<F>(
+        &self,
+        file_path: &str,
+        callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        self.upload_with_options(file_path, &UploadOptions::default(), callback)
+            .await
+    }
+
+    pub async fn upload_with_options<F>(
+        &self,
+        file_path: &str,
+        options: &UploadOptions,
+        mut callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        self.upload_with_chunk_progress(file_path, options, |_, uploaded, total| {
+            callback(uploaded, total)
+        })
+        .await
+    }
+
+    /// Uploads several files, running up to `concurrency` uploads at once.
+    ///
+    /// The results are in the order of `file_paths`; one failure doesn't
+    /// stop the others, unless the
+    /// [`batch_retry_budget`](StreamShareBuilder::batch_retry_budget) runs
+    /// out. `callback` gets the index of the file, its `(uploaded, total)`
+    /// like [`upload`](Self::upload), and the bytes uploaded across all files
+    /// so far.
+    pub async fn upload_many<F>(
+        &self,
+        file_paths: &[String],
+        concurrency: usize,
+        callback: F,
+    ) -> Vec<Result<UploadResult, StreamShareError>>
+    where
+        F: FnMut(usize, u64, u64, u64),
+    {
+        let progress = Mutex::new((0u64, callback));
+        self.batch(file_paths.len(), concurrency, |index| {
+            let progress = &progress;
+            let mut uploaded_before = 0;
+            self.upload(&file_paths[index], move |uploaded, total| {
+                let mut progress = progress.lock().unwrap();
+                let (overall, callback) = &mut *progress;
+                *overall += uploaded.saturating_sub(uploaded_before);
+                uploaded_before = uploaded;
+                callback(index, uploaded, total, *overall);
+            })
+        })
+        .await
+    }
+
+    /// Like [`upload`](Self::upload), but publishes progress to a
+    /// [`watch::Receiver`] instead of a callback.
+    ///
+    /// The receiver only ever holds the newest [`Progress`], so a render loop
+    /// can sample it at its own pace without draining updates. Nothing is sent
+    /// until the returned future is polled.
+    pub fn upload_watched<'a>(
+        &'a self,
+        file_path: &'a str,
+    ) -> (
+        watch::Receiver<Progress>,
+        impl Future<Output = Result<UploadResult, StreamShareError>> + 'a,
+    ) {
+        let (sender, receiver) = watch::channel(Progress::default());
+        let upload = self.upload(file_path, move |uploaded, total| {
+            sender.send_replace(Progress { uploaded, total });
+        });
+        (receiver, upload)
+    }
+
+    /// Like [`upload`](Self::upload), but stops cleanly on Ctrl-C, and on
+    /// `SIGTERM` on Unix, instead of the process dying mid-upload.
+    ///
+    /// On a signal the WebSocket is closed with a close frame before the next
+    /// chunk and [`StreamShareError::Cancelled`] returned, so the server sees
+    /// the upload end rather than a dropped connection. Signals are only caught
+    /// while the upload runs. It is a wrapper over [`UploadOptions::abort`],
+    /// which can be used directly to stop an upload on other events.
+    pub async fn upload_until_ctrl_c<F>(
+        &self,
+        file_path: &str,
+        callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let abort = Arc::new(AtomicBool::new(false));
+        let options = UploadOptions {
+            abort: Some(abort.clone()),
+            ..Default::default()
+        };
+        let upload = self.upload_with_options(file_path, &options, callback);
+        tokio::pin!(upload);
+        tokio::select! {
+            result = &mut upload => return result,
+            _ = shutdown_signal() => abort.store(true, Ordering::Relaxed),
+        }
+        upload.await
+    }
+
+    /// Uploads several local files as a single file named `file_name`, as if
+    /// they were concatenated in the given order.
+    ///
+    /// The files are read one after another over the same WebSocket, so
+    /// nothing is joined on disk. Progress is reported against their combined
+    /// size.
+    pub async fn upload_concat<F>(
+        &self,
+        file_paths: &[&str],
+        file_name: &str,
+        mut callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let mut total = 0;
+        let mut reader: Box<dyn AsyncRead + Unpin + Send> = Box::new(tokio::io::empty());
+        for file_path in file_paths {
+            let metadata = fs::metadata(file_path).await?;
+            if !metadata.is_file() {
+                return Err(StreamShareError::NotAFile {
+                    path: file_path.to_string(),
+                });
+            }
+            total += metadata.len();
+            reader = Box::new(reader.chain(File::open(file_path).await?));
+        }
+
+        self.upload_stream(
+            file_name,
+            UploadSource::Reader(reader),
+            Some(total),
+            None,
+            &UploadOptions::default(),
+            |_, uploaded, total| callback(uploaded, total),
+        )
+        .await
+    }
+
+    /// Like [`upload_with_options`](Self::upload_with_options), but the callback
+    /// also receives the zero-based index of the chunk that was just acknowledged:
+    /// `callback(chunk_index, uploaded, total)`.
+    ///
+    /// Together with [`chunk_count`] this allows showing "chunk 5 of 20".
+    pub async fn upload_with_chunk_progress<F>(
+        &self,
+        file_path: &str,
+        options: &UploadOptions,
+        callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64, u64),
+    {
+        let path = Path::new(file_path);
+        let metadata = fs::metadata(path).await?;
+
+        // Pipes and character devices have no meaningful size, so they are
+        // streamed like any other reader of unknown length.
+        let file_size = if metadata.is_file() {
+            Some(metadata.len())
+        } else if self.allow_non_regular && !metadata.is_dir() {
+            None
+        } else {
+            return Err(StreamShareError::NotAFile {
+                path: file_path.to_string(),
+            });
+        };
+        if self.reject_empty && file_size == Some(0) {
+            return Err(StreamShareError::EmptyFile {
+                path: path.to_path_buf(),
+            });
+        }
+
+        let file_name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("unknown");
+
+        let checksum = match file_size {
+            Some(_) if self.send_checksum => Some(
+                Checksum::of_file(self.hash_algorithm, path)
+                    .await
+                    .map_err(|e| read_failed(e.into(), path))?,
+            ),
+            _ => None,
+        };
+
+        let file = File::open(path)
+            .await
+            .map_err(|e| read_failed(e.into(), path))?;
+        // Unless the create request needs the checksum up front, the file is
+        // hashed in the same pass that uploads it.
+        let prefix = (self.compute_checksum && checksum.is_none()).then(|| {
+            Mutex::new(HashedPrefix {
+                hasher: Hasher::new(self.hash_algorithm),
+                len: 0,
+            })
+        });
+        let source = match &prefix {
+            Some(prefix) => Either::Left(HashingReader {
+                inner: file,
+                prefix,
+            }),
+            None => Either::Right(file),
+        };
+        let mut result = self
+            .upload_stream(
+                file_name,
+                UploadSource::Reader(source),
+                file_size,
+                checksum,
+                options,
+                callback,
+            )
+            .await
+            .map_err(|e| read_failed(e, path))?;
+
+        if let Some(prefix) = prefix {
+            result = result.with_client_checksum(prefix.into_inner().unwrap().hasher.finish());
+        }
+        Ok(result)
+    }
+
+    /// Uploads everything read from `reader` under the given `file_name`.
+    ///
+    /// A known `size` is sent to the server when the upload is created and
+    /// used for progress reporting. When it is `None` the callback receives
+    /// `0` as the total.
+    pub async fn upload_reader<R, F>(
+        &self,
+        file_name: &str,
+        reader: R,
+        size: Option<u64>,
+        callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        R: AsyncRead + Unpin,
+        F: FnMut(u64, u64),
+    {
+        self.upload_reader_with_options(
+            file_name,
+            reader,
+            size,
+            &UploadOptions::default(),
+            callback,
+        )
+        .await
+    }
+
+    pub async fn upload_reader_with_options<R, F>(
+        &self,
+        file_name: &str,
+        reader: R,
+        size: Option<u64>,
+        options: &UploadOptions,
+        mut callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        R: AsyncRead + Unpin,
+        F: FnMut(u64, u64),
+    {
+        self.upload_stream(
+            file_name,
+            UploadSource::Reader(reader),
+            size,
+            None,
+            options,
+            |_, uploaded, total| callback(uploaded, total),
+        )
+        .await
+    }
+
+    /// Uploads data already held as [`Bytes`], e.g. when re-hosting content.
+    ///
+    /// Chunks are reference-counted slices of `data`, so unlike
+    /// [`upload_reader`](Self::upload_reader) nothing is copied per chunk.
+    pub async fn upload_bytes<F>(
+        &self,
+        file_name: &str,
+        data: Bytes,
+        mut callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let size = data.len() as u64;
+        let checksum = self
+            .send_checksum
+            .then(|| Checksum::of(self.hash_algorithm, &data));
+        self.upload_stream(
+            file_name,
+            UploadSource::<tokio::io::Empty>::Bytes(data),
+            Some(size),
+            checksum,
+            &UploadOptions::default(),
+            |_, uploaded, total| callback(uploaded, total),
+        )
+        .await
+    }
+
+    /// Like [`upload`](Self::upload), but memory-maps the file and sends
+    /// chunks as slices of the mapping, so a huge file is neither copied into
+    /// a chunk buffer nor read with a syscall per chunk. Needs the `mmap`
+    /// feature.
+    ///
+    /// The file must not be truncated until the upload returns: reading a
+    /// page past the new end of a mapped file raises `SIGBUS`, which kills the
+    /// process and can't be caught here. Data changed in place is sent as it
+    /// is when read. Prefer [`upload`](Self::upload) for files that other
+    /// processes may still write to.
+    #[cfg(feature = "mmap")]
+    pub async fn upload_mmap<F>(
+        &self,
+        file_path: &str,
+        callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let path = Path::new(file_path);
+        let file = std::fs::File::open(path).map_err(|e| read_failed(e.into(), path))?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(StreamShareError::NotAFile {
+                path: file_path.to_string(),
+            });
+        }
+        if self.reject_empty && metadata.len() == 0 {
+            return Err(StreamShareError::EmptyFile {
+                path: path.to_path_buf(),
+            });
+        }
+        let file_name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("unknown");
+
+        // Mapping zero bytes fails on some platforms.
+        let data = if metadata.len() == 0 {
+            Bytes::new()
+        } else {
+            // SAFETY: the mapping is only read. Truncating the file while it
+            // is mapped is the caller's responsibility, as documented above.
+            let map = unsafe { memmap2::Mmap::map(&file)? };
+            #[cfg(unix)]
+            let _ = map.advise(memmap2::Advice::Sequential);
+            Bytes::from_owner(map)
+        };
+        self.upload_bytes(file_name, data, callback).await
+    }
+
+    /// Shares a snippet of text as a `text/plain` file, named `paste.txt`
+    /// unless `file_name` is given. The share link is then
+    /// [`download_url`](Self::download_url) of the returned identifier.
+    pub async fn upload_text<F>(
+        &self,
+        text: &str,
+        file_name: Option<&str>,
+        mut callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let data = Bytes::copy_from_slice(text.as_bytes());
+        let size = data.len() as u64;
+        let checksum = self
+            .send_checksum
+            .then(|| Checksum::of(self.hash_algorithm, &data));
+        let options = UploadOptions {
+            content_type: Some("text/plain; charset=utf-8".to_string()),
+            ..UploadOptions::default()
+        };
+        self.upload_stream(
+            file_name.unwrap_or("paste.txt"),
+            UploadSource::<tokio::io::Empty>::Bytes(data),
+            Some(size),
+            checksum,
+            &options,
+            |_, uploaded, total| callback(uploaded, total),
+        )
+        .await
+    }
+
+    async fn upload_stream<R, F>(
+        &self,
+        file_name: &str,
+        source: UploadSource<R>,
+        size: Option<u64>,
+        checksum: Option<Checksum>,
+        options: &UploadOptions,
+        callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        R: AsyncRead + Unpin,
+        F: FnMut(u64, u64, u64),
+    {
+        let mut meter = Meter::start(self, TransferKind::Upload);
+        let _permit =
+            unless_aborted(options, async { Ok(self.acquire_upload_permit().await) }).await?;
+
+        #[cfg(feature = "compression")]
+        if let Some(level) = self.compression {
+            if self.send_checksum || self.compute_checksum {
+                return Err(StreamShareError::InvalidConfig(
+                    builder::COMPRESSED_CHECKSUM.into(),
+                ));
+            }
+            let result = unless_aborted(
+                options,
+                self.create(&format!("{}.gz", file_name), None, None, options),
+            )
+            .await?;
+            let reader = match source {
+                UploadSource::Reader(reader) | UploadSource::Live(reader) => Either::Left(reader),
+                UploadSource::Bytes(data) => Either::Right(std::io::Cursor::new(data)),
+            };
+            let encoder = GzipEncoder::with_quality(
+                tokio::io::BufReader::new(reader),
+                async_compression::Level::Precise(level as i32),
+            );
+            let sent = self
+                .send_chunks(
+                    &result.file_identifier,
+                    UploadSource::Reader(encoder),
+                    None,
+                    0,
+                    &mut meter,
+                    options,
+                    callback,
+                )
+                .await?;
+
+            meter.success = true;
+            return Ok(result.with_sent(sent));
+        }
+
+        if let Some(size) = self.direct_upload_size(size, options).await {
+            let data = match source {
+                UploadSource::Bytes(data) => data,
+                UploadSource::Reader(mut reader) | UploadSource::Live(mut reader) => {
+                    let mut data = Vec::with_capacity(size as usize);
+                    reader.read_to_end(&mut data).await?;
+                    Bytes::from(data)
+                }
+            };
+            let result = unless_aborted(
+                options,
+                self.upload_direct(
+                    file_name,
+                    data,
+                    checksum.as_ref(),
+                    &mut meter,
+                    options,
+                    callback,
+                ),
+            )
+            .await?;
+            meter.success = true;
+            return Ok(result);
+        }
+
+        self.check_chunk_count(size)?;
+        let result = unless_aborted(
+            options,
+            self.create(file_name, size, checksum.as_ref(), options),
+        )
+        .await?;
+        let sent = self
+            .send_chunks(
+                &result.file_identifier,
+                source,
+                size,
+                0,
+                &mut meter,
+                options,
+                callback,
+            )
+            .await?;
+
+        meter.success = true;
+        Ok(result.with_sent(sent))
+    }
+
+    /// The size of an upload that should skip the WebSocket, if it is below
+    /// `small_file_threshold`, the server supports direct uploads and there is
+    /// no metadata that only `/api/create` accepts.
+    async fn direct_upload_size(&self, size: Option<u64>, options: &UploadOptions) -> Option<u64> {
+        let threshold = self.small_file_threshold?;
+        let size = size.filter(|&size| size <= threshold)?;
+        if options.description.is_some()
+            || options.tags.is_some()
+            || options.password.is_some()
+            || options.expires_in.is_some()
+        {
+            return None;
+        }
+        let capabilities = self.capabilities().await.ok()?;
+        capabilities.direct_upload.then_some(size)
+    }
+
+    /// Uploads `data` in a single `POST /api/upload?name={name}` request with
+    /// the file as body, which the server answers like `/api/create`.
+    async fn upload_direct<F>(
+        &self,
+        file_name: &str,
+        data: Bytes,
+        checksum: Option<&Checksum>,
+        meter: &mut Meter<'_>,
+        options: &UploadOptions,
+        mut callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64, u64),
+    {
+        let size = data.len() as u64;
+        let mut request = self
+            .request(Method::POST, &self.http_url("/api/upload"))
+            .query(&[("name", file_name)])
+            .body(data);
+        if let Some(checksum) = checksum {
+            request = request.query(&[(checksum.algorithm.name(), &checksum.digest)]);
+        }
+        if let Some(content_type) = &options.content_type {
+            request = request.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+        if let Some(key) = &options.idempotency_key {
+            request = request.header(IDEMPOTENCY_KEY_HEADER, key);
+        } else if self.idempotency_keys {
+            request = request.header(IDEMPOTENCY_KEY_HEADER, uuid::Uuid::new_v4().to_string());
+        }
+
+        let result = self.created(request, file_name, checksum).await?;
+        // The whole body goes in one request, so it counts as one chunk.
+        meter.bytes = size;
+        meter.chunks = 1;
+        callback(0, size, size);
+        Ok(result)
+    }
+
+    /// Waits for a free upload slot if `max_concurrent_uploads` is configured.
+    async fn acquire_upload_permit(&self) -> Option<SemaphorePermit<'_>> {
+        match &self.upload_permits {
+            Some(permits) => Some(
+                permits
+                    .acquire()
+                    .await
+                    .expect("upload semaphore is never closed"),
+            ),
+            None => None,
+        }
+    }
+
+    /// Waits until `host` is below `max_connections_per_host`, if configured.
+    async fn host_permit(&self, host: Option<&str>) -> Option<OwnedSemaphorePermit> {
+        match (&self.host_limits, host) {
+            (Some(limits), Some(host)) => Some(limits.acquire(host).await),
+            _ => None,
+        }
+    }
+
+    /// Registers a new upload with the server.
+    async fn create(
+        &self,
+        file_name: &str,
+        size: Option<u64>,
+        checksum: Option<&Checksum>,
+        options: &UploadOptions,
+    ) -> Result<UploadResult, StreamShareError> {
+        let create_url = self.create_url();
+
+        let request = self.request(Method::POST, &create_url);
+        let mut request = match self.protocol_version {
+            ProtocolVersion::V1 => request.json(&CreateRequest {
+                name: file_name,
+                size,
+                checksum,
+                options,
+            }),
+            ProtocolVersion::V2 => request.json(&CreateRequestV2 {
+                version: 2,
+                file: CreateFileV2 {
+                    name: file_name,
+                    size,
+                    checksum,
+                },
+                metadata: options,
+            }),
+        };
+
+        let idempotency_key = options.idempotency_key.clone().or_else(|| {
+            self.idempotency_keys
+                .then(|| uuid::Uuid::new_v4().to_string())
+        });
+        if let Some(key) = idempotency_key {
+            request = request.header(IDEMPOTENCY_KEY_HEADER, key);
+        }
+
+        self.created(request, file_name, checksum).await
+    }
+
+    /// The pause a `slow_down_token` message asks for, or `None` if `text`
+    /// isn't one.
+    fn slow_down_pause(&self, text: &str) -> Option<Duration> {
+        let rest = text.strip_prefix(self.slow_down_token.as_deref()?)?;
+        if rest.is_empty() {
+            return Some(DEFAULT_SLOW_DOWN_PAUSE);
+        }
+        let millis = rest.strip_prefix(':')?.trim().parse().ok()?;
+        Some(Duration::from_millis(millis))
+    }
+
+    /// Whether `text` is a status message to skip while waiting for an ACK.
+    fn is_informational(&self, text: &str) -> bool {
+        text != "ACK"
+            && text != self.nack_token
+            && self
+                .informational_prefixes
+                .iter()
+                .any(|prefix| text.starts_with(prefix.as_str()))
+    }
+
+    /// Fails with [`StreamShareError::TooManyChunks`] if `size` needs more
+    /// chunks than `max_chunks`.
+    fn check_chunk_count(&self, size: Option<u64>) -> Result<(), StreamShareError> {
+        let (Some(size), Some(limit)) = (size, self.max_chunks) else {
+            return Ok(());
+        };
+        let count = chunk_count(size, self.chunk_size);
+        if count > limit {
+            return Err(StreamShareError::TooManyChunks { count, limit });
+        }
+        Ok(())
+    }
+
+    /// Sends a request that creates a file and reads the created file from
+    /// its response.
+    async fn created(
+        &self,
+        request: RequestBuilder,
+        file_name: &str,
+        checksum: Option<&Checksum>,
+    ) -> Result<UploadResult, StreamShareError> {
+        let res = self.exchange(request).await?;
+
+        if !res.status.is_success() {
+            return Err(StreamShareError::CreateFailed { status: res.status });
+        }
+
+        self.check_server_version(&res.headers)?;
+        let header_token = self.header_token(&res.headers);
+        let (file_identifier, deletion_token, sha256, stored_file_name, expires_at) =
+            match self.protocol_version {
+                ProtocolVersion::V1 => {
+                    let response: CreateResponse = res.json()?;
+                    (
+                        response.file_identifier,
+                        response.deletion_token,
+                        response.sha256,
+                        response.file_name,
+                        response.expires_at,
+                    )
+                }
+                ProtocolVersion::V2 => {
+                    let response: CreateResponseV2 = res.json()?;
+                    (
+                        response.id,
+                        response.deletion_token,
+                        response.sha256,
+                        response.name,
+                        response.expires_at,
+                    )
+                }
+            };
+        let result = UploadResult {
+            file_identifier,
+            deletion_token: deletion_token.or(header_token).unwrap_or_default(),
+            checksum: None,
+            summary: None,
+            sha256: sha256.map(|digest| digest.to_ascii_lowercase()),
+            stored_file_name: Some(stored_file_name.unwrap_or_else(|| file_name.to_string())),
+            expires_at,
+            #[cfg(feature = "metrics")]
+            stats: None,
+        };
+        let result = match checksum {
+            Some(checksum) => result.with_client_checksum(checksum.clone()),
+            None => result,
+        };
+        if let Some(on_created) = &self.on_created {
+            on_created(&result);
+        }
+
+        Ok(result)
+    }
+
+    /// POSTs an arbitrary JSON body to `/api/create` and returns the response
+    /// untyped.
+    ///
+    /// Meant for experimenting with modified servers; it doesn't start an
+    /// upload. Use [`upload`](Self::upload) for the regular flow.
+    pub async fn create_raw(
+        &self,
+        body: serde_json::Value,
+    ) -> Result<serde_json::Value, StreamShareError> {
+        self.create_typed(&body).await
+    }
+
+    /// Like [`create_raw`](Self::create_raw), but deserializes the response
+    /// into `T`, for a modified server that returns extra fields.
+    ///
+    /// `T` must still contain the file identifier, as the upload WebSocket at
+    /// [`upload_url`](Self::upload_url) is addressed by it. A response that
+    /// doesn't fit `T` is a [`StreamShareError::Decode`].
+    ///
+    /// ```no_run
+    /// #[derive(serde::Deserialize)]
+    /// #[serde(rename_all = "camelCase")]
+    /// struct Created {
+    ///     file_identifier: String,
+    ///     expires_at: u64,
+    /// }
+    ///
+    /// # async fn run(client: streamshare::StreamShare) -> Result<(), streamshare::StreamShareError> {
+    /// let created: Created = client
+    ///     .create_typed(&serde_json::json!({ "name": "video.mp4", "size": 1024 }))
+    ///     .await?;
+    /// println!("{} expires at {}", client.upload_url(&created.file_identifier)?, created.expires_at);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_typed<T: DeserializeOwned>(
+        &self,
+        body: &impl Serialize,
+    ) -> Result<T, StreamShareError> {
+        let res = self
+            .exchange(self.request(Method::POST, &self.create_url()).json(body))
+            .await?;
+
+        if !res.status.is_success() {
+            return Err(StreamShareError::CreateFailed { status: res.status });
+        }
+
+        res.json()
+    }
+
+    /// Creates a file and opens its upload WebSocket without sending anything,
+    /// for custom chunking, framing or progress handling.
+    ///
+    /// Each binary message is appended to the file. The caller is responsible
+    /// for sending the close frame once done, normally
+    /// [`CloseCode::Normal`] with the reason `FILE_UPLOAD_DONE`; the upload is
+    /// incomplete until then.
+    pub async fn open_upload_stream(
+        &self,
+        file_name: &str,
+    ) -> Result<(UploadResult, WsStream), StreamShareError> {
+        let result = self
+            .create(file_name, None, None, &UploadOptions::default())
+            .await?;
+        let (ws_stream, response) = self
+            .connect_ws(self.upload_request(&result.file_identifier, 0)?)
+            .await?;
+        *self.last_handshake.lock().unwrap() = Some(HandshakeInfo::from_response(&response));
+        let header_token = self.header_token(response.headers());
+
+        Ok((result.with_fallback_token(header_token), ws_stream))
+    }
+
+    /// The handshake request for the upload WebSocket of `file_identifier`,
+    /// asking the server to continue after the first `offset` bytes if it
+    /// isn't 0.
+    fn upload_request(
+        &self,
+        file_identifier: &str,
+        offset: u64,
+    ) -> Result<tungstenite::handshake::client::Request, StreamShareError> {
+        let mut url = self.upload_url(file_identifier)?;
+        if offset > 0 {
+            url.push_str(&format!("?offset={}", offset));
+        }
+        let mut ws_request = url.into_client_request()?;
+        if let Some(token) = &self.auth_token {
+            ws_request
+                .headers_mut()
+                .insert(AUTHORIZATION, bearer_header(token)?);
+        } else if let Some(basic) = &self.basic_auth {
+            ws_request
+                .headers_mut()
+                .insert(AUTHORIZATION, basic.clone());
+        }
+        if let Some(origin) = &self.origin {
+            let origin = HeaderValue::from_str(origin).map_err(|_| {
+                StreamShareError::InvalidConfig("origin is not a valid header value".into())
+            })?;
+            ws_request.headers_mut().insert(ORIGIN, origin);
+        }
+        Ok(ws_request)
+    }
+
+    /// Streams `reader` over the upload WebSocket of an already created file.
+    ///
+    /// A non-zero `offset` resumes an upload whose first `offset` bytes the
+    /// server already has; `source` must start right after them.
+    #[allow(clippy::too_many_arguments)]
+    async fn send_chunks<R, F>(
+        &self,
+        file_identifier: &str,
+        mut source: UploadSource<R>,
+        size: Option<u64>,
+        offset: u64,
+        meter: &mut Meter<'_>,
+        options: &UploadOptions,
+        mut callback: F,
+    ) -> Result<ChunksSent, StreamShareError>
+    where
+        R: AsyncRead + Unpin,
+        F: FnMut(u64, u64, u64),
+    {
+        let total = size.unwrap_or(0);
+        let active = ActiveGuard::register(self, file_identifier);
+        let ws_request = self.upload_request(file_identifier, offset)?;
+        let _host_permit = self.host_permit(ws_request.uri().host()).await;
+        let started = Instant::now();
+        let deadline = self.overall_timeout.map(|timeout| started + timeout);
+        let timed_out = |elapsed: Elapsed, uploaded: u64| elapsed.into_error(uploaded, started);
+
+        let (mut ws_stream, response) = unless_aborted(options, async {
+            with_deadline(self.connect_ws(ws_request), deadline, None)
+                .await
+                .map_err(|elapsed| timed_out(elapsed, 0))?
+        })
+        .await?;
+        *self.last_handshake.lock().unwrap() = Some(HandshakeInfo::from_response(&response));
+        let header_token = self.header_token(response.headers());
+
+        // Shared bytes are sliced directly, so they don't need a read buffer.
+        let mut buffer = match (&source, &self.buffer_pool) {
+            (UploadSource::Bytes(_), _) => ChunkBuffer::Owned(Vec::new()),
+            (_, Some(pool)) => ChunkBuffer::Pooled(pool.acquire().await),
+            (_, None) => ChunkBuffer::Owned(vec![0u8; self.chunk_size]),
+        };
+        let fill = !matches!(source, UploadSource::Live(_));
+        // Only with a deadline to aim for; see the `adaptive` module.
+        let mut sizer = deadline
+            .filter(|_| self.adaptive_chunking)
+            .map(|deadline| ChunkSizer::new(self.chunk_size, deadline));
+        // A panicking callback must not unwind past the open WebSocket.
+        let mut report = |chunk_index, confirmed, total| {
+            panic::catch_unwind(AssertUnwindSafe(|| callback(chunk_index, confirmed, total)))
+                .is_ok()
+        };
+        // `sent` is the position in the source, `confirmed` only advances on
+        // an ACK, so progress never runs ahead of what the server has.
+        let mut sent = offset;
+        let mut confirmed = offset;
+        let mut chunk_index: u64 = 0;
+        let mut last_report: Option<Instant> = None;
+        let mut reported = offset;
+        // Longest pause the server asked for since the last chunk.
+        let mut slow_down = Duration::ZERO;
+        let mut frame_index: u64 = 0;
+        let frame_data_size = self
+            .max_frame_size
+            .saturating_sub(self.chunk_framing.header_len())
+            .max(1);
+        #[cfg(feature = "metrics")]
+        let mut latencies = Vec::new();
+
+        loop {
+            if let Some(handle) = &options.handle {
+                let paused = async {
+                    tokio::select! {
+                        _ = handle.wait_until_resumed() => {}
+                        _ = active.cancel.notified() => {}
+                    }
+                };
+                keep_alive(&mut ws_stream, self.keepalive_interval, paused).await?;
+            }
+            if active.cancelled.load(Ordering::Relaxed)
+                || options
+                    .abort
+                    .as_ref()
+                    .is_some_and(|abort| abort.load(Ordering::Relaxed))
+            {
+                let _ = ws_stream
+                    .close(Some(tungstenite::protocol::CloseFrame {
+                        code: CloseCode::Away,
+                        reason: "Upload cancelled".into(),
+                    }))
+                    .await;
+                return Err(StreamShareError::Cancelled);
+            }
+
+            let chunk_len = sizer.as_ref().map_or(self.chunk_size, ChunkSizer::current);
+            let chunk = match &mut source {
+                UploadSource::Reader(reader) | UploadSource::Live(reader) => {
+                    let len = chunk_len.min(buffer.len());
+                    let read = with_deadline(
+                        keep_alive(
+                            &mut ws_stream,
+                            self.keepalive_interval,
+                            read_chunk(reader, &mut buffer[..len], fill),
+                        ),
+                        deadline,
+                        None,
+                    )
+                    .await
+                    .map_err(|elapsed| timed_out(elapsed, confirmed))??;
+                    let n = match read {
+                        Ok(n) => n,
+                        Err(source) => {
+                            // Tell the server the upload is over instead of
+                            // letting it wait for the next chunk.
+                            let _ = ws_stream
+                                .close(Some(tungstenite::protocol::CloseFrame {
+                                    code: CloseCode::Error,
+                                    reason: "Failed to read upload data".into(),
+                                }))
+                                .await;
+                            return Err(StreamShareError::ReadError {
+                                offset: sent,
+                                source,
+                            });
+                        }
+                    };
+                    Bytes::copy_from_slice(&buffer[..n])
+                }
+                UploadSource::Bytes(data) => data.split_to(data.len().min(chunk_len)),
+            };
+            if chunk.is_empty() {
+                break;
+            }
+
+            let chunk_started = Instant::now();
+            let chunk_sent = chunk.len();
+            // Each frame is acknowledged on its own, so a chunk larger than
+            // the server accepts in one message still goes through.
+            let mut rest = chunk;
+            while !rest.is_empty() {
+                let frame = rest.split_to(rest.len().min(frame_data_size));
+                sent += frame.len() as u64;
+                let frame = self.chunk_framing.frame(frame_index, frame);
+                frame_index += 1;
+                meter.chunks += 1;
+                let mut resends = 0;
+                #[cfg(feature = "metrics")]
+                let sent_at = Instant::now();
+                let acknowledged = loop {
+                    with_deadline(
+                        ws_stream.send(Message::Binary(frame.clone())),
+                        deadline,
+                        None,
+                    )
+                    .await
+                    .map_err(|elapsed| timed_out(elapsed, confirmed))??;
+
+                    let ack = loop {
+                        let message = with_deadline(ws_stream.next(), deadline, self.ack_timeout)
+                            .await
+                            .map_err(|elapsed| timed_out(elapsed, confirmed))?;
+                        match &message {
+                            Some(Ok(Message::Text(text))) => match self.slow_down_pause(text) {
+                                Some(pause) => slow_down = slow_down.max(pause),
+                                None if self.is_informational(text) => {
+                                    if let Some(on_informational) = &self.on_informational {
+                                        on_informational(text);
+                                    }
+                                }
+                                None => break message,
+                            },
+                            // Answers to keep-alive pings, or the server's own.
+                            Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+                            _ => break message,
+                        }
+                    };
+
+                    match ack {
+                        Some(Ok(Message::Text(text))) if text == "ACK" => break None,
+                        Some(Ok(Message::Text(text)))
+                            if self.text_ack == TextAck::Offset && text.starts_with("ACK:") =>
+                        {
+                            let reported = text["ACK:".len()..].trim().parse().map_err(|_| {
+                                StreamShareError::UnexpectedMessage(format!(
+                                    "Malformed ACK {:?}",
+                                    text
+                                ))
+                            })?;
+                            if reported != sent {
+                                return Err(StreamShareError::AckDesync {
+                                    expected: sent,
+                                    reported,
+                                });
+                            }
+                            break Some(reported);
+                        }
+                        Some(Ok(Message::Text(text))) if text == self.nack_token => {
+                            if resends == self.max_chunk_resends {
+                                return Err(StreamShareError::ChunkRejected {
+                                    reason: format!("still rejected after {} resends", resends),
+                                });
+                            }
+                            resends += 1;
+                            meter.retries += 1;
+                        }
+                        Some(Ok(Message::Text(text))) => {
+                            return Err(StreamShareError::ChunkRejected {
+                                reason: text.to_string(),
+                            });
+                        }
+                        Some(Ok(Message::Binary(data))) if self.binary_ack != BinaryAck::Reject => {
+                            break self.binary_ack.offset(&data).ok_or_else(|| {
+                                StreamShareError::UnexpectedMessage(format!(
+                                    "Binary ACK {:?}",
+                                    data
+                                ))
+                            })?;
+                        }
+                        Some(Ok(msg)) => {
+                            return Err(StreamShareError::UnexpectedMessage(format!("{:?}", msg)));
+                        }
+                        Some(Err(e)) => return Err(e.into()),
+                        None => return Err(StreamShareError::ConnectionClosed),
+                    }
+                };
+
+                #[cfg(feature = "metrics")]
+                latencies.push(sent_at.elapsed());
+
+                if let Some(acknowledged) = acknowledged.filter(|&offset| offset > sent) {
+                    return Err(StreamShareError::UnexpectedMessage(format!(
+                        "Server acknowledged {} bytes but only {} were sent",
+                        acknowledged, sent
+                    )));
+                }
+
+                confirmed = confirmed.max(acknowledged.unwrap_or(sent));
+                meter.bytes = confirmed - offset;
+            }
+            if let Some(sizer) = &mut sizer {
+                sizer.record(chunk_sent, chunk_started.elapsed());
+            }
+            let due = last_report.is_none_or(|at| at.elapsed() >= self.progress_interval)
+                || size == Some(confirmed);
+            if due {
+                if !report(chunk_index, confirmed, total) {
+                    close_after_panic(&mut ws_stream).await;
+                    return Err(StreamShareError::CallbackPanicked);
+                }
+                last_report = Some(Instant::now());
+                reported = confirmed;
+            }
+            chunk_index += 1;
+
+            let pause = self.chunk_delay.unwrap_or_default() + slow_down;
+            if !pause.is_zero() {
+                let sleep = tokio::time::sleep(pause);
+                with_deadline(
+                    keep_alive(&mut ws_stream, self.keepalive_interval, sleep),
+                    deadline,
+                    None,
+                )
+                .await
+                .map_err(|elapsed| timed_out(elapsed, confirmed))??;
+                slow_down = Duration::ZERO;
+            }
+        }
+        // The final progress always gets through the throttle.
+        if reported != confirmed && !report(chunk_index - 1, confirmed, total) {
+            close_after_panic(&mut ws_stream).await;
+            return Err(StreamShareError::CallbackPanicked);
+        }
+
+        ws_stream
+            .close(Some(tungstenite::protocol::CloseFrame {
+                code: self.completion_code,
+                reason: self.completion_reason.clone().into(),
+            }))
+            .await?;
+
+        // Frames the server sent after the final ACK are read until its close
+        // frame. Anything but a summary is ignored, and so is a server that
+        // never closes: every chunk is confirmed at this point.
+        let mut summary = None;
+        while let Ok(Some(Ok(message))) =
+            with_deadline(ws_stream.next(), deadline, Some(TRAILING_FRAME_TIMEOUT)).await
+        {
+            match message {
+                Message::Text(text) => summary = summary.or_else(|| UploadSummary::parse(&text)),
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+
+        Ok(ChunksSent {
+            header_token,
+            summary,
+            #[cfg(feature = "metrics")]
+            latencies,
+        })
+    }
+
+    /// Replaces the content of an existing share in place, keeping its
+    /// identifier and therefore its public URL.
+    ///
+    /// **Experimental:** this needs a server that implements
+    /// `POST /api/update/{file_identifier}/{deletion_token}`, which resets the
+    /// stored file and accepts a fresh upload on the usual
+    /// `/api/upload/{file_identifier}` WebSocket. The public server may not
+    /// support it.
+    pub async fn update<F>(
+        &self,
+        file_identifier: &str,
+        deletion_token: &str,
+        new_file: &str,
+        mut callback: F,
+    ) -> Result<(), StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let mut meter = Meter::start(self, TransferKind::Upload);
+        let path = Path::new(new_file);
+        let metadata = fs::metadata(path).await?;
+        if !metadata.is_file() {
+            return Err(StreamShareError::NotAFile {
+                path: new_file.to_string(),
+            });
+        }
+
+        let file_name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("unknown");
+
+        self.check_chunk_count(Some(metadata.len()))?;
+        let _permit = self.acquire_upload_permit().await;
+        let update_url = urls::update_url(&self.api_endpoint(), file_identifier, deletion_token)?;
+        let status = self
+            .exchange(
+                self.request(Method::POST, &update_url)
+                    .json(&serde_json::json!({ "name": file_name })),
+            )
+            .await?
+            .status;
+        if !status.is_success() {
+            return Err(StreamShareError::UpdateFailed { status });
+        }
+
+        let file = File::open(path).await?;
+        self.send_chunks(
+            file_identifier,
+            UploadSource::Reader(file),
+            Some(metadata.len()),
+            0,
+            &mut meter,
+            &UploadOptions::default(),
+            |_, uploaded, total| callback(uploaded, total),
+        )
+        .await?;
+        meter.success = true;
+        Ok(())
+    }
+
+    /// Re-hosts a remote file by streaming the body of `source_url` straight
+    /// into a new upload, without buffering it to disk.
+    ///
+    /// The source's `Content-Length`, when present, is sent as the size in the
+    /// create request and used as the progress total. A source that ends
+    /// before that length fails the upload with
+    /// [`StreamShareError::ReadError`] instead of storing a truncated file.
+    /// Without it, e.g. for a chunked response, the upload has an unknown size
+    /// and progress totals are `0`.
+    pub async fn relay<F>(
+        &self,
+        source_url: &str,
+        file_name: &str,
+        callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let res = self
+            .client
+            .get(source_url)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let size = res.content_length();
+        let reader = StreamReader::new(res.bytes_stream().map_err(std::io::Error::other));
+
+        self.upload_reader(file_name, reader, size, callback).await
+    }
+
+    /// Estimates how long uploading `file_path` takes at `measured_bps` bytes
+    /// per second, e.g. to warn about a long upload before starting it.
+    ///
+    /// The throughput can come from [`measure_upload_bps`](Self::measure_upload_bps)
+    /// or from a previous upload.
+    pub async fn estimate_upload_duration(
+        &self,
+        file_path: &str,
+        measured_bps: u64,
+    ) -> Result<Duration, StreamShareError> {
+        let file_size = fs::metadata(file_path).await?.len();
+        estimate_duration(file_size, measured_bps).ok_or_else(|| {
+            StreamShareError::InvalidConfig("measured_bps must be greater than zero".to_string())
+        })
+    }
+
+    /// Measures upload throughput by uploading `probe_size` zero bytes and
+    /// deleting the probe file again. Returns bytes per second.
+    pub async fn measure_upload_bps(&self, probe_size: u64) -> Result<u64, StreamShareError> {
+        let probe = tokio::io::repeat(0).take(probe_size);
+        let started = Instant::now();
+        let result = self
+            .upload_reader("streamshare-probe", probe, Some(probe_size), |_, _| {})
+            .await?;
+        let elapsed = started.elapsed();
+        self.delete(&result.file_identifier, &result.deletion_token)
+            .await?;
+
+        Ok((probe_size as u128 * 1_000_000_000 / elapsed.as_nanos().max(1)) as u64)
+    }
+
+    /// Undoes an upload: if it is still in progress on this client, it is
+    /// cancelled and its WebSocket closed first, then the file is deleted.
+    ///
+    /// The cancelled upload returns [`StreamShareError::Cancelled`]. A file
+    /// that is already gone counts as success.
+    pub async fn cancel_and_delete(&self, result: &UploadResult) -> Result<(), StreamShareError> {
+        let active = self
+            .active_uploads
+            .lock()
+            .unwrap()
+            .get(&result.file_identifier)
+            .cloned();
+        if let Some(active) = active {
+            active.cancelled.store(true, Ordering::Relaxed);
+            active.cancel.notify_one();
+            let _ = active
+                .finished
+                .subscribe()
+                .wait_for(|finished| *finished)
+                .await;
+        }
+
+        self.delete(&result.file_identifier, &result.deletion_token)
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes a file from the server.
+    ///
+    /// A `404 Not Found` is treated as success and reported as
+    /// [`DeleteOutcome::AlreadyAbsent`], so cleanup can safely run twice.
+    /// Other failures, such as a rejected deletion token, are still errors.
+    pub async fn delete(
+        &self,
+        file_identifier: &str,
+        deletion_token: &str,
+    ) -> Result<DeleteOutcome, StreamShareError> {
+        self.delete_at(&self.deletion_url(file_identifier, deletion_token)?)
+            .await
+    }
+
+    /// Like [`delete`](Self::delete), but over `scheme` instead of the
+    /// client's, e.g. plain `http` for a server that only takes deletions
+    /// there. The deletion token and any auth are sent over it too.
+    pub async fn delete_with_scheme(
+        &self,
+        file_identifier: &str,
+        deletion_token: &str,
+        scheme: Scheme,
+    ) -> Result<DeleteOutcome, StreamShareError> {
+        self.delete_at(&self.deletion_url_with_scheme(file_identifier, deletion_token, scheme)?)
+            .await
+    }
+
+    /// Deletes several files given as `(file_identifier, deletion_token)`,
+    /// running up to `concurrency` requests at once. The results are in the
+    /// order of `items`, sharing the
+    /// [`batch_retry_budget`](StreamShareBuilder::batch_retry_budget) like
+    /// [`download_many`](Self::download_many).
+    pub async fn delete_many(
+        &self,
+        items: &[(String, String)],
+        concurrency: usize,
+    ) -> Vec<Result<DeleteOutcome, StreamShareError>> {
+        self.batch(items.len(), concurrency, |index| {
+            let (file_identifier, deletion_token) = &items[index];
+            self.delete(file_identifier, deletion_token)
+        })
+        .await
+    }
+
+    async fn delete_at(&self, delete_url: &str) -> Result<DeleteOutcome, StreamShareError> {
+        let status = self
+            .exchange(self.request(Method::DELETE, delete_url))
+            .await?
+            .status;
+        if status.is_success() {
+            Ok(DeleteOutcome::Deleted)
+        } else if status == StatusCode::NOT_FOUND {
+            Ok(DeleteOutcome::AlreadyAbsent)
+        } else {
+            Err(StreamShareError::DeleteFailed { status })
+        }
+    }
+
+    /// Deletes any file with the server's admin key instead of its deletion
+    /// token, e.g. when the token was lost.
+    ///
+    /// Sends `DELETE /api/admin/delete/{file_identifier}` with the
+    /// [`admin_key`](StreamShareBuilder::admin_key), or the
+    /// [`auth_token`](StreamShareBuilder::auth_token) if none is set, as a
+    /// bearer token. The server must grant that key admin rights; a rejected
+    /// key is a [`StreamShareError::DeleteFailed`]. Outcomes are the same as
+    /// for [`delete`](Self::delete).
+    pub async fn admin_delete(
+        &self,
+        file_identifier: &str,
+    ) -> Result<DeleteOutcome, StreamShareError> {
+        let key = self
+            .admin_key
+            .as_ref()
+            .or(self.auth_token.as_ref())
+            .ok_or_else(|| {
+                StreamShareError::InvalidConfig("admin_delete needs an admin key".into())
+            })?;
+        let url = urls::admin_delete_url(&self.api_endpoint(), file_identifier)?;
+
+        // Not `self.request`, whose default authorization would clash with
+        // the admin key.
+        let request = self
+            .client
+            .request(Method::DELETE, &url)
+            .header(AUTHORIZATION, bearer_header(key)?);
+        let status = self.exchange(request).await?.status;
+        if status.is_success() {
+            Ok(DeleteOutcome::Deleted)
+        } else if status == StatusCode::NOT_FOUND {
+            Ok(DeleteOutcome::AlreadyAbsent)
+        } else {
+            Err(StreamShareError::DeleteFailed { status })
+        }
+    }
+
+    /// Checks whether `deletion_token` would delete the file, without deleting it.
+    ///
+    /// Sends a `HEAD` to the delete endpoint, `/api/delete/{id}/{token}`, which
+    /// the server answers like the `DELETE` but without removing anything.
+    /// Returns `false` for a rejected token (`401`/`403`) or a file that no
+    /// longer exists (`404`); other statuses are a
+    /// [`StreamShareError::TokenCheckFailed`].
+    pub async fn verify_token(
+        &self,
+        file_identifier: &str,
+        deletion_token: &str,
+    ) -> Result<bool, StreamShareError> {
+        let delete_url = self.deletion_url(file_identifier, deletion_token)?;
+
+        let res = self.send(self.request(Method::HEAD, &delete_url)).await?;
+        match res.status() {
+            status if status.is_success() => Ok(true),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => Ok(false),
+            status => Err(StreamShareError::TokenCheckFailed { status }),
+        }
+    }
+
+    /// A `curl` command making the same request as
+    /// [`download`](Self::download), for telling client bugs from server bugs.
+    ///
+    /// Credentials are replaced with `<redacted>` unless `include_secrets` is
+    /// set.
+    pub fn curl_for_download(
+        &self,
+        file_identifier: &str,
+        include_secrets: bool,
+    ) -> Result<String, StreamShareError> {
+        let url = self.download_url(file_identifier)?;
+        Ok(self.curl(&["-L", "-OJ"], &url, include_secrets))
+    }
+
+    /// A `curl` command making the same request as [`delete`](Self::delete).
+    /// Like the auth headers, the deletion token is replaced with `REDACTED`
+    /// unless `include_secrets` is set.
+    pub fn curl_for_delete(
+        &self,
+        file_identifier: &str,
+        deletion_token: &str,
+        include_secrets: bool,
+    ) -> Result<String, StreamShareError> {
+        // Stays readable in the URL, where `<redacted>` would be encoded.
+        let token = if include_secrets {
+            deletion_token
+        } else {
+            "REDACTED"
+        };
+        let url = self.deletion_url(file_identifier, token)?;
+        Ok(self.curl(&["-X", "DELETE"], &url, include_secrets))
+    }
+
+    fn curl(&self, args: &[&str], url: &str, include_secrets: bool) -> String {
+        let authorization = match (&self.auth_token, &self.basic_auth) {
+            (Some(token), _) if include_secrets => Some(format!("Bearer {}", token)),
+            (Some(_), _) => Some("Bearer <redacted>".to_string()),
+            (None, Some(basic)) if include_secrets => basic.to_str().ok().map(str::to_string),
+            (None, Some(_)) => Some("Basic <redacted>".to_string()),
+            (None, None) => None,
+        };
+
+        let mut command = String::from("curl");
+        for arg in args {
+            command.push(' ');
+            command.push_str(arg);
+        }
+        if let Some(authorization) = authorization {
+            command.push_str(" -H ");
+            command.push_str(&shell_quote(&format!("Authorization: {}", authorization)));
+        }
+        command.push(' ');
+        command.push_str(&shell_quote(url));
+        command
+    }
+
+    /// Fetches metadata for a file with a `HEAD` request, without downloading it.
+    pub async fn info(&self, file_identifier: &str) -> Result<FileInfo, StreamShareError> {
+        self.info_with_params(file_identifier, &[]).await
+    }
+
+    /// Checks whether a file can still be downloaded, with a `HEAD` request.
+    ///
+    /// Returns `false` only for a `404`. Any other non-success status is a
+    /// [`StreamShareError::ExistenceCheckFailed`], so an outage isn't mistaken
+    /// for a deleted file.
+    pub async fn exists(&self, file_identifier: &str) -> Result<bool, StreamShareError> {
+        let download_url = self.download_url(file_identifier)?;
+
+        let res = self.send(self.request(Method::HEAD, &download_url)).await?;
+        match res.status() {
+            status if status.is_success() => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            status => Err(StreamShareError::ExistenceCheckFailed { status }),
+        }
+    }
+
+    async fn info_with_params(
+        &self,
+        file_identifier: &str,
+        params: &[(&str, &str)],
+    ) -> Result<FileInfo, StreamShareError> {
+        let res = self
+            .exchange(
+                self.request(Method::HEAD, &self.download_url(file_identifier)?)
+                    .query(params),
+            )
+            .await?;
+        if !res.status.is_success() {
+            return Err(StreamShareError::InfoFailed { status: res.status });
+        }
+        self.check_server_version(&res.headers)?;
+
+        let size = res
+            .headers
+            .get("content-length")
+            .and_then(|header| header.to_str().ok())
+            .and_then(|value| value.parse().ok());
+
+        let accepts_ranges = res
+            .headers
+            .get("accept-ranges")
+            .and_then(|header| header.to_str().ok())
+            .is_some_and(|value| value.eq_ignore_ascii_case("bytes"));
+
+        Ok(FileInfo {
+            file_name: file_name_from_headers(&res.headers),
+            size,
+            accepts_ranges,
+        })
+    }
+
+    /// Uploads a file, then waits until its download URL answers, so a
+    /// recipient following the link right away doesn't get a `404`.
+    ///
+    /// Polls like [`wait_until_available`](Self::wait_until_available), every
+    /// [`availability_poll_interval`](StreamShareBuilder::availability_poll_interval),
+    /// and fails with [`StreamShareError::NotAvailable`] after `timeout`.
+    /// Returns the upload result together with the verified download URL.
+    pub async fn upload_and_wait_ready<F>(
+        &self,
+        file_path: &str,
+        callback: F,
+        timeout: Duration,
+    ) -> Result<(UploadResult, String), StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let result = self.upload(file_path, callback).await?;
+        self.wait_until_available(&result.file_identifier, timeout)
+            .await?;
+        let url = self.download_url(&result.file_identifier)?;
+        Ok((result, url))
+    }
+
+    /// Polls [`info`](Self::info) until the file is available or `timeout` elapses.
+    ///
+    /// The server may still be persisting a file right after an upload finishes,
+    /// so a download issued immediately afterwards can 404. Call this after
+    /// `upload` to make sure the file can actually be fetched.
+    pub async fn wait_until_available(
+        &self,
+        file_identifier: &str,
+        timeout: Duration,
+    ) -> Result<FileInfo, StreamShareError> {
+        let started = Instant::now();
+
+        loop {
+            match self.info(file_identifier).await {
+                Ok(info) => return Ok(info),
+                Err(_) if started.elapsed() >= timeout => {
+                    return Err(StreamShareError::NotAvailable {
+                        file_identifier: file_identifier.to_string(),
+                        timeout,
+                    });
+                }
+                Err(_) => tokio::time::sleep(self.availability_poll_interval).await,
+            }
+        }
+    }
+
+    /// Downloads a file to `download_path`.
+    ///
+    /// `download_path` may be a directory (the server's filename is used), a
+    /// file path, or empty for the current directory. It may also contain the
+    /// placeholders `{name}` (the server's filename) and `{id}` (the file
+    /// identifier), e.g. `~/Downloads/{id}-{name}`, which are substituted
+    /// before the path is resolved. A leading `~` is expanded to the home
+    /// directory unless disabled with
+    /// [`expand_tilde`](StreamShareBuilder::expand_tilde).
+    pub async fn download(
+        &self,
+        file_identifier: &str,
+        download_path: &str,
+        replace: bool,
+    ) -> Result<(), StreamShareError> {
+        self.download_with_progress(file_identifier, download_path, replace, |_, _| {})
+            .await
+    }
+
+    /// Like [`download`](Self::download), reporting `(written, total)` as the
+    /// body arrives.
+    ///
+    /// When the server doesn't send a `Content-Length` the total is `0` and
+    /// the size of the written file isn't verified.
+    pub async fn download_with_progress<F>(
+        &self,
+        file_identifier: &str,
+        download_path: &str,
+        replace: bool,
+        callback: F,
+    ) -> Result<(), StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        self.download_with_query(
+            file_identifier,
+            &[],
+            download_path,
+            LocalName::Server,
+            replace,
+            callback,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Like [`download`](Self::download), but fails with
+    /// [`StreamShareError::UnexpectedFilename`] before writing anything if
+    /// the server's filename isn't exactly `expected_name`, e.g. because an
+    /// identifier was reused for other content. A server sending no name
+    /// fails too. `None` skips the check.
+    pub async fn download_expecting(
+        &self,
+        file_identifier: &str,
+        download_path: &str,
+        expected_name: Option<&str>,
+        replace: bool,
+    ) -> Result<(), StreamShareError> {
+        let local_name = match expected_name {
+            Some(expected_name) => LocalName::Expect(expected_name),
+            None => LocalName::Server,
+        };
+        self.download_with_query(
+            file_identifier,
+            &[],
+            download_path,
+            local_name,
+            replace,
+            |_, _| {},
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Downloads a file like [`download`](Self::download), then deletes it from
+    /// the server, for one-time transfers.
+    ///
+    /// The file is only deleted once it has been fully written and passed
+    /// the same size and [`verify_on_disk`](StreamShareBuilder::verify_on_disk)
+    /// checks as any download; a failed or partial download leaves it on the
+    /// server. If the deletion itself fails, the downloaded file is kept and
+    /// the error is returned.
+    pub async fn download_and_delete(
+        &self,
+        file_identifier: &str,
+        deletion_token: &str,
+        download_path: &str,
+        replace: bool,
+    ) -> Result<DeleteOutcome, StreamShareError> {
+        self.download(file_identifier, download_path, replace)
+            .await?;
+        self.delete(file_identifier, deletion_token).await
+    }
+
+    /// Like [`download`](Self::download), but appends `params` to the download
+    /// URL as an encoded query string, for servers that accept options such as
+    /// `inline=1` or `password=...` there.
+    pub async fn download_with_params(
+        &self,
+        file_identifier: &str,
+        params: &[(&str, &str)],
+        download_path: &str,
+        replace: bool,
+    ) -> Result<(), StreamShareError> {
+        self.download_with_query(
+            file_identifier,
+            params,
+            download_path,
+            LocalName::Server,
+            replace,
+            |_, _| {},
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Downloads a file into `dir` as `file_name`, whatever name the server
+    /// sends, for scripts that expect a known name.
+    ///
+    /// `file_name` must be a plain name without path separators, and `dir` an
+    /// existing directory, or empty for the current one. With
+    /// [`auto_decompress`](StreamShareBuilder::auto_decompress) a `.gz` file
+    /// is still decompressed, into `file_name`.
+    pub async fn download_as(
+        &self,
+        file_identifier: &str,
+        dir: &str,
+        file_name: &str,
+        replace: bool,
+    ) -> Result<(), StreamShareError> {
+        if file_name.is_empty()
+            || matches!(file_name, "." | "..")
+            || file_name.contains(['/', '\\'])
+        {
+            return Err(StreamShareError::InvalidPath(format!(
+                "Not a plain file name: {}",
+                file_name
+            )));
+        }
+        // Resolved like a `{name}` placeholder, so `dir` gets the usual
+        // checks and `~` expansion.
+        let download_path = if dir.is_empty() {
+            "{name}".to_string()
+        } else {
+            format!("{}/{{name}}", dir.trim_end_matches(['/', '\\']))
+        };
+        self.download_with_query(
+            file_identifier,
+            &[],
+            &download_path,
+            LocalName::Rename(file_name),
+            replace,
+            |_, _| {},
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Downloads several files, running up to `concurrency` downloads at once,
+    /// e.g. to restore a backup of many shares.
+    ///
+    /// Each item is a file identifier and a `download_path` as taken by
+    /// [`download`](Self::download). The results are in the order of `items`,
+    /// each the path the file was written to or why it failed; one failure
+    /// doesn't stop the others, unless the
+    /// [`batch_retry_budget`](StreamShareBuilder::batch_retry_budget) runs
+    /// out.
+    ///
+    /// `callback` gets the index of the item, its `(written, total)` like
+    /// [`download_with_progress`](Self::download_with_progress), and the bytes
+    /// written across all downloads so far.
+    pub async fn download_many<F>(
+        &self,
+        items: &[(String, String)],
+        replace: bool,
+        concurrency: usize,
+        callback: F,
+    ) -> Vec<Result<PathBuf, StreamShareError>>
+    where
+        F: FnMut(usize, u64, u64, u64),
+    {
+        let progress = Mutex::new((0u64, callback));
+        self.batch(items.len(), concurrency, |index| {
+            let (file_identifier, download_path) = &items[index];
+            let progress = &progress;
+            let mut written_before = 0;
+            async move {
+                self.download_with_query(
+                    file_identifier,
+                    &[],
+                    download_path,
+                    LocalName::Server,
+                    replace,
+                    |written, total| {
+                        let mut progress = progress.lock().unwrap();
+                        let (overall, callback) = &mut *progress;
+                        *overall += written.saturating_sub(written_before);
+                        written_before = written;
+                        callback(index, written, total, *overall);
+                    },
+                )
+                .await
+            }
+        })
+        .await
+    }
+
+    /// Runs `run` for items `0..len`, up to `concurrency` at once, and
+    /// returns the results in item order.
+    ///
+    /// With a [`batch_retry_budget`](StreamShareBuilder::batch_retry_budget)
+    /// every retry of every item takes from it. Once a retry is refused, that
+    /// request fails with [`StreamShareError::RetryBudgetExhausted`] and so
+    /// does every item that hasn't started yet, without sending anything.
+    async fn batch<T, F, Fut>(
+        &self,
+        len: usize,
+        concurrency: usize,
+        mut run: F,
+    ) -> Vec<Result<T, StreamShareError>>
+    where
+        F: FnMut(usize) -> Fut,
+        Fut: Future<Output = Result<T, StreamShareError>>,
+    {
+        let budget = self.batch_retry_budget.map(|retries| {
+            Arc::new(RetryBudget {
+                remaining: AtomicU32::new(retries),
+                exhausted: AtomicBool::new(false),
+            })
+        });
+
+        let items = (0..len).map(|index| {
+            let budget = budget.clone();
+            let item = run(index);
+            async move {
+                if budget.is_some_and(|budget| budget.exhausted.load(Ordering::Relaxed)) {
+                    return (index, Err(StreamShareError::RetryBudgetExhausted));
+                }
+                (index, item.await)
+            }
+        });
+        let all = async {
+            let mut results: Vec<_> = (0..len).map(|_| None).collect();
+            let mut finished = futures::stream::iter(items).buffer_unordered(concurrency.max(1));
+            while let Some((index, result)) = finished.next().await {
+                results[index] = Some(result);
+            }
+            results
+                .into_iter()
+                .map(|result| result.expect("every item finishes"))
+                .collect()
+        };
+        match budget.clone() {
+            Some(budget) => BATCH_RETRIES.scope(budget, all).await,
+            None => all.await,
+        }
+    }
+
+    /// Downloads with `params` in the query string. A given `file_name`
+    /// replaces the one from the server.
+    async fn download_with_query<F>(
+        &self,
+        file_identifier: &str,
+        params: &[(&str, &str)],
+        download_path: &str,
+        local_name: LocalName<'_>,
+        replace: bool,
+        callback: F,
+    ) -> Result<PathBuf, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let meter = Meter::start(self, TransferKind::Download);
+        let file_name = match local_name {
+            LocalName::Rename(file_name) => Some(file_name),
+            _ => None,
+        };
+        let check_name = |actual: Option<&str>| match local_name {
+            LocalName::Expect(expected) if actual != Some(expected) => {
+                Err(StreamShareError::UnexpectedFilename {
+                    expected: expected.to_string(),
+                    actual: actual.map(str::to_string),
+                })
+            }
+            _ => Ok(()),
+        };
+
+        let (checked_path, checked_size) = if self.download_precheck {
+            let info = self.info_with_params(file_identifier, params).await?;
+            check_name(info.file_name.as_deref())?;
+            let server_name = info
+                .file_name
+                .unwrap_or_else(|| self.fallback_name(file_identifier));
+            let (server_name, decompress) = self.decompressed_name(server_name);
+            let file_name = file_name.unwrap_or(&server_name);
+            let file_path =
+                self.resolve_download_path(download_path, file_name, file_identifier, replace)?;
+            (Some((file_path, decompress)), info.size)
+        } else {
+            (None, None)
+        };
+
+        let res = self
+            .send(
+                self.request(Method::GET, &self.download_url(file_identifier)?)
+                    .query(params),
+            )
+            .await?
+            .error_for_status()?;
+
+        let (file_path, decompress) = match checked_path {
+            Some(checked) => checked,
+            None => {
+                let server_name = file_name_from_headers(res.headers());
+                check_name(server_name.as_deref())?;
+                let server_name =
+                    server_name.unwrap_or_else(|| self.fallback_name(file_identifier));
+                let (server_name, decompress) = self.decompressed_name(server_name);
+                let file_path = self.resolve_download_path(
+                    download_path,
+                    file_name.unwrap_or(&server_name),
+                    file_identifier,
+                    replace,
+                )?;
+                (file_path, decompress)
+            }
+        };
+
+        write_download(
+            self,
+            res,
+            &file_path,
+            decompress,
+            checked_size,
+            meter,
+            callback,
+        )
+        .await?;
+        Ok(file_path)
+    }
+
+    /// Downloads from a complete pre-signed URL, e.g. a time-limited link or
+    /// one for a CDN in front of the server, instead of building
+    /// `/download/{id}`. The query string, signature included, is sent as is.
+    ///
+    /// `download_path` is resolved like for [`download`](Self::download), with
+    /// the URL's last path segment standing in for the file identifier. The URL
+    /// must point at the configured server unless
+    /// [`allow_foreign_signed_urls`](StreamShareBuilder::allow_foreign_signed_urls)
+    /// is set, otherwise this fails with [`StreamShareError::ForeignHost`]. Auth
+    /// credentials are only sent to the configured server.
+    pub async fn download_signed(
+        &self,
+        signed_url: &str,
+        download_path: &str,
+        replace: bool,
+    ) -> Result<(), StreamShareError> {
+        let url = reqwest::Url::parse(signed_url).map_err(|e| {
+            StreamShareError::InvalidConfig(format!("Invalid signed URL {}: {}", signed_url, e))
+        })?;
+        let own_host = self.is_own_host(&url);
+        if !own_host && !self.allow_foreign_signed_urls {
+            return Err(StreamShareError::ForeignHost {
+                url: signed_url.to_string(),
+            });
+        }
+        let file_identifier = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|segment| !segment.is_empty())
+            .unwrap_or("download")
+            .to_string();
+        self.download_from(url, own_host, &file_identifier, download_path, replace)
+            .await
+    }
+
+    /// Like [`download`](Self::download), but over `scheme` instead of the
+    /// client's, e.g. plain `http` for shares served from another origin
+    /// than the API while uploads stay on `https`. Auth credentials are sent
+    /// over it too. `download_path` is resolved as for
+    /// [`download`](Self::download).
+    pub async fn download_with_scheme(
+        &self,
+        file_identifier: &str,
+        scheme: Scheme,
+        download_path: &str,
+        replace: bool,
+    ) -> Result<(), StreamShareError> {
+        let url = self.download_url_with_scheme(file_identifier, scheme)?;
+        let url = reqwest::Url::parse(&url).map_err(|e| {
+            StreamShareError::InvalidConfig(format!("Invalid download URL {}: {}", url, e))
+        })?;
+        self.download_from(url, true, file_identifier, download_path, replace)
+            .await
+    }
+
+    /// Downloads `url`, with auth only if it's on `own_host`.
+    async fn download_from(
+        &self,
+        url: reqwest::Url,
+        own_host: bool,
+        file_identifier: &str,
+        download_path: &str,
+        replace: bool,
+    ) -> Result<(), StreamShareError> {
+        let meter = Meter::start(self, TransferKind::Download);
+        let request = if own_host {
+            self.request(Method::GET, url.as_str())
+        } else {
+            self.client.get(url)
+        };
+        let res = self.send(request).await?.error_for_status()?;
+
+        let server_name = file_name_from_headers(res.headers())
+            .unwrap_or_else(|| self.fallback_name(file_identifier));
+        let (server_name, decompress) = self.decompressed_name(server_name);
+        let file_path =
+            self.resolve_download_path(download_path, &server_name, file_identifier, replace)?;
+        write_download(self, res, &file_path, decompress, None, meter, |_, _| {}).await
+    }
+
+    /// Whether `url` has the host and port of the configured server.
+    fn is_own_host(&self, url: &reqwest::Url) -> bool {
+        let Ok(own) = reqwest::Url::parse(&self.http_url("/")) else {
+            return false;
+        };
+        url.host_str()
+            .zip(own.host_str())
+            .is_some_and(|(host, own)| host.eq_ignore_ascii_case(own))
+            && url.port_or_known_default() == own.port_or_known_default()
+    }
+
+    /// Strips `.gz` from `file_name` if the download should be decompressed.
+    fn decompressed_name(&self, file_name: String) -> (String, bool) {
+        #[cfg(feature = "compression")]
+        if self.auto_decompress {
+            if let Some(stem) = file_name
+                .strip_suffix(".gz")
+                .filter(|stem| !stem.is_empty())
+            {
+                return (stem.to_string(), true);
+            }
+        }
+        (file_name, false)
+    }
+
+    /// Streams a file's content into `writer` instead of a path, returning the
+    /// number of bytes written.
+    pub async fn download_to<W>(
+        &self,
+        file_identifier: &str,
+        writer: &mut W,
+    ) -> Result<u64, StreamShareError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut reader = self.download_stream(file_identifier).await?;
+        let written = tokio::io::copy(&mut reader, writer).await?;
+        writer.flush().await?;
+        Ok(written)
+    }
+
+    /// Streams a file's content into a bounded channel from a background task.
+    ///
+    /// At most `buffer` chunks are held at a time, so a slow consumer slows
+    /// the download down instead of filling memory. A failure while streaming
+    /// arrives as a final `Err`; the channel closes after the last chunk.
+    pub async fn download_channel(
+        &self,
+        file_identifier: &str,
+        buffer: usize,
+    ) -> Result<mpsc::Receiver<Result<Bytes, StreamShareError>>, StreamShareError> {
+        let res = self
+            .send(self.request(Method::GET, &self.download_url(file_identifier)?))
+            .await?
+            .error_for_status()?;
+
+        let (sender, receiver) = mpsc::channel(buffer);
+        tokio::spawn(async move {
+            let mut stream = res.bytes_stream();
+            while let Some(chunk) = stream.next().await {
+                let failed = chunk.is_err();
+                if sender.send(chunk.map_err(Into::into)).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(receiver)
+    }
+
+    /// Returns a file's content as an [`AsyncRead`], leaving buffering and
+    /// consumption entirely to the caller.
+    pub async fn download_stream(
+        &self,
+        file_identifier: &str,
+    ) -> Result<impl AsyncRead + Unpin, StreamShareError> {
+        let res = self
+            .send(self.request(Method::GET, &self.download_url(file_identifier)?))
+            .await?
+            .error_for_status()?;
+
+        Ok(StreamReader::new(
+            res.bytes_stream().map_err(std::io::Error::other),
+        ))
+    }
+
+    /// Downloads a file only if it changed since the version described by
+    /// `validator`.
+    ///
+    /// Sends `If-None-Match` for an ETag or `If-Modified-Since` for a
+    /// `Last-Modified` date. On `304 Not Modified` nothing is written and
+    /// [`DownloadOutcome::NotModified`] is returned. Otherwise the file is
+    /// downloaded like [`download`](Self::download) and the server's
+    /// validators are returned so they can be passed back next time.
+    pub async fn download_conditional(
+        &self,
+        file_identifier: &str,
+        download_path: &str,
+        replace: bool,
+        validator: Option<&Validator>,
+    ) -> Result<DownloadOutcome, StreamShareError> {
+        let mut meter = Meter::start(self, TransferKind::Download);
+        let mut request = self.request(Method::GET, &self.download_url(file_identifier)?);
+        request = match validator {
+            Some(Validator::ETag(etag)) => {
+                request.header("if-none-match", quote_etag(etag).as_ref())
+            }
+            Some(Validator::LastModified(date)) => request.header("if-modified-since", date),
+            None => request,
+        };
+
+        let res = self.send(request).await?;
+        if res.status() == StatusCode::NOT_MODIFIED {
+            meter.success = true;
+            return Ok(DownloadOutcome::NotModified);
+        }
+        let res = res.error_for_status()?;
+
+        let header = |name: &str| {
+            res.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header("etag");
+        let last_modified = header("last-modified");
+
+        // Servers that ignore `If-None-Match` still send the current ETag,
+        // so an unchanged file is caught here too.
+        if let (Some(Validator::ETag(known)), Some(current)) = (validator, &etag) {
+            if etag_matches(known, current) {
+                meter.success = true;
+                return Ok(DownloadOutcome::NotModified);
+            }
+        }
+
+        let file_name = file_name_from_headers(res.headers())
+            .unwrap_or_else(|| self.fallback_name(file_identifier));
+        let file_path =
+            self.resolve_download_path(download_path, &file_name, file_identifier, replace)?;
+
+        write_download(self, res, &file_path, false, None, meter, |_, _| {}).await?;
+
+        Ok(DownloadOutcome::Downloaded {
+            path: file_path,
+            etag,
+            last_modified,
+        })
+    }
+
+    /// Downloads a file using up to `segments` concurrent range requests.
+    ///
+    /// Each segment fetches a disjoint byte range and writes it at its offset
+    /// in a preallocated file, so the result is byte-identical to a sequential
+    /// download whatever order the segments finish in. A segment whose
+    /// `Content-Range` or length doesn't match what it asked for fails the
+    /// download instead of writing outside its range. If the server doesn't
+    /// advertise range support (`Accept-Ranges: bytes`) or the size is
+    /// unknown, this falls back to a single stream. The callback receives the bytes written across all
+    /// segments and the total size.
+    pub async fn download_parallel<F>(
+        &self,
+        file_identifier: &str,
+        download_path: &str,
+        replace: bool,
+        segments: usize,
+        mut callback: F,
+    ) -> Result<(), StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let mut meter = Meter::start(self, TransferKind::Download);
+        let url = self.download_url(file_identifier)?;
+        let info = self.info(file_identifier).await?;
+        let file_name = info
+            .file_name
+            .unwrap_or_else(|| self.fallback_name(file_identifier));
+        let file_path =
+            self.resolve_download_path(download_path, &file_name, file_identifier, replace)?;
+
+        let size = match info.size {
+            Some(size) if info.accepts_ranges && segments > 1 && size > 0 => size,
+            _ => {
+                let res = self
+                    .send(self.request(Method::GET, &url))
+                    .await?
+                    .error_for_status()?;
+                return write_download(self, res, &file_path, false, None, meter, callback).await;
+            }
+        };
+
+        let existed = file_path.exists();
+        let file = File::create(&file_path).await?;
+        file.set_len(size).await?;
+        drop(file);
+
+        let progress = Mutex::new((&mut meter, &mut callback));
+
+        let result =
+            futures::future::try_join_all(segment_ranges(size, segments).map(|(start, end)| {
+                self.download_range(&url, &file_path, start, end, |n| {
+                    let mut progress = progress.lock().unwrap();
+                    progress.0.bytes += n;
+                    progress.0.chunks += 1;
+                    let written = progress.0.bytes;
+                    (progress.1)(written, size);
+                })
+            }))
+            .await
+            .map(|_| ());
+        meter.success = result.is_ok();
+        drop(meter);
+
+        if result.is_err() && !existed {
+            let _ = fs::remove_file(&file_path).await;
+        }
+
+        result
+    }
+
+    /// Streams a file as `(offset, bytes)` pieces fetched over up to
+    /// `segments` concurrent range requests, for callers that place the data
+    /// themselves, e.g. into a memory-mapped file.
+    ///
+    /// Pieces of different segments arrive interleaved; each carries its
+    /// absolute offset in the file. Ranges need a server that advertises
+    /// `Accept-Ranges: bytes` and a known size. Otherwise the whole file comes
+    /// from a single request, still with offsets. A range answered with
+    /// anything but `206 Partial Content` fails with
+    /// [`StreamShareError::RangeNotSupported`]. Each range request counts
+    /// against [`max_connections_per_host`](StreamShareBuilder::max_connections_per_host)
+    /// and waits out `503` responses like any other download.
+    pub async fn download_segments(
+        &self,
+        file_identifier: &str,
+        segments: usize,
+    ) -> Result<
+        impl futures::Stream<Item = Result<(u64, Bytes), StreamShareError>> + '_,
+        StreamShareError,
+    > {
+        let url = self.download_url(file_identifier)?;
+        let info = self.info(file_identifier).await?;
+
+        let ranges = match info.size {
+            Some(size) if info.accepts_ranges && segments > 1 && size > 0 => {
+                segment_ranges(size, segments).map(Some).collect()
+            }
+            _ => vec![None],
+        };
+
+        let streams = ranges.into_iter().map(|range| {
+            let mut request = self.request(Method::GET, &url);
+            if let Some((start, end)) = range {
+                request = request.header("range", format!("bytes={}-{}", start, end));
+            }
+            let response = async move {
+                let res = self.send(request).await?.error_for_status()?;
+                if let Some((start, end)) = range {
+                    if res.status() != StatusCode::PARTIAL_CONTENT {
+                        return Err(StreamShareError::RangeNotSupported {
+                            status: res.status(),
+                        });
+                    }
+                    check_content_range(res.headers(), start, end)?;
+                }
+                let mut offset = range.map_or(0, |(start, _)| start);
+                let limit = range.map(|(_, end)| end + 1);
+                Ok(res.bytes_stream().map(move |chunk| {
+                    let chunk = chunk?;
+                    let at = offset;
+                    offset += chunk.len() as u64;
+                    // A segment must never reach into the next one.
+                    if let Some(limit) = limit.filter(|&limit| offset > limit) {
+                        let start = range.map_or(0, |(start, _)| start);
+                        return Err(StreamShareError::SizeMismatch {
+                            expected: limit - start,
+                            actual: offset - start,
+                        });
+                    }
+                    Ok((at, chunk))
+                }))
+            };
+            Box::pin(futures::stream::once(response).try_flatten())
+        });
+        Ok(futures::stream::select_all(streams))
+    }
+
+    /// Downloads bytes `range` of a file and writes them into `file` starting
+    /// at `offset`, for assembling a file from several sources.
+    ///
+    /// The data lands at `offset` whatever the file's current position; the
+    /// position afterwards is just past the last byte written. The file is
+    /// neither created nor truncated nor grown up front, so preallocate it
+    /// with [`File::set_len`] if needed. Like the segments of
+    /// [`download_parallel`](Self::download_parallel), the server has to answer
+    /// with `206 Partial Content` for exactly `range`. The callback receives
+    /// the bytes written and the length of `range`.
+    pub async fn download_into_at<F>(
+        &self,
+        file_identifier: &str,
+        file: &mut File,
+        offset: u64,
+        range: std::ops::Range<u64>,
+        mut callback: F,
+    ) -> Result<(), StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        if range.is_empty() {
+            return Ok(());
+        }
+        let mut meter = Meter::start(self, TransferKind::Download);
+        let url = self.download_url(file_identifier)?;
+        let total = range.end - range.start;
+        let result = self
+            .write_range(&url, file, offset, range.start, range.end - 1, |n| {
+                meter.bytes += n;
+                meter.chunks += 1;
+                callback(meter.bytes, total);
+            })
+            .await;
+        meter.success = result.is_ok();
+        result
+    }
+
+    /// Fetches bytes `start..=end` and writes them at the same offset of `file_path`.
+    async fn download_range<F>(
+        &self,
+        url: &str,
+        file_path: &Path,
+        start: u64,
+        end: u64,
+        on_written: F,
+    ) -> Result<(), StreamShareError>
+    where
+        F: FnMut(u64),
+    {
+        let mut file = fs::OpenOptions::new().write(true).open(file_path).await?;
+        self.write_range(url, &mut file, start, start, end, on_written)
+            .await
+    }
+
+    /// Fetches bytes `start..=end` and writes them into `file` at `at`.
+    async fn write_range<F>(
+        &self,
+        url: &str,
+        file: &mut File,
+        at: u64,
+        start: u64,
+        end: u64,
+        mut on_written: F,
+    ) -> Result<(), StreamShareError>
+    where
+        F: FnMut(u64),
+    {
+        let request = self
+            .request(Method::GET, url)
+            .header("range", format!("bytes={}-{}", start, end));
+        let res = self.send(request).await?.error_for_status()?;
+
+        if res.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(StreamShareError::RangeNotSupported {
+                status: res.status(),
+            });
+        }
+        check_content_range(res.headers(), start, end)?;
+
+        file.seek(SeekFrom::Start(at)).await?;
+
+        let expected = end - start + 1;
+        let mut written = 0;
+        let mut stream = res.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            if written + chunk.len() as u64 > expected {
+                return Err(StreamShareError::SizeMismatch {
+                    expected,
+                    actual: written + chunk.len() as u64,
+                });
+            }
+            file.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+            on_written(chunk.len() as u64);
+        }
+
+        if written != expected {
+            return Err(StreamShareError::SizeMismatch {
+                expected,
+                actual: written,
+            });
+        }
+
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Name for a download the server sent no file name for.
+    fn fallback_name(&self, file_identifier: &str) -> String {
+        match &self.fallback_extension {
+            Some(extension) => format!("{}.{}", file_identifier, extension),
+            None => file_identifier.to_string(),
+        }
+    }
+
+    /// Decides where a download of `file_name` ends up, applying the same
+    /// placeholder, `~` and directory handling as [`download`](Self::download)
+    /// and the same checks for replacing, symlinks and writability.
+    ///
+    /// The destination itself isn't created, so a CLI can validate and show
+    /// it before starting the transfer. Checking writability does briefly
+    /// create and remove a hidden probe file in the target directory.
+    pub fn resolve_download_path(
+        &self,
+        download_path: &str,
+        file_name: &str,
+        file_identifier: &str,
+        replace: bool,
+    ) -> Result<PathBuf, StreamShareError> {
+        let file_name = &*fit_file_name(file_name);
+        let download_path = download_path
+            .replace("{name}", file_name)
+            .replace("{id}", file_identifier);
+        let expanded_path = if self.expand_tilde {
+            shellexpand::tilde(&download_path)
+        } else {
+            download_path.as_str().into()
+        };
+        let path = Path::new(&*expanded_path);
+
+        let file_path = if path.as_os_str().is_empty() {
+            PathBuf::from(file_name)
+        } else if path.exists() {
+            if path.is_dir() {
+                path.join(file_name)
+            } else if path.is_file() {
+                path.to_path_buf()
+            } else {
+                return Err(StreamShareError::InvalidPath(format!(
+                    "Path exists but is neither a file nor a directory: {}",
+                    path.display()
+                )));
+            }
+        } else if let Some(parent) = path.parent() {
+            if parent.exists() && parent.is_dir() {
+                path.to_path_buf()
+            } else {
+                return Err(StreamShareError::InvalidPath(format!(
+                    "Parent directory does not exist or is not a directory: {}",
+                    parent.display()
+                )));
+            }
+        } else {
+            path.to_path_buf()
+        };
+
+        let mut file_path = file_path;
+        loop {
+            if !self.follow_symlinks {
+                self.check_within_root(&file_path)?;
+            }
+            if replace || !file_path.exists() {
+                break;
+            }
+            let Some(on_conflict) = &self.on_conflict else {
+                return Err(StreamShareError::FileExists { path: file_path });
+            };
+            match on_conflict(&file_path) {
+                ConflictDecision::Replace => break,
+                ConflictDecision::Skip => {
+                    return Err(StreamShareError::Skipped { path: file_path });
+                }
+                ConflictDecision::Rename(path) => file_path = path,
+            }
+        }
+        check_writable(&file_path)?;
+
+        Ok(file_path)
+    }
+
+    /// Makes sure `file_path` is not a symlink and that its real parent directory
+    /// lies within the download root (or the current directory).
+    fn check_within_root(&self, file_path: &Path) -> Result<(), StreamShareError> {
+        let root = match &self.download_root {
+            Some(root) => root.clone(),
+            None => std::env::current_dir()?,
+        };
+        let root = std::fs::canonicalize(&root)?;
+
+        let escapes = || StreamShareError::PathEscapesRoot {
+            path: file_path.to_path_buf(),
+            root: root.clone(),
+        };
+
+        if std::fs::symlink_metadata(file_path)
+            .is_ok_and(|metadata| metadata.file_type().is_symlink())
+        {
+            return Err(escapes());
+        }
+
+        let parent = match file_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let parent = std::fs::canonicalize(parent)?;
+
+        if parent.starts_with(&root) {
+            Ok(())
+        } else {
+            Err(escapes())
+        }
+    }
+}
+
+/// Streams the body of `res` into `file_path`, gunzipping it first if
+/// `decompress`, and reports the outcome through `meter`.
+///
+/// A plain download fails as soon as more bytes arrive than its
+/// `Content-Length`, or `known_size` if the response has none, and with
+/// `verify_on_disk` the written file is read back and compared against the
+/// received data. If the transfer fails, a file that didn't exist before is
+/// removed again.
+async fn write_download<F>(
+    client: &StreamShare,
+    res: Response,
+    file_path: &Path,
+    decompress: bool,
+    known_size: Option<u64>,
+    mut meter: Meter<'_>,
+    mut callback: F,
+) -> Result<(), StreamShareError>
+where
+    F: FnMut(u64, u64),
+{
+    let tally = |written, total| {
+        meter.bytes = written;
+        meter.chunks += 1;
+        callback(written, total);
+    };
+    #[cfg(feature = "compression")]
+    let result = if decompress {
+        write_decompressed(client, res, file_path, tally).await
+    } else {
+        write_to_path(client, res, file_path, known_size, tally).await
+    };
+    #[cfg(not(feature = "compression"))]
+    let result = {
+        let _ = decompress;
+        write_to_path(client, res, file_path, known_size, tally).await
+    };
+    meter.success = result.is_ok();
+    result
+}
+
+async fn write_to_path<F>(
+    client: &StreamShare,
+    res: Response,
+    file_path: &Path,
+    known_size: Option<u64>,
+    mut callback: F,
+) -> Result<(), StreamShareError>
+where
+    F: FnMut(u64, u64),
+{
+    if let Some(parent) = file_path.parent() {
+        if !parent.as_os_str().is_empty() && !parent.exists() {
+            fs::create_dir_all(parent).await?;
+        }
+    }
+
+    let expected = res.content_length().or(known_size);
+    if let Some(expected) = expected {
+        check_space(file_path, expected)?;
+    }
+    #[cfg(feature = "xattr")]
+    let content_type = res
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let existed = file_path.exists();
+    let mut file =
+        BufWriter::with_capacity(client.write_buffer_size, File::create(file_path).await?);
+
+    let result: Result<(), StreamShareError> = async {
+        let total = expected.unwrap_or(0);
+        let mut written = 0;
+        let mut hasher = client
+            .verify_on_disk
+            .then(|| Hasher::new(client.hash_algorithm));
+        let mut stream = res.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            if let Some(expected) = expected {
+                let actual = written + chunk.len() as u64;
+                if actual > expected {
+                    return Err(StreamShareError::SizeMismatch { expected, actual });
+                }
+            }
+            file.write_all(&chunk).await?;
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&chunk);
+            }
+            written += chunk.len() as u64;
+            callback(written, total);
+        }
+        file.flush().await?;
+
+        if let Some(expected) = expected.filter(|&expected| expected != written) {
+            return Err(StreamShareError::SizeMismatch {
+                expected,
+                actual: written,
+            });
+        }
+
+        if client.sync_on_complete || hasher.is_some() {
+            file.get_ref().sync_all().await?;
+        }
+        if let Some(hasher) = hasher {
+            if Checksum::of_file(client.hash_algorithm, file_path).await? != hasher.finish() {
+                return Err(StreamShareError::DiskVerificationFailed {
+                    path: file_path.to_path_buf(),
+                });
+            }
+        }
+        Ok(())
+    }
+    .await
+    .map_err(|e| write_failed(e, file_path));
+    drop(file);
+
+    // Don't leave a truncated file behind, unless we overwrote one that was
+    // already there. A partial file on a full disk goes either way.
+    if (result.is_err() && !existed) || matches!(result, Err(StreamShareError::DiskFull { .. })) {
+        let _ = fs::remove_file(file_path).await;
+    }
+
+    #[cfg(feature = "xattr")]
+    if let (Ok(()), true, Some(content_type)) = (&result, client.set_type_xattr, content_type) {
+        set_type_xattr(file_path, &content_type);
+    }
+
+    result
+}
+
+/// Records `content_type` in the `user.mime_type` attribute of `file_path`,
+/// the name shared-mime-info looks for. Failures are ignored, as the
+/// download itself succeeded.
+#[cfg(feature = "xattr")]
+fn set_type_xattr(file_path: &Path, content_type: &str) {
+    #[cfg(unix)]
+    if xattr::SUPPORTED_PLATFORM {
+        let _ = xattr::set(file_path, "user.mime_type", content_type.as_bytes());
+    }
+    #[cfg(not(unix))]
+    let _ = (file_path, content_type);
+}
+
+/// Writes a gzip response to `file_path` decompressed, reporting compressed
+/// bytes as progress.
+#[cfg(feature = "compression")]
+async fn write_decompressed<F>(
+    client: &StreamShare,
+    res: Response,
+    file_path: &Path,
+    mut callback: F,
+) -> Result<(), StreamShareError>
+where
+    F: FnMut(u64, u64),
+{
+    if let Some(parent) = file_path.parent() {
+        if !parent.as_os_str().is_empty() && !parent.exists() {
+            fs::create_dir_all(parent).await?;
+        }
+    }
+
+    let total = res.content_length().unwrap_or(0);
+    let mut received = 0;
+    let mut stream = res
+        .bytes_stream()
+        .map_err(std::io::Error::other)
+        .inspect_ok(|chunk| {
+            received += chunk.len() as u64;
+            callback(received, total);
+        });
+
+    // Check the magic bytes before creating the file, so a mislabelled
+    // download doesn't leave garbage behind.
+    let mut head = Vec::new();
+    while head.len() < 2 {
+        match stream.next().await {
+            Some(chunk) => head.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    if !head.starts_with(&[0x1f, 0x8b]) {
+        return Err(StreamShareError::NotGzip {
+            path: file_path.to_path_buf(),
+        });
+    }
+
+    let compressed = futures::stream::iter([Ok(Bytes::from(head))]).chain(stream);
+    let mut decoder = GzipDecoder::new(StreamReader::new(compressed));
+
+    let existed = file_path.exists();
+    let mut file =
+        BufWriter::with_capacity(client.write_buffer_size, File::create(file_path).await?);
+    let result: Result<(), StreamShareError> = async {
+        tokio::io::copy(&mut decoder, &mut file).await?;
+        file.flush().await?;
+        if client.sync_on_complete {
+            file.get_ref().sync_all().await?;
+        }
+        Ok(())
+    }
+    .await
+    .map_err(|e| write_failed(e, file_path));
+    drop(file);
+
+    if (result.is_err() && !existed) || matches!(result, Err(StreamShareError::DiskFull { .. })) {
+        let _ = fs::remove_file(file_path).await;
+    }
+
+    result
+}
+
+/// Shortens a server-provided filename to the 255 bytes most filesystems
+/// allow per path component, keeping the extension.
+fn fit_file_name(file_name: &str) -> Cow<'_, str> {
+    if file_name.len() <= MAX_FILE_NAME_LEN {
+        return Cow::Borrowed(file_name);
+    }
+
+    let (stem, extension) = match file_name.rfind('.') {
+        Some(dot) if dot > 0 && file_name.len() - dot < MAX_FILE_NAME_LEN / 2 => {
+            file_name.split_at(dot)
+        }
+        _ => (file_name, ""),
+    };
+    let mut end = MAX_FILE_NAME_LEN - extension.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!("{}{}", &stem[..end], extension))
+}
+
+/// Creates and removes a probe file next to `file_path`, so read-only mounts
+/// and missing permissions are reported before anything is downloaded.
+fn check_writable(file_path: &Path) -> Result<(), StreamShareError> {
+    let dir = match file_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let probe = dir.join(format!(".streamshare-{}", uuid::Uuid::new_v4().simple()));
+
+    match std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+    {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            Ok(())
+        }
+        Err(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::ReadOnlyFilesystem
+            ) =>
+        {
+            Err(StreamShareError::DestinationNotWritable {
+                path: dir.to_path_buf(),
+            })
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn file_name_from_headers(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    headers
+        .get("content-disposition")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header_value| {
+            header_value.split(';').find_map(|part| {
+                let trimmed = part.trim();
+                if trimmed.starts_with("filename=") {
+                    // `filename=""` names nothing; joined onto a directory it
+                    // would point at the directory itself.
+                    Some(
+                        trimmed
+                            .trim_start_matches("filename=")
+                            .trim_matches('"')
+                            .to_string(),
+                    )
+                    .filter(|name| !name.trim().is_empty())
+                } else {
+                    None
+                }
+            })
+        })
+}
+
+/// Number of chunks an upload of `file_size` bytes is split into.
+///
+/// Empty files are uploaded without sending any chunk, so a size of zero
+/// yields zero chunks.
+///
+/// ```
+/// use streamshare::chunk_count;
+///
+/// assert_eq!(chunk_count(0, 1024), 0);
+/// assert_eq!(chunk_count(2048, 1024), 2);
+/// assert_eq!(chunk_count(2049, 1024), 3);
+/// ```
+pub fn chunk_count(file_size: u64, chunk_size: usize) -> u64 {
+    file_size.div_ceil(chunk_size as u64)
+}
+
+/// What an upload costs beyond the file's own bytes, see [`estimated_overhead`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverheadEstimate {
+    /// Binary frames sent, one per chunk.
+    pub frames: u64,
+    /// Times the client waits for an `ACK` before sending on, one per chunk.
+    pub ack_round_trips: u64,
+    /// Bytes of WebSocket frame headers for the chunks plus the `ACK` frames.
+    pub framing_bytes: u64,
+}
+
+/// Estimates the WebSocket overhead of uploading `file_size` bytes in chunks of
+/// `chunk_size`, e.g. to show why tiny chunks are slow: every chunk costs a
+/// round trip, however small it is.
+///
+/// Counts a masked binary frame per chunk and a text `ACK` frame back. The
+/// handshake, the close frames and TLS records are left out.
+///
+/// ```
+/// use streamshare::estimated_overhead;
+///
+/// let overhead = estimated_overhead(2048, 1024);
+/// assert_eq!(overhead.frames, 2);
+/// assert_eq!(overhead.ack_round_trips, 2);
+/// // 8 header bytes per 1 KiB frame and 5 bytes per `ACK`.
+/// assert_eq!(overhead.framing_bytes, 26);
+/// ```
+pub fn estimated_overhead(file_size: u64, chunk_size: usize) -> OverheadEstimate {
+    /// Base header, extended length and mask key of a client frame.
+    fn header_len(payload: u64) -> u64 {
+        let extended = match payload {
+            0..=125 => 0,
+            126..=65535 => 2,
+            _ => 8,
+        };
+        2 + extended + 4
+    }
+    /// An unmasked text frame carrying `ACK`.
+    const ACK_FRAME_LEN: u64 = 2 + 3;
+
+    let frames = chunk_count(file_size, chunk_size);
+    let full = file_size / chunk_size as u64;
+    let rest = file_size % chunk_size as u64;
+    let mut framing_bytes = full * header_len(chunk_size as u64) + frames * ACK_FRAME_LEN;
+    if rest > 0 {
+        framing_bytes += header_len(rest);
+    }
+    OverheadEstimate {
+        frames,
+        ack_round_trips: frames,
+        framing_bytes,
+    }
+}
+
+/// Guesses the MIME type of a local file from its extension, falling back to
+/// sniffing its first bytes when the extension is missing or unknown.
+#[cfg(feature = "mime")]
+pub fn guess_content_type(file_path: &str) -> Option<String> {
+    use std::io::Read;
+
+    if let Some(mime) = mime_guess::from_path(file_path).first_raw() {
+        return Some(mime.to_string());
+    }
+
+    let mut head = Vec::with_capacity(8192);
+    std::fs::File::open(file_path)
+        .ok()?
+        .take(8192)
+        .read_to_end(&mut head)
+        .ok()?;
+    infer::get(&head).map(|kind| kind.mime_type().to_string())
+}
+
+/// Time needed to transfer `size` bytes at `bps` bytes per second, or `None`
+/// if `bps` is zero.
+///
+/// ```
+/// use std::time::Duration;
+/// use streamshare::estimate_duration;
+///
+/// assert_eq!(estimate_duration(1_000, 100), Some(Duration::from_secs(10)));
+/// assert_eq!(estimate_duration(150, 100), Some(Duration::from_millis(1500)));
+/// assert_eq!(estimate_duration(1_000, 0), None);
+/// ```
+pub fn estimate_duration(size: u64, bps: u64) -> Option<Duration> {
+    if bps == 0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(size as f64 / bps as f64))
+}
+
+/// Reads a local file as a stream of `chunk_size` chunks, the last one
+/// possibly shorter, as [`StreamShare::upload`] would send them.
+///
+/// Chunks can be transformed, e.g. encrypted or hashed, and then sent over
+/// [`StreamShare::open_upload_stream`].
+pub fn chunk_stream(
+    file_path: impl AsRef<Path>,
+    chunk_size: usize,
+) -> impl futures::Stream<Item = std::io::Result<Bytes>> {
+    let file_path = file_path.as_ref().to_path_buf();
+    futures::stream::try_unfold(None, move |file: Option<File>| {
+        let file_path = file_path.clone();
+        async move {
+            let mut file = match file {
+                Some(file) => file,
+                None => File::open(&file_path).await?,
+            };
+            let mut buffer = vec![0u8; chunk_size];
+            let n = read_chunk(&mut file, &mut buffer, true).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            buffer.truncate(n);
+            Ok(Some((Bytes::from(buffer), Some(file))))
+        }
+    })
+}
+
+/// Which limit ran out in [`with_deadline`].
+enum Elapsed {
+    Overall,
+    Ack,
+}
+
+impl Elapsed {
+    fn into_error(self, uploaded_bytes: u64, started: Instant) -> StreamShareError {
+        let elapsed = started.elapsed();
+        let average_bps = match elapsed.as_secs_f64() {
+            secs if secs > 0.0 => (uploaded_bytes as f64 / secs) as u64,
+            _ => 0,
+        };
+
+        match self {
+            Elapsed::Overall => StreamShareError::Timeout {
+                uploaded_bytes,
+                elapsed,
+                average_bps,
+            },
+            Elapsed::Ack => StreamShareError::AckTimeout {
+                uploaded_bytes,
+                elapsed,
+                average_bps,
+            },
+        }
+    }
+}
+
+/// Awaits `future`, giving up at the overall `deadline` or after `step`,
+/// whichever comes first.
+async fn with_deadline<F: Future>(
+    future: F,
+    deadline: Option<Instant>,
+    step: Option<Duration>,
+) -> Result<F::Output, Elapsed> {
+    let step_deadline = step.map(|step| Instant::now() + step);
+
+    let (limit, elapsed) = match (deadline, step_deadline) {
+        (Some(deadline), Some(step)) if step < deadline => (step, Elapsed::Ack),
+        (Some(deadline), _) => (deadline, Elapsed::Overall),
+        (None, Some(step)) => (step, Elapsed::Ack),
+        (None, None) => return Ok(future.await),
+    };
+
+    tokio::time::timeout_at(limit.into(), future)
+        .await
+        .map_err(|_| elapsed)
+}
+
+fn bearer_header(token: &str) -> Result<HeaderValue, StreamShareError> {
+    format!("Bearer {}", token).parse().map_err(|_| {
+        StreamShareError::InvalidConfig("auth token is not a valid header value".into())
+    })
+}
+
+pub(crate) fn basic_header(username: &str, password: &str) -> HeaderValue {
+    let credentials = BASE64_STANDARD.encode(format!("{}:{}", username, password));
+    let mut value = HeaderValue::try_from(format!("Basic {}", credentials))
+        .expect("base64 is a valid header value");
+    value.set_sensitive(true);
+    value
+}
+
+/// An upload in progress, so [`StreamShare::cancel_and_delete`] can stop it.
+#[derive(Default)]
+struct ActiveUpload {
+    cancelled: AtomicBool,
+    /// Wakes the upload if it is paused.
+    cancel: Notify,
+    finished: watch::Sender<bool>,
+}
+
+/// Keeps an upload registered in `active_uploads` while it runs.
+struct ActiveGuard<'a> {
+    client: &'a StreamShare,
+    file_identifier: String,
+    upload: Arc<ActiveUpload>,
+}
+
+impl<'a> ActiveGuard<'a> {
+    fn register(client: &'a StreamShare, file_identifier: &str) -> Self {
+        let upload = Arc::new(ActiveUpload::default());
+        client
+            .active_uploads
+            .lock()
+            .unwrap()
+            .insert(file_identifier.to_string(), upload.clone());
+
+        Self {
+            client,
+            file_identifier: file_identifier.to_string(),
+            upload,
+        }
+    }
+}
+
+impl std::ops::Deref for ActiveGuard<'_> {
+    type Target = ActiveUpload;
+
+    fn deref(&self) -> &ActiveUpload {
+        &self.upload
+    }
+}
+
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        self.client
+            .active_uploads
+            .lock()
+            .unwrap()
+            .remove(&self.file_identifier);
+        self.upload.finished.send_replace(true);
+    }
+}
+
+/// What [`StreamShare::send_chunks`] learned besides the ACKs.
+struct ChunksSent {
+    /// The deletion token, if the handshake response carried one in the
+    /// `deletion_token_header`.
+    header_token: Option<String>,
+    summary: Option<UploadSummary>,
+    /// Send-to-ACK time of every chunk.
+    #[cfg(feature = "metrics")]
+    latencies: Vec<Duration>,
+}
+
+/// Where [`StreamShare::send_chunks`] takes its chunks from.
+enum UploadSource<R> {
+    Reader(R),
+    /// A reader whose data is sent as soon as it arrives instead of once a
+    /// full chunk is read, e.g. a file that is still being written.
+    Live(R),
+    /// Already in memory; each chunk is a cheap slice of the shared buffer.
+    Bytes(Bytes),
+}
+
+/// Turns a failure to read the file at `path` because of missing permissions
+/// into [`StreamShareError::PermissionDenied`].
+fn read_failed(error: StreamShareError, path: &Path) -> StreamShareError {
+    let kind = match &error {
+        StreamShareError::Io(e) | StreamShareError::ReadError { source: e, .. } => e.kind(),
+        _ => return error,
+    };
+    if kind == std::io::ErrorKind::PermissionDenied {
+        StreamShareError::PermissionDenied {
+            path: path.to_path_buf(),
+        }
+    } else {
+        error
+    }
+}
+
+/// Turns running out of space while writing `path` into
+/// [`StreamShareError::DiskFull`].
+fn write_failed(error: StreamShareError, path: &Path) -> StreamShareError {
+    match &error {
+        StreamShareError::Io(e) if e.kind() == std::io::ErrorKind::StorageFull => {
+            StreamShareError::DiskFull {
+                path: path.to_path_buf(),
+            }
+        }
+        _ => error,
+    }
+}
+
+/// Fails early if `needed` bytes won't fit next to `file_path`, counting the
+/// space of a file it replaces. Passes when the free space is unknown.
+fn check_space(file_path: &Path, needed: u64) -> Result<(), StreamShareError> {
+    let dir = match file_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let Some(available) = available_space(dir) else {
+        return Ok(());
+    };
+    let replaced = std::fs::metadata(file_path).map_or(0, |metadata| metadata.len());
+    if available + replaced < needed {
+        return Err(StreamShareError::DiskFull {
+            path: file_path.to_path_buf(),
+        });
+    }
+    Ok(())
+}
+
+/// Free bytes available to this process on the file system holding `dir`.
+#[cfg(unix)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read after
+    // `statvfs` reported success, i.e. filled it in.
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    // The field types differ between platforms.
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+/// Quotes `value` for a POSIX shell.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Wraps a bare ETag in quotes, leaving quoted and weak (`W/"..."`) tags as
+/// they are.
+fn quote_etag(etag: &str) -> Cow<'_, str> {
+    let etag = etag.trim();
+    if etag.starts_with('"') || etag.starts_with("W/\"") {
+        Cow::Borrowed(etag)
+    } else {
+        Cow::Owned(format!("\"{}\"", etag))
+    }
+}
+
+/// Weak comparison from RFC 9110: two tags match if their opaque values are
+/// equal, whether or not either is marked weak.
+fn etag_matches(a: &str, b: &str) -> bool {
+    let value = |etag: &str| {
+        let etag = quote_etag(etag);
+        etag.strip_prefix("W/").unwrap_or(&etag).to_string()
+    };
+    value(a) == value(b)
+}
+
+/// Resolves on Ctrl-C, or `SIGTERM` on Unix. Never resolves if the handlers
+/// can't be installed, so a failure doesn't look like a signal.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Runs `future` unless [`UploadOptions::abort`] is set first, in which case
+/// it is dropped and [`StreamShareError::Cancelled`] returned.
+async fn unless_aborted<T>(
+    options: &UploadOptions,
+    future: impl Future<Output = Result<T, StreamShareError>>,
+) -> Result<T, StreamShareError> {
+    let Some(abort) = &options.abort else {
+        return future.await;
+    };
+    // An `AtomicBool` can't be awaited, so it is polled.
+    let aborted = async {
+        while !abort.load(Ordering::Relaxed) {
+            tokio::time::sleep(ABORT_POLL_INTERVAL).await;
+        }
+    };
+    tokio::select! {
+        biased;
+        _ = aborted => Err(StreamShareError::Cancelled),
+        result = future => result,
+    }
+}
+
+/// Whether a server version such as `1.4.2` or `v1.4` has a supported major.
+/// Versions that don't start with a number are given the benefit of the doubt.
+fn server_version_supported(version: &str) -> bool {
+    let version = version.trim();
+    let version = version.strip_prefix('v').unwrap_or(version);
+    version
+        .split('.')
+        .next()
+        .and_then(|major| major.parse::<u64>().ok())
+        .is_none_or(|major| SUPPORTED_SERVER_MAJORS.contains(&major))
+}
+
+/// Reads the rest of a response nobody needs, so its connection goes back
+/// to the pool instead of being closed.
+async fn drain(res: Response) {
+    let _ = res.bytes().await;
+}
+
+/// Runs `future`, sending a ping on `ws_stream` every `interval` until it
+/// completes.
+async fn keep_alive<F: Future>(
+    ws_stream: &mut WsStream,
+    interval: Option<Duration>,
+    future: F,
+) -> Result<F::Output, tungstenite::Error> {
+    let Some(interval) = interval else {
+        return Ok(future.await);
+    };
+    tokio::pin!(future);
+    let mut ticks = tokio::time::interval_at((Instant::now() + interval).into(), interval);
+    loop {
+        tokio::select! {
+            output = &mut future => return Ok(output),
+            _ = ticks.tick() => ws_stream.send(Message::Ping(Bytes::new())).await?,
+        }
+    }
+}
+
+/// Tells the server the upload is over after the progress callback panicked.
+async fn close_after_panic(ws_stream: &mut WsStream) {
+    let _ = ws_stream
+        .close(Some(tungstenite::protocol::CloseFrame {
+            code: CloseCode::Error,
+            reason: "Progress callback panicked".into(),
+        }))
+        .await;
+}
+
+/// Splits `0..size` into at most `segments` inclusive `(start, end)` ranges
+/// that are contiguous, in order and cover every byte exactly once.
+fn segment_ranges(size: u64, segments: usize) -> impl Iterator<Item = (u64, u64)> {
+    debug_assert!(size > 0 && segments > 0);
+    let segment_size = size.div_ceil(segments as u64);
+    (0..size)
+        .step_by(segment_size as usize)
+        .map(move |start| (start, (start + segment_size).min(size) - 1))
+}
+
+/// Makes sure a `206` response covers exactly `start..=end`. A missing
+/// `Content-Range` is accepted; the length is checked while reading.
+fn check_content_range(headers: &HeaderMap, start: u64, end: u64) -> Result<(), StreamShareError> {
+    let Some(value) = headers.get("content-range") else {
+        return Ok(());
+    };
+    let actual = value.to_str().unwrap_or_default();
+    let range = actual
+        .strip_prefix("bytes ")
+        .and_then(|rest| rest.split_once('/'))
+        .and_then(|(range, _)| range.split_once('-'))
+        .and_then(|(first, last)| Some((first.trim().parse().ok()?, last.trim().parse().ok()?)));
+    if range == Some((start, end)) {
+        return Ok(());
+    }
+    Err(StreamShareError::RangeMismatch {
+        expected: format!("bytes {}-{}", start, end),
+        actual: actual.to_string(),
+    })
+}
+
+/// Fills `buffer` from `reader`, returning fewer bytes only at end of input.
+/// Without `fill` it returns whatever the first read yields.
+async fn read_chunk<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buffer: &mut [u8],
+    fill: bool,
+) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let n = reader.read(&mut buffer[filled..]).await?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+        if !fill {
+            break;
+        }
+    }
+    Ok(filled)
+}
+
+impl Default for StreamShare {
+    fn default() -> Self {
+        Self::builder().build_unchecked()
+    }
 }
@@ -0,0 +1,82 @@
+use clap::{Parser, Subcommand};
+use indicatif::{ProgressBar, ProgressStyle};
+use streamshare::{DeleteOutcome, StreamShare, StreamShareError};
+
+#[derive(Parser)]
+#[command(name = "streamshare", version, about = "Upload files to streamshare")]
+struct Cli {
+    /// Server to talk to, without scheme
+    #[arg(
+        long,
+        env = "STREAMSHARE_SERVER",
+        default_value = "streamshare.wireway.ch"
+    )]
+    server: String,
+
+    /// Upload chunk size in bytes
+    #[arg(long, env = "STREAMSHARE_CHUNK_SIZE", default_value_t = 1024 * 1024)]
+    chunk_size: usize,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Upload a file and print its download URL and deletion token
+    Upload { path: String },
+    /// Download a file into a directory or to a file path
+    Download {
+        id: String,
+        #[arg(default_value = "")]
+        dest: String,
+        /// Overwrite the destination if it already exists
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Delete a file using its deletion token
+    Delete { id: String, token: String },
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let client = StreamShare::new(cli.server, cli.chunk_size);
+
+    let result = match cli.command {
+        Command::Upload { path } => upload(&client, &path).await,
+        Command::Download { id, dest, replace } => client
+            .download(&id, &dest, replace)
+            .await
+            .map(|_| println!("File downloaded successfully")),
+        Command::Delete { id, token } => {
+            client
+                .delete(&id, &token)
+                .await
+                .map(|outcome| match outcome {
+                    DeleteOutcome::Deleted => println!("File deleted successfully"),
+                    DeleteOutcome::AlreadyAbsent => println!("File was already deleted"),
+                })
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn upload(client: &StreamShare, path: &str) -> Result<(), StreamShareError> {
+    let bar = ProgressBar::new(0);
+    bar.set_style(
+        ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
+            .unwrap(),
+    );
+
+    let result = client.upload_with_bar(path, &bar).await?;
+
+    let links = client.share_links(&result)?;
+    println!("Download URL: {}", links.download);
+    println!("Deletion token: {}", result.deletion_token);
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// A bounded set of reusable chunk buffers shared between uploads.
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    permits: Semaphore,
+    buffer_size: usize,
+}
+
+impl BufferPool {
+    pub(crate) fn new(max_buffers: usize, buffer_size: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::with_capacity(max_buffers)),
+            permits: Semaphore::new(max_buffers),
+            buffer_size,
+        }
+    }
+
+    /// Waits until a buffer is free and hands it out.
+    pub(crate) async fn acquire(&self) -> PooledBuffer<'_> {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("buffer pool semaphore is never closed");
+
+        let buffer = self
+            .buffers
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| vec![0u8; self.buffer_size]);
+
+        PooledBuffer {
+            pool: self,
+            buffer: Some(buffer),
+            _permit: permit,
+        }
+    }
+}
+
+/// A buffer borrowed from a [`BufferPool`], returned to it on drop.
+pub(crate) struct PooledBuffer<'a> {
+    pool: &'a BufferPool,
+    buffer: Option<Vec<u8>>,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.buffer.as_deref().unwrap()
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.buffer.as_deref_mut().unwrap()
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            self.pool.buffers.lock().unwrap().push(buffer);
+        }
+    }
+}
+
+/// Either a buffer from the shared pool or one owned by a single upload.
+pub(crate) enum ChunkBuffer<'a> {
+    Owned(Vec<u8>),
+    Pooled(PooledBuffer<'a>),
+}
+
+impl Deref for ChunkBuffer<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ChunkBuffer::Owned(buffer) => buffer,
+            ChunkBuffer::Pooled(buffer) => buffer,
+        }
+    }
+}
+
+impl DerefMut for ChunkBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            ChunkBuffer::Owned(buffer) => buffer,
+            ChunkBuffer::Pooled(buffer) => buffer,
+        }
+    }
+}
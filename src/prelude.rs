@@ -0,0 +1,19 @@
+//! The types most code using this crate needs.
+//!
+//! ```no_run
+//! use streamshare::prelude::*;
+//!
+//! # async fn run() -> Result<(), StreamShareError> {
+//! let client = StreamShare::builder().build()?;
+//! let result: UploadResult = client.upload("Cargo.toml", |_, _| {}).await?;
+//! client.delete(&result.file_identifier, &result.deletion_token).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub use crate::{
+    BinaryAck, ChunkFraming, ConflictDecision, DeleteOutcome, DownloadOutcome, HashAlgorithm,
+    IpPreference, Progress, ProtocolVersion, Scheme, ShareLinks, StreamShare, StreamShareBuilder,
+    StreamShareError, TextAck, TimeoutProfile, TlsVersion, Transfer, TransferKind, TransferMetrics,
+    UploadHandle, UploadOptions, UploadResult,
+};
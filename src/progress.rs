@@ -0,0 +1,27 @@
+use indicatif::ProgressBar;
+
+use crate::{StreamShare, StreamShareError, UploadResult};
+
+impl StreamShare {
+    /// Uploads a file while driving `bar`: its length follows the file size
+    /// and its position the uploaded bytes. The bar is finished when the
+    /// upload succeeds and abandoned when it fails.
+    pub async fn upload_with_bar(
+        &self,
+        file_path: &str,
+        bar: &ProgressBar,
+    ) -> Result<UploadResult, StreamShareError> {
+        let result = self
+            .upload(file_path, |uploaded, total| {
+                bar.set_length(total);
+                bar.set_position(uploaded);
+            })
+            .await;
+
+        match &result {
+            Ok(_) => bar.finish(),
+            Err(_) => bar.abandon(),
+        }
+        result
+    }
+}
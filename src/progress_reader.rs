@@ -0,0 +1,76 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Wraps an [`AsyncRead`] and reports `(read, total)` to a callback as bytes
+/// pass through, so progress can be tracked at any stage of a pipeline.
+///
+/// `total` is `0` if the size isn't known.
+///
+/// ```no_run
+/// use streamshare::{ProgressReader, StreamShare};
+///
+/// # async fn run() -> Result<(), streamshare::StreamShareError> {
+/// let client = StreamShare::builder().build()?;
+/// let file = tokio::fs::File::open("video.mp4").await?;
+/// let size = file.metadata().await?.len();
+/// let reader = ProgressReader::new(file, Some(size), |read, total| {
+///     println!("{}/{}", read, total);
+/// });
+/// client.upload_reader("video.mp4", reader, Some(size), |_, _| {}).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ProgressReader<R, F> {
+    inner: R,
+    read: u64,
+    total: u64,
+    callback: F,
+}
+
+impl<R, F> ProgressReader<R, F>
+where
+    F: FnMut(u64, u64),
+{
+    pub fn new(inner: R, total: Option<u64>, callback: F) -> Self {
+        Self {
+            inner,
+            read: 0,
+            total: total.unwrap_or(0),
+            callback,
+        }
+    }
+
+    /// Bytes read through this wrapper so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R, F> AsyncRead for ProgressReader<R, F>
+where
+    R: AsyncRead + Unpin,
+    F: FnMut(u64, u64) + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let read = (buf.filled().len() - before) as u64;
+        if read > 0 {
+            let this = &mut *self;
+            this.read += read;
+            (this.callback)(this.read, this.total);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,294 @@
+//! Upload state that survives a crash of the uploading process.
+//!
+//! [`StreamShare::upload_with_state`] writes an [`UploadState`] as JSON next
+//! to the upload, e.g.
+//!
+//! ```json
+//! {
+//!   "file_identifier": "4a3a6fb0...",
+//!   "deletion_token": "...",
+//!   "file_path": "/home/me/video.mp4",
+//!   "size": 73400320,
+//!   "confirmed": 16777216,
+//!   "chunk_size": 1048576,
+//!   "prefix_sha256": "9f86d081..."
+//! }
+//! ```
+//!
+//! To resume, the client opens `/api/upload/{file_identifier}?offset={confirmed}`.
+//! The server keeps the first `confirmed` bytes, drops anything after them
+//! and appends the frames that follow. A server holding fewer bytes than
+//! `offset` rejects the handshake.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, File};
+use tokio::io::AsyncReadExt;
+
+use crate::checksum::{HashedPrefix, Hasher, HashingReader};
+use crate::{
+    HashAlgorithm, Meter, StreamShare, StreamShareError, TransferKind, UploadOptions, UploadResult,
+    UploadSource,
+};
+
+/// Progress of an upload as saved by [`StreamShare::upload_with_state`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadState {
+    pub file_identifier: String,
+    pub deletion_token: String,
+    pub file_path: PathBuf,
+    pub size: u64,
+    /// Bytes the server has acknowledged.
+    pub confirmed: u64,
+    /// Chunk size of the original upload. Resuming uses the client's own.
+    pub chunk_size: usize,
+    /// SHA-256 of the first `confirmed` bytes, to detect a changed file.
+    pub prefix_sha256: String,
+}
+
+impl UploadState {
+    /// Reads a state file written by [`StreamShare::upload_with_state`].
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, StreamShareError> {
+        let data = fs::read(path).await?;
+        Ok(serde_json::from_slice(&data).map_err(io::Error::from)?)
+    }
+
+    /// Replaces the file at `path` atomically, so a crash mid-write leaves
+    /// the previous state intact.
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        std::fs::write(&temp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&temp, path)
+    }
+}
+
+impl StreamShare {
+    /// Like [`upload`](Self::upload), but keeps an [`UploadState`] at
+    /// `state_path` so the upload can be continued with
+    /// [`resume_from_state`](Self::resume_from_state) after the process
+    /// crashed.
+    ///
+    /// The state is written when the upload is created and then every
+    /// [`state_save_interval`](crate::StreamShareBuilder::state_save_interval)
+    /// chunks. A failed write leaves the previous, still valid state in
+    /// place. The file is removed once the upload finishes.
+    pub async fn upload_with_state<F>(
+        &self,
+        file_path: &str,
+        state_path: impl AsRef<Path>,
+        callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        self.upload_with_state_options(
+            file_path,
+            state_path.as_ref(),
+            &UploadOptions::default(),
+            callback,
+        )
+        .await
+    }
+
+    pub(crate) async fn upload_with_state_options<F>(
+        &self,
+        file_path: &str,
+        state_path: &Path,
+        options: &UploadOptions,
+        callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let mut meter = Meter::start(self, TransferKind::Upload);
+        let path = Path::new(file_path);
+        let metadata = fs::metadata(path).await?;
+        if !metadata.is_file() {
+            return Err(StreamShareError::NotAFile {
+                path: file_path.to_string(),
+            });
+        }
+        if self.reject_empty && metadata.len() == 0 {
+            return Err(StreamShareError::EmptyFile {
+                path: path.to_path_buf(),
+            });
+        }
+        let file_name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("unknown");
+
+        self.check_chunk_count(Some(metadata.len()))?;
+        let _permit = self.acquire_upload_permit().await;
+        let file = File::open(path).await?;
+        let result = self
+            .create(file_name, Some(metadata.len()), None, options)
+            .await?;
+
+        let hasher = Hasher::new(HashAlgorithm::Sha256);
+        let state = UploadState {
+            file_identifier: result.file_identifier,
+            deletion_token: result.deletion_token,
+            file_path: path.to_path_buf(),
+            size: metadata.len(),
+            confirmed: 0,
+            chunk_size: self.chunk_size,
+            prefix_sha256: hasher.clone().finish().digest,
+        };
+        state.save(state_path)?;
+
+        self.send_with_state(
+            state, state_path, file, hasher, &mut meter, options, callback,
+        )
+        .await
+    }
+
+    /// Continues an upload from a state file written by
+    /// [`upload_with_state`](Self::upload_with_state), e.g. after a restart.
+    ///
+    /// The local file must still have the same size and the same first
+    /// `confirmed` bytes, otherwise this fails with
+    /// [`StreamShareError::ResumeStateMismatch`]. The server has to report
+    /// `resume` in its [`capabilities`](Self::capabilities); see the
+    /// [`UploadState`] docs for the protocol.
+    pub async fn resume_from_state<F>(
+        &self,
+        state_path: impl AsRef<Path>,
+        callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        self.resume_with_options(state_path.as_ref(), &UploadOptions::default(), callback)
+            .await
+    }
+
+    pub(crate) async fn resume_with_options<F>(
+        &self,
+        state_path: &Path,
+        options: &UploadOptions,
+        callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let mut meter = Meter::start(self, TransferKind::Upload);
+        let state = UploadState::load(state_path).await?;
+        if !self.capabilities().await?.resume {
+            return Err(StreamShareError::ResumeUnsupported);
+        }
+
+        let mismatch = || StreamShareError::ResumeStateMismatch {
+            path: state.file_path.clone(),
+        };
+        let mut file = File::open(&state.file_path).await?;
+        if file.metadata().await?.len() != state.size {
+            return Err(mismatch());
+        }
+
+        // Reading the prefix leaves the file positioned where the upload
+        // continues.
+        let mut hasher = Hasher::new(HashAlgorithm::Sha256);
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut remaining = state.confirmed;
+        while remaining > 0 {
+            let want = buffer.len().min(remaining as usize);
+            let n = file.read(&mut buffer[..want]).await?;
+            if n == 0 {
+                return Err(mismatch());
+            }
+            hasher.update(&buffer[..n]);
+            remaining -= n as u64;
+        }
+        if hasher.clone().finish().digest != state.prefix_sha256 {
+            return Err(mismatch());
+        }
+
+        let _permit = self.acquire_upload_permit().await;
+        self.send_with_state(
+            state, state_path, file, hasher, &mut meter, options, callback,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn send_with_state<F>(
+        &self,
+        mut state: UploadState,
+        state_path: &Path,
+        file: File,
+        hasher: Hasher,
+        meter: &mut Meter<'_>,
+        options: &UploadOptions,
+        mut callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        F: FnMut(u64, u64),
+    {
+        let prefix = Mutex::new(HashedPrefix {
+            hasher,
+            len: state.confirmed,
+        });
+        let reader = HashingReader {
+            inner: file,
+            prefix: &prefix,
+        };
+
+        let file_identifier = state.file_identifier.clone();
+        let interval = self.state_save_interval;
+        let mut saved_at = 0;
+        let sent = self
+            .send_chunks(
+                &file_identifier,
+                UploadSource::Reader(reader),
+                Some(state.size),
+                state.confirmed,
+                meter,
+                options,
+                |chunk_index, confirmed, total| {
+                    callback(confirmed, total);
+                    // Counted from the last save, as `progress_interval` may skip
+                    // chunks.
+                    if chunk_index + 1 - saved_at < interval {
+                        return;
+                    }
+                    // Only bytes the server confirmed may be recorded, and the
+                    // hash has to cover exactly those.
+                    let prefix = prefix.lock().unwrap();
+                    if prefix.len == confirmed {
+                        state.confirmed = confirmed;
+                        state.prefix_sha256 = prefix.hasher.clone().finish().digest;
+                        let _ = state.save(state_path);
+                        saved_at = chunk_index + 1;
+                    }
+                },
+            )
+            .await?;
+
+        match fs::remove_file(state_path).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        meter.success = true;
+        Ok(UploadResult {
+            file_identifier,
+            deletion_token: state.deletion_token,
+            checksum: None,
+            summary: None,
+            sha256: None,
+            stored_file_name: state
+                .file_path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(str::to_string),
+            expires_at: None,
+            #[cfg(feature = "metrics")]
+            stats: None,
+        }
+        .with_sent(sent))
+    }
+}
@@ -0,0 +1,1014 @@
+//! An in-process StreamShare server for tests.
+//!
+//! [`MockServer`] speaks just enough HTTP and WebSocket to cover create,
+//! upload, download and delete, keeping everything in memory. It listens on
+//! a random local port over plain `http`/`ws`, so tests run offline and
+//! deterministically.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), streamshare::StreamShareError> {
+//! use streamshare::testing::MockServer;
+//!
+//! let server = MockServer::start().await;
+//! let client = server.client();
+//!
+//! let result = client.upload("Cargo.toml", |_, _| {}).await?;
+//! assert!(server.file(&result.file_identifier).is_some());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::builder::percent_decode;
+use crate::{StreamShare, StreamShareBuilder};
+
+/// A file held by the [`MockServer`].
+#[derive(Debug, Clone)]
+pub struct MockFile {
+    pub name: String,
+    pub data: Vec<u8>,
+    /// Size sent in the create request, `None` for an upload of unknown size.
+    pub size: Option<u64>,
+    pub deletion_token: String,
+    /// Whether the upload finished with a close frame.
+    pub complete: bool,
+    /// Every binary frame received for this file, in order, so tests can
+    /// assert exact chunk boundaries including the final partial chunk.
+    pub chunks: Vec<MockChunk>,
+}
+
+/// Position of one uploaded chunk within a [`MockFile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MockChunk {
+    pub offset: u64,
+    pub len: usize,
+}
+
+/// How the [`MockServer`] acknowledges uploaded chunks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MockAck {
+    /// The text frame `ACK`.
+    #[default]
+    Text,
+    /// A binary frame with the total bytes received as big-endian `u64`,
+    /// matching [`BinaryAck::OffsetBe`](crate::BinaryAck::OffsetBe).
+    BinaryOffset,
+    /// `ACK:<bytes>` with the total bytes received, matching
+    /// [`TextAck::Offset`](crate::TextAck::Offset).
+    TextOffset,
+    /// Like `TextOffset`, but reporting one byte less than received, to
+    /// provoke [`StreamShareError::AckDesync`](crate::StreamShareError::AckDesync).
+    TextOffsetShort,
+}
+
+/// Which `ETag` the [`MockServer`] sends with downloads, derived from the
+/// file's content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockEtag {
+    /// `"<hash>"`
+    Strong,
+    /// `W/"<hash>"`
+    Weak,
+}
+
+/// An HTTP request as the [`MockServer`] received it, upload handshakes
+/// included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path including the query string, e.g. `/api/upload/abc?offset=0`.
+    pub path: String,
+    /// Names are lowercase.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The body parsed as JSON, e.g. of a create request.
+    pub fn json(&self) -> Option<serde_json::Value> {
+        serde_json::from_slice(&self.body).ok()
+    }
+}
+
+/// A WebSocket frame of an upload, in either direction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    pub file_identifier: String,
+    /// `true` for frames the client sent, `false` for the server's replies.
+    pub from_client: bool,
+    pub message: Message,
+}
+
+/// Everything a [`MockServer`] received and answered on its WebSockets, from
+/// [`MockServer::recorder`].
+///
+/// ```no_run
+/// # async fn run() -> Result<(), streamshare::StreamShareError> {
+/// use streamshare::testing::MockServer;
+///
+/// let server = MockServer::start().await;
+/// let recorder = server.recorder();
+/// server.client().upload("Cargo.toml", |_, _| {}).await?;
+///
+/// let create = &recorder.requests_to("/api/create")[0];
+/// assert_eq!(create.json().unwrap()["name"], "Cargo.toml");
+/// println!("{} binary frames", recorder.binary_frames_sent());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Recorder {
+    state: Arc<State>,
+}
+
+impl Recorder {
+    /// Every request in the order it arrived.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.recording.lock().unwrap().requests.clone()
+    }
+
+    /// Requests whose path, without the query, is `path`.
+    pub fn requests_to(&self, path: &str) -> Vec<RecordedRequest> {
+        self.state
+            .recording
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .filter(|request| request.path.split('?').next() == Some(path))
+            .cloned()
+            .collect()
+    }
+
+    /// Every upload frame in the order the server read or wrote it.
+    pub fn frames(&self) -> Vec<RecordedFrame> {
+        self.state.recording.lock().unwrap().frames.clone()
+    }
+
+    /// How many binary frames clients sent, across all uploads.
+    pub fn binary_frames_sent(&self) -> usize {
+        self.state
+            .recording
+            .lock()
+            .unwrap()
+            .frames
+            .iter()
+            .filter(|frame| frame.from_client && frame.message.is_binary())
+            .count()
+    }
+
+    /// Forgets everything recorded so far.
+    pub fn clear(&self) {
+        *self.state.recording.lock().unwrap() = Recording::default();
+    }
+}
+
+#[derive(Default)]
+struct Recording {
+    requests: Vec<RecordedRequest>,
+    frames: Vec<RecordedFrame>,
+}
+
+#[derive(Default)]
+struct State {
+    files: Mutex<HashMap<String, MockFile>>,
+    recording: Mutex<Recording>,
+    ack: Mutex<MockAck>,
+    slow_down: Mutex<Option<String>>,
+    download_overshoot: Mutex<usize>,
+    download_cutoff: Mutex<Option<usize>>,
+    chunked_downloads: Mutex<bool>,
+    download_redirects: Mutex<usize>,
+    etag: Mutex<Option<MockEtag>>,
+    omit_file_names: Mutex<bool>,
+    ignore_if_none_match: Mutex<bool>,
+    disconnect_after: Mutex<Option<usize>>,
+    nack_every: Mutex<Option<usize>>,
+    bandwidth: Mutex<Option<u64>>,
+    unavailable: AtomicUsize,
+    unavailable_downloads: AtomicUsize,
+    connections: AtomicUsize,
+    response_bandwidth: Mutex<Option<u64>>,
+    open_uploads: Gauge,
+    open_requests: Gauge,
+}
+
+/// How many of something are open right now, and the most that ever were.
+#[derive(Default)]
+struct Gauge {
+    open: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl Gauge {
+    /// Counts one more as open until the returned guard is dropped.
+    fn enter(&self) -> GaugeGuard<'_> {
+        let open = self.open.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(open, Ordering::SeqCst);
+        GaugeGuard(self)
+    }
+
+    fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+}
+
+struct GaugeGuard<'a>(&'a Gauge);
+
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        self.0.open.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl State {
+    fn record_frame(&self, file_identifier: &str, from_client: bool, message: &Message) {
+        self.recording.lock().unwrap().frames.push(RecordedFrame {
+            file_identifier: file_identifier.to_string(),
+            from_client,
+            message: message.clone(),
+        });
+    }
+}
+
+/// A local StreamShare server running on a background task.
+///
+/// The server shuts down when this value is dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<State>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind mock server");
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(State::default());
+
+        let task = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    state.connections.fetch_add(1, Ordering::Relaxed);
+                    tokio::spawn(handle_connection(stream, state.clone()));
+                }
+            }
+        });
+
+        Self { addr, state, task }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The `host:port` to use as server URL.
+    pub fn server_url(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// A builder already pointed at this server over plain `http`/`ws`.
+    pub fn builder(&self) -> StreamShareBuilder {
+        StreamShare::builder()
+            .server_url(self.server_url())
+            .secure(false)
+    }
+
+    pub fn client(&self) -> StreamShare {
+        self.builder().build_unchecked()
+    }
+
+    pub fn file(&self, file_identifier: &str) -> Option<MockFile> {
+        self.state
+            .files
+            .lock()
+            .unwrap()
+            .get(file_identifier)
+            .cloned()
+    }
+
+    /// Changes how chunks are acknowledged for uploads started afterwards.
+    pub fn set_ack(&self, ack: MockAck) {
+        *self.state.ack.lock().unwrap() = ack;
+    }
+
+    /// Sends the text frame `frame`, e.g. `THROTTLE:100`, before every ACK of
+    /// uploads started afterwards, to exercise
+    /// [`slow_down_token`](crate::StreamShareBuilder::slow_down_token).
+    /// `None` stops it.
+    pub fn set_slow_down(&self, frame: Option<&str>) {
+        *self.state.slow_down.lock().unwrap() = frame.map(str::to_string);
+    }
+
+    /// Appends `extra` bytes to every full `GET` download and sends it with
+    /// chunked encoding, while `HEAD` keeps reporting the real size. Simulates
+    /// a server sending more data than it advertised.
+    pub fn set_download_overshoot(&self, extra: usize) {
+        *self.state.download_overshoot.lock().unwrap() = extra;
+    }
+
+    /// Answers every `GET` download with `hops` redirects, each to the same
+    /// download with a `hop` query parameter counting up, before sending the
+    /// file, like a server handing downloads off to a CDN.
+    pub fn set_download_redirects(&self, hops: usize) {
+        *self.state.download_redirects.lock().unwrap() = hops;
+    }
+
+    /// Sends an `ETag` with every download, and answers a `GET` whose
+    /// `If-None-Match` matches it, compared weakly, with `304 Not Modified`.
+    /// `None`, the default, sends none.
+    pub fn set_etag(&self, etag: Option<MockEtag>) {
+        *self.state.etag.lock().unwrap() = etag;
+    }
+
+    /// Leaves out `content-disposition` on downloads, so clients can't tell
+    /// the file's name.
+    pub fn set_omit_file_names(&self, omit: bool) {
+        *self.state.omit_file_names.lock().unwrap() = omit;
+    }
+
+    /// Sends the full download even if `If-None-Match` matches, like a
+    /// server without conditional requests that still sends an `ETag`.
+    pub fn set_ignore_if_none_match(&self, ignore: bool) {
+        *self.state.ignore_if_none_match.lock().unwrap() = ignore;
+    }
+
+    /// Sends full `GET` downloads with chunked encoding and no
+    /// `Content-Length`, like a server streaming a file of unknown size.
+    pub fn set_chunked_downloads(&self, chunked: bool) {
+        *self.state.chunked_downloads.lock().unwrap() = chunked;
+    }
+
+    /// Sends only the first `bytes` of every full `GET` download, under the
+    /// real `Content-Length`, and then closes the connection, like a link
+    /// that fails mid-transfer. `None` sends downloads in full.
+    pub fn set_download_cutoff(&self, bytes: Option<usize>) {
+        *self.state.download_cutoff.lock().unwrap() = bytes;
+    }
+
+    /// Drops the next upload's connection without a close frame once `frames`
+    /// binary frames were acknowledged, like a network failure. Later uploads
+    /// are unaffected, so a resumed upload goes through.
+    pub fn set_disconnect_after(&self, frames: Option<usize>) {
+        *self.state.disconnect_after.lock().unwrap() = frames;
+    }
+
+    /// Answers every `frames`-th binary frame of uploads started afterwards
+    /// with `NACK` instead of an ACK and discards it, so the client has to
+    /// send it again. `None` accepts every frame.
+    pub fn set_nack_every(&self, frames: Option<usize>) {
+        *self.state.nack_every.lock().unwrap() = frames.filter(|&frames| frames > 0);
+    }
+
+    /// Writes HTTP response bodies as if the link carried `bytes_per_second`,
+    /// e.g. to keep downloads running long enough to overlap. `None`, the
+    /// default, sends them at once.
+    pub fn set_response_bandwidth(&self, bytes_per_second: Option<u64>) {
+        *self.state.response_bandwidth.lock().unwrap() = bytes_per_second;
+    }
+
+    /// Holds back every ACK as if the upload link carried `bytes_per_second`,
+    /// i.e. by a frame's length divided by it. Unlike the other settings this
+    /// also applies to running uploads, from their next frame on, so a test
+    /// can change the link speed mid-upload. `None` ACKs right away.
+    pub fn set_bandwidth(&self, bytes_per_second: Option<u64>) {
+        *self.state.bandwidth.lock().unwrap() = bytes_per_second;
+    }
+
+    /// Answers the next `requests` plain HTTP requests with `503 Service
+    /// Unavailable` and `Retry-After: 0`, like a server in maintenance.
+    /// Upload WebSockets are unaffected.
+    pub fn set_unavailable(&self, requests: usize) {
+        self.state.unavailable.store(requests, Ordering::Relaxed);
+    }
+
+    /// Like [`set_unavailable`](Self::set_unavailable), but only for `GET`
+    /// downloads, ranged or not, so the `HEAD` requests before them go through.
+    pub fn set_unavailable_downloads(&self, requests: usize) {
+        self.state
+            .unavailable_downloads
+            .store(requests, Ordering::Relaxed);
+    }
+
+    /// How many TCP connections were accepted so far, including upload
+    /// WebSockets. Lets tests check that HTTP requests reuse pooled
+    /// connections.
+    pub fn connection_count(&self) -> usize {
+        self.state.connections.load(Ordering::Relaxed)
+    }
+
+    /// The most upload WebSockets that were open at the same time. An upload
+    /// stops counting once the client sends its close frame.
+    pub fn peak_open_uploads(&self) -> usize {
+        self.state.open_uploads.peak()
+    }
+
+    /// The most HTTP requests that were in progress at the same time, each
+    /// counting from when it was read until its response was written.
+    /// Upload WebSockets aren't included.
+    pub fn peak_open_requests(&self) -> usize {
+        self.state.open_requests.peak()
+    }
+
+    /// A handle on every request and upload frame this server sees,
+    /// including those before this call.
+    pub fn recorder(&self) -> Recorder {
+        Recorder {
+            state: self.state.clone(),
+        }
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.state.files.lock().unwrap().len()
+    }
+
+    /// Stores a complete file directly, returning its identifier and deletion token.
+    pub fn insert_file(&self, name: &str, data: impl Into<Vec<u8>>) -> (String, String) {
+        let file_identifier = uuid::Uuid::new_v4().simple().to_string();
+        let deletion_token = uuid::Uuid::new_v4().simple().to_string();
+        let data = data.into();
+
+        self.state.files.lock().unwrap().insert(
+            file_identifier.clone(),
+            MockFile {
+                name: name.to_string(),
+                size: Some(data.len() as u64),
+                data,
+                deletion_token: deletion_token.clone(),
+                complete: true,
+                chunks: Vec::new(),
+            },
+        );
+
+        (file_identifier, deletion_token)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Response {
+    status: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+    chunked: bool,
+    /// Send only this much of the body and close the connection.
+    cutoff: Option<usize>,
+    /// Pace the body to this many bytes per second.
+    bandwidth: Option<u64>,
+}
+
+impl Response {
+    fn new(status: &'static str) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            chunked: false,
+            cutoff: None,
+            bandwidth: None,
+        }
+    }
+
+    fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: Arc<State>) {
+    let mut buffer = Vec::new();
+
+    while let Some(request) = read_request(&mut stream, &mut buffer).await {
+        state
+            .recording
+            .lock()
+            .unwrap()
+            .requests
+            .push(RecordedRequest {
+                method: request.method.clone(),
+                path: request.path.clone(),
+                headers: request.headers.clone(),
+                body: request.body.clone(),
+            });
+        if request
+            .header("upgrade")
+            .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+        {
+            handle_upgrade(stream, request, std::mem::take(&mut buffer), &state).await;
+            return;
+        }
+
+        let head_only = request.method == "HEAD";
+        let _open = state.open_requests.enter();
+        let take = |count: &AtomicUsize| {
+            count
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok()
+        };
+        let unavailable = take(&state.unavailable)
+            || (request.method == "GET"
+                && request.path.starts_with("/download/")
+                && take(&state.unavailable_downloads));
+        let mut response = if unavailable {
+            Response::new("503 Service Unavailable").header("retry-after", "0")
+        } else {
+            route(&request, &state)
+        };
+        response.bandwidth = *state.response_bandwidth.lock().unwrap();
+        let cut_off = response.cutoff.is_some();
+        if write_response(&mut stream, response, head_only)
+            .await
+            .is_err()
+            || cut_off
+        {
+            return;
+        }
+    }
+}
+
+/// Reads one request, keeping any bytes past it in `buffer` for the next one.
+async fn read_request(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> Option<Request> {
+    let head_end = loop {
+        if let Some(pos) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break pos + 4;
+        }
+
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+    buffer.drain(..head_end);
+
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    while buffer.len() < content_length {
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+    let body = buffer.drain(..content_length).collect();
+
+    Some(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    response: Response,
+    head_only: bool,
+) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if response.chunked {
+        head.push_str("transfer-encoding: chunked\r\n\r\n");
+    } else {
+        head.push_str(&format!("content-length: {}\r\n\r\n", response.body.len()));
+    }
+
+    stream.write_all(head.as_bytes()).await?;
+    if !head_only {
+        if response.chunked {
+            stream
+                .write_all(format!("{:x}\r\n", response.body.len()).as_bytes())
+                .await?;
+            write_paced(stream, &response.body, response.bandwidth).await?;
+            stream.write_all(b"\r\n0\r\n\r\n").await?;
+        } else {
+            let len = response.cutoff.map_or(response.body.len(), |cutoff| {
+                cutoff.min(response.body.len())
+            });
+            write_paced(stream, &response.body[..len], response.bandwidth).await?;
+        }
+    }
+    stream.flush().await
+}
+
+/// Writes `data` in small pieces at `bytes_per_second`, or all at once.
+async fn write_paced(
+    stream: &mut TcpStream,
+    data: &[u8],
+    bytes_per_second: Option<u64>,
+) -> std::io::Result<()> {
+    let Some(bytes_per_second) = bytes_per_second.filter(|&bps| bps > 0) else {
+        return stream.write_all(data).await;
+    };
+    for piece in data.chunks(8 * 1024) {
+        stream.write_all(piece).await?;
+        stream.flush().await?;
+        let secs = piece.len() as f64 / bytes_per_second as f64;
+        tokio::time::sleep(Duration::from_secs_f64(secs)).await;
+    }
+    Ok(())
+}
+
+fn route(request: &Request, state: &State) -> Response {
+    let path = request.path.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["api", "create"]) => create(request, state),
+        ("GET", ["api", "capabilities"]) => Response::new("200 OK")
+            .header("content-type", "application/json")
+            .body(r#"{"ranges":true,"resume":true,"directUpload":true}"#),
+        ("POST", ["api", "upload"]) => upload_direct(request, state),
+        ("GET", ["api", "usage"]) => usage(state),
+        ("GET" | "HEAD", ["download", file_identifier]) => {
+            download(request, state, file_identifier)
+        }
+        (method @ ("DELETE" | "HEAD"), ["api", "delete", file_identifier, deletion_token]) => {
+            delete(state, file_identifier, deletion_token, method == "DELETE")
+        }
+        _ => Response::new("404 Not Found"),
+    }
+}
+
+/// Every stored file counts, with no quota.
+fn usage(state: &State) -> Response {
+    let files = state.files.lock().unwrap();
+    let used_bytes: usize = files.values().map(|file| file.data.len()).sum();
+    Response::new("200 OK")
+        .header("content-type", "application/json")
+        .body(serde_json::json!({ "usedBytes": used_bytes, "fileCount": files.len() }).to_string())
+}
+
+fn create(request: &Request, state: &State) -> Response {
+    let body = serde_json::from_slice::<serde_json::Value>(&request.body).ok();
+    let name = body
+        .as_ref()
+        .and_then(|body| body.get("name")?.as_str().map(str::to_string));
+
+    let Some(name) = name else {
+        return Response::new("400 Bad Request");
+    };
+    let size = body.as_ref().and_then(|body| body.get("size")?.as_u64());
+    let expires_in = body.and_then(|body| body.get("expiresIn")?.as_u64());
+
+    created(state, name, size, expires_in, Vec::new(), false)
+}
+
+/// `POST /api/upload?name=...` with the whole file as body.
+fn upload_direct(request: &Request, state: &State) -> Response {
+    let query = request.path.split_once('?').map_or("", |(_, query)| query);
+    let name = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "name")
+        .map(|(_, value)| percent_decode(&value.replace('+', " ")));
+
+    let Some(name) = name else {
+        return Response::new("400 Bad Request");
+    };
+
+    let size = Some(request.body.len() as u64);
+    created(state, name, size, None, request.body.clone(), true)
+}
+
+fn created(
+    state: &State,
+    name: String,
+    size: Option<u64>,
+    expires_in: Option<u64>,
+    data: Vec<u8>,
+    complete: bool,
+) -> Response {
+    let file_identifier = uuid::Uuid::new_v4().simple().to_string();
+    let deletion_token = uuid::Uuid::new_v4().simple().to_string();
+    let mut body = serde_json::json!({
+        "fileIdentifier": file_identifier,
+        "deletionToken": deletion_token,
+        "fileName": name,
+    });
+    // Files never actually expire; the time is only reported, in Unix seconds.
+    if let Some(expires_in) = expires_in {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        body["expiresAt"] = (now.as_secs() + expires_in).into();
+    }
+
+    state.files.lock().unwrap().insert(
+        file_identifier.clone(),
+        MockFile {
+            name,
+            size,
+            data,
+            deletion_token: deletion_token.clone(),
+            complete,
+            chunks: Vec::new(),
+        },
+    );
+
+    Response::new("200 OK")
+        .header("content-type", "application/json")
+        .body(body.to_string())
+}
+
+fn download(request: &Request, state: &State, file_identifier: &str) -> Response {
+    let Some(file) = state.files.lock().unwrap().get(file_identifier).cloned() else {
+        return Response::new("404 Not Found");
+    };
+
+    let hops = *state.download_redirects.lock().unwrap();
+    let hop = request
+        .path
+        .split_once('?')
+        .and_then(|(_, query)| query.split('&').find_map(|pair| pair.strip_prefix("hop=")))
+        .and_then(|hop| hop.parse::<usize>().ok())
+        .unwrap_or(0);
+    if request.method == "GET" && hop < hops {
+        return Response::new("302 Found").header(
+            "location",
+            format!("/download/{}?hop={}", file_identifier, hop + 1),
+        );
+    }
+
+    let mut response = Response::new("200 OK")
+        .header("content-type", "application/octet-stream")
+        .header("accept-ranges", "bytes");
+    if !*state.omit_file_names.lock().unwrap() {
+        response = response.header(
+            "content-disposition",
+            format!("attachment; filename=\"{}\"", file.name),
+        );
+    }
+
+    if let Some(etag) = *state.etag.lock().unwrap() {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        std::hash::Hash::hash(&file.data, &mut hasher);
+        let value = format!("\"{:016x}\"", std::hash::Hasher::finish(&hasher));
+        let matches = request.header("if-none-match").is_some_and(|tags| {
+            tags.split(',')
+                .any(|tag| tag.trim().trim_start_matches("W/") == value)
+        });
+        let tag = match etag {
+            MockEtag::Strong => value,
+            MockEtag::Weak => format!("W/{}", value),
+        };
+        if matches && request.method == "GET" && !*state.ignore_if_none_match.lock().unwrap() {
+            return Response::new("304 Not Modified").header("etag", tag);
+        }
+        response = response.header("etag", tag);
+    }
+
+    let Some(range) = request.header("range") else {
+        let overshoot = *state.download_overshoot.lock().unwrap();
+        if request.method == "GET" && overshoot > 0 {
+            let mut body = file.data;
+            body.resize(body.len() + overshoot, 0);
+            return Response {
+                chunked: true,
+                ..response.body(body)
+            };
+        }
+        let cutoff = *state.download_cutoff.lock().unwrap();
+        if request.method == "GET" && cutoff.is_some() {
+            return Response {
+                cutoff,
+                ..response.body(file.data)
+            };
+        }
+        let chunked = *state.chunked_downloads.lock().unwrap();
+        return Response {
+            chunked: chunked && request.method == "GET",
+            ..response.body(file.data)
+        };
+    };
+
+    match parse_range(range, file.data.len() as u64) {
+        Some((start, end)) => Response {
+            status: "206 Partial Content",
+            ..response
+        }
+        .header(
+            "content-range",
+            format!("bytes {}-{}/{}", start, end, file.data.len()),
+        )
+        .body(&file.data[start as usize..=end as usize]),
+        None => Response::new("416 Range Not Satisfiable"),
+    }
+}
+
+/// Parses a single `bytes=start-end` range against a body of `len` bytes.
+fn parse_range(range: &str, len: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let start: u64 = start.parse().ok()?;
+    let end = match end {
+        "" => len.checked_sub(1)?,
+        end => end.parse::<u64>().ok()?.min(len.checked_sub(1)?),
+    };
+
+    (start <= end).then_some((start, end))
+}
+
+/// A `HEAD` only checks the token, without removing the file.
+fn delete(state: &State, file_identifier: &str, deletion_token: &str, remove: bool) -> Response {
+    let mut files = state.files.lock().unwrap();
+
+    match files.get(file_identifier) {
+        None => Response::new("404 Not Found"),
+        Some(file) if file.deletion_token != deletion_token => Response::new("401 Unauthorized"),
+        Some(_) => {
+            if remove {
+                files.remove(file_identifier);
+            }
+            Response::new("200 OK")
+        }
+    }
+}
+
+async fn handle_upgrade(mut stream: TcpStream, request: Request, leftover: Vec<u8>, state: &State) {
+    let (path, query) = request
+        .path
+        .split_once('?')
+        .unwrap_or((request.path.as_str(), ""));
+    let file_identifier = path
+        .strip_prefix("/api/upload/")
+        .map(str::to_string)
+        .filter(|file_identifier| state.files.lock().unwrap().contains_key(file_identifier));
+
+    let (Some(file_identifier), Some(key)) = (file_identifier, request.header("sec-websocket-key"))
+    else {
+        let _ = write_response(&mut stream, Response::new("404 Not Found"), false).await;
+        return;
+    };
+
+    // A resumed upload keeps the first `offset` bytes and continues after them.
+    let offset = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("offset="))
+        .and_then(|offset| offset.parse::<u64>().ok())
+        .unwrap_or(0);
+    let resumable = match state.files.lock().unwrap().get_mut(&file_identifier) {
+        Some(file) if offset <= file.data.len() as u64 => {
+            file.data.truncate(offset as usize);
+            file.chunks.retain(|chunk| chunk.offset < offset);
+            true
+        }
+        _ => false,
+    };
+    if !resumable {
+        let _ = write_response(&mut stream, Response::new("409 Conflict"), false).await;
+        return;
+    }
+
+    let accept = derive_accept_key(key.as_bytes());
+    let head = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nconnection: Upgrade\r\nupgrade: websocket\r\nsec-websocket-accept: {}\r\n\r\n",
+        accept
+    );
+    if stream.write_all(head.as_bytes()).await.is_err() {
+        return;
+    }
+
+    let mut ws = WebSocketStream::from_partially_read(stream, leftover, Role::Server, None).await;
+    let open = state.open_uploads.enter();
+    let ack_style = *state.ack.lock().unwrap();
+    let slow_down = state.slow_down.lock().unwrap().clone();
+    let disconnect_after = state.disconnect_after.lock().unwrap().take();
+    let nack_every = *state.nack_every.lock().unwrap();
+    let mut received = offset;
+    let mut acknowledged = 0;
+    let mut frames = 0;
+
+    while let Some(Ok(message)) = ws.next().await {
+        state.record_frame(&file_identifier, true, &message);
+        match message {
+            Message::Binary(data) => {
+                frames += 1;
+                if nack_every.is_some_and(|every| frames % every == 0) {
+                    let nack = Message::Text("NACK".into());
+                    state.record_frame(&file_identifier, false, &nack);
+                    if ws.send(nack).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                if let Some(file) = state.files.lock().unwrap().get_mut(&file_identifier) {
+                    file.chunks.push(MockChunk {
+                        offset: received,
+                        len: data.len(),
+                    });
+                    file.data.extend_from_slice(&data);
+                }
+                received += data.len() as u64;
+                let bandwidth = *state.bandwidth.lock().unwrap();
+                if let Some(bytes_per_second) = bandwidth.filter(|&bps| bps > 0) {
+                    let secs = data.len() as f64 / bytes_per_second as f64;
+                    tokio::time::sleep(Duration::from_secs_f64(secs)).await;
+                }
+                if let Some(frame) = &slow_down {
+                    let frame = Message::Text(frame.as_str().into());
+                    state.record_frame(&file_identifier, false, &frame);
+                    if ws.send(frame).await.is_err() {
+                        return;
+                    }
+                }
+                let ack = match ack_style {
+                    MockAck::Text => Message::Text("ACK".into()),
+                    MockAck::BinaryOffset => {
+                        Message::Binary(received.to_be_bytes().to_vec().into())
+                    }
+                    MockAck::TextOffset => Message::Text(format!("ACK:{}", received).into()),
+                    MockAck::TextOffsetShort => {
+                        Message::Text(format!("ACK:{}", received - 1).into())
+                    }
+                };
+                state.record_frame(&file_identifier, false, &ack);
+                if ws.send(ack).await.is_err() {
+                    return;
+                }
+                acknowledged += 1;
+                if disconnect_after == Some(acknowledged) {
+                    return;
+                }
+            }
+            Message::Close(_) => {
+                if let Some(file) = state.files.lock().unwrap().get_mut(&file_identifier) {
+                    file.complete = true;
+                }
+                drop(open);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let _ = ws.close(None).await;
+}
@@ -0,0 +1,238 @@
+//! Reliable uploads in one call, combining state files, resuming, retries
+//! and hash verification.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use crate::checksum::Checksum;
+use crate::{
+    HashAlgorithm, Progress, StreamShare, StreamShareError, UploadHandle, UploadOptions,
+    UploadResult, UploadState,
+};
+
+/// An upload that survives dropped connections, from
+/// [`StreamShare::transfer`].
+///
+/// [`start`](Self::start) hashes the file, uploads it with an
+/// [`UploadState`] on disk and, when the connection fails, waits and
+/// continues from the last acknowledged byte, or starts over if the server
+/// can't resume. Once done, a SHA-256 the server reports is compared with the
+/// local one. [`pause`](Self::pause), [`resume`](Self::resume) and
+/// [`cancel`](Self::cancel) work from another task while it runs.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), streamshare::StreamShareError> {
+/// use streamshare::StreamShare;
+///
+/// let client = StreamShare::builder().build()?;
+/// let transfer = client.transfer("video.mp4").max_attempts(10);
+/// let mut progress = transfer.progress();
+///
+/// let (result, _) = tokio::join!(transfer.start(), async {
+///     while progress.changed().await.is_ok() {
+///         let progress = *progress.borrow();
+///         println!("{}/{}", progress.uploaded, progress.total);
+///     }
+/// });
+/// println!("{}", result?.file_identifier);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Transfer<'a> {
+    client: &'a StreamShare,
+    file_path: String,
+    state_path: PathBuf,
+    max_attempts: u32,
+    retry_delay: Duration,
+    handle: UploadHandle,
+    abort: Arc<AtomicBool>,
+    cancelled: CancellationToken,
+    progress: watch::Sender<Progress>,
+}
+
+impl StreamShare {
+    /// Prepares a [`Transfer`] of `file_path`. Nothing is sent until
+    /// [`Transfer::start`].
+    pub fn transfer(&self, file_path: &str) -> Transfer<'_> {
+        Transfer {
+            client: self,
+            file_path: file_path.to_string(),
+            state_path: PathBuf::from(format!("{}.streamshare-state", file_path)),
+            max_attempts: 5,
+            retry_delay: Duration::from_secs(2),
+            handle: UploadHandle::new(),
+            abort: Arc::new(AtomicBool::new(false)),
+            cancelled: CancellationToken::new(),
+            progress: watch::Sender::new(Progress::default()),
+        }
+    }
+}
+
+impl Transfer<'_> {
+    /// Where the [`UploadState`] is kept. Defaults to the file's path with
+    /// `.streamshare-state` appended. A state file left there by an earlier
+    /// process is picked up by [`start`](Self::start).
+    pub fn state_path(mut self, state_path: impl Into<PathBuf>) -> Self {
+        self.state_path = state_path.into();
+        self
+    }
+
+    /// How many times the upload is tried in total before the last error is
+    /// returned. Defaults to 5.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// How long to wait after a failed attempt, doubling with each further
+    /// one. Defaults to 2 seconds.
+    pub fn retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Progress across all attempts; a resumed attempt continues from the
+    /// bytes already confirmed.
+    pub fn progress(&self) -> watch::Receiver<Progress> {
+        self.progress.subscribe()
+    }
+
+    /// Holds the upload before its next chunk, keeping the connection open.
+    pub fn pause(&self) {
+        self.handle.pause();
+    }
+
+    pub fn resume(&self) {
+        self.handle.resume();
+    }
+
+    /// Stops the transfer: [`start`](Self::start) returns
+    /// [`StreamShareError::Cancelled`] after deleting the partial file from
+    /// the server and removing the state file.
+    pub fn cancel(&self) {
+        self.abort.store(true, Ordering::Relaxed);
+        self.cancelled.cancel();
+        // A paused upload has to wake up to notice.
+        self.handle.resume();
+    }
+
+    /// Runs the transfer to the end.
+    ///
+    /// Only failures of the connection or an overloaded server are retried;
+    /// errors like a missing file or a rejected chunk are returned at once.
+    /// The state file is removed once the upload succeeds. Verification
+    /// needs a server that reports the SHA-256 it stored; otherwise the
+    /// result carries the local hash in
+    /// [`sha256`](UploadResult::sha256).
+    pub async fn start(&self) -> Result<UploadResult, StreamShareError> {
+        let local = Checksum::of_file(HashAlgorithm::Sha256, Path::new(&self.file_path)).await?;
+        let options = UploadOptions {
+            abort: Some(self.abort.clone()),
+            handle: Some(self.handle.clone()),
+            ..Default::default()
+        };
+        let callback = |uploaded, total| {
+            self.progress.send_replace(Progress { uploaded, total });
+        };
+
+        let mut attempt = 1;
+        let result = loop {
+            let result = if self.state_path.exists() {
+                match self
+                    .client
+                    .resume_with_options(&self.state_path, &options, callback)
+                    .await
+                {
+                    Err(StreamShareError::ResumeUnsupported) => {
+                        self.discard_state().await;
+                        continue;
+                    }
+                    result => result,
+                }
+            } else {
+                self.client
+                    .upload_with_state_options(
+                        &self.file_path,
+                        &self.state_path,
+                        &options,
+                        callback,
+                    )
+                    .await
+            };
+
+            match result {
+                Ok(result) => break result,
+                Err(StreamShareError::Cancelled) => {
+                    self.discard_state().await;
+                    return Err(StreamShareError::Cancelled);
+                }
+                Err(e) if attempt < self.max_attempts && is_transient(&e) => {
+                    if let Some(on_retry) = &self.client.on_retry {
+                        on_retry(attempt, &e);
+                    }
+                    let delay = self.retry_delay * 2u32.saturating_pow(attempt - 1);
+                    tokio::select! {
+                        _ = self.cancelled.cancelled() => {
+                            self.discard_state().await;
+                            return Err(StreamShareError::Cancelled);
+                        }
+                        _ = tokio::time::sleep(delay) => {}
+                    }
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let reported = result
+            .summary
+            .as_ref()
+            .and_then(|summary| summary.checksum.as_ref())
+            .filter(|checksum| checksum.algorithm == HashAlgorithm::Sha256);
+        if let Some(reported) = reported {
+            if reported.digest != local.digest {
+                return Err(StreamShareError::ChecksumMismatch {
+                    expected: local.digest,
+                    actual: reported.digest.clone(),
+                });
+            }
+        }
+        Ok(UploadResult {
+            sha256: Some(local.digest),
+            ..result
+        })
+    }
+
+    /// Deletes the partial upload a state file points at, then the file.
+    /// Both are best effort; the upload is abandoned either way.
+    async fn discard_state(&self) {
+        if let Ok(state) = UploadState::load(&self.state_path).await {
+            let _ = self
+                .client
+                .delete(&state.file_identifier, &state.deletion_token)
+                .await;
+        }
+        let _ = tokio::fs::remove_file(&self.state_path).await;
+    }
+}
+
+/// Whether an attempt failed for reasons another attempt may not hit.
+fn is_transient(error: &StreamShareError) -> bool {
+    match error {
+        StreamShareError::WebSocket(_)
+        | StreamShareError::ConnectionClosed
+        | StreamShareError::AckTimeout { .. }
+        | StreamShareError::HttpTimeout(_)
+        | StreamShareError::ConnectError(_)
+        | StreamShareError::DnsError(_)
+        | StreamShareError::ServerUnavailable { .. } => true,
+        StreamShareError::CreateFailed { status }
+        | StreamShareError::UploadSessionRejected { status } => status.is_server_error(),
+        _ => false,
+    }
+}
@@ -0,0 +1,342 @@
+//! Uploads whose chunks pass through a pipeline of transforms, e.g. to
+//! compress, encrypt and hash them on the way out.
+
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bytes::{Bytes, BytesMut};
+use futures::stream;
+use tokio::io::AsyncRead;
+use tokio_util::io::StreamReader;
+
+use crate::checksum::Hasher;
+use crate::{
+    read_chunk, Checksum, HashAlgorithm, StreamShare, StreamShareError, UploadOptions,
+    UploadResult, UploadSource,
+};
+
+/// One stage of [`StreamShare::upload_reader_transformed`].
+///
+/// Each chunk read from the source goes through every stage in order, the
+/// output of one being the input of the next, so the server stores what the
+/// last stage returns. A stage may hold data back, like a compressor does,
+/// and return it from [`finish`](Self::finish) once the source has ended.
+pub trait ChunkTransform: Send {
+    /// Transforms the next piece of data. Returning nothing is fine; the
+    /// later stages are then skipped for this chunk.
+    fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes>;
+
+    /// Whatever is still held back after the last chunk. Defaults to nothing.
+    fn finish(&mut self) -> io::Result<Bytes> {
+        Ok(Bytes::new())
+    }
+}
+
+/// Passes data through unchanged while hashing it.
+///
+/// Keep a clone to read the [`checksum`](Self::checksum) after the upload.
+/// Placed first in a pipeline it hashes the original data, placed last the
+/// bytes the server stores and a download returns.
+#[derive(Clone)]
+pub struct HashTransform {
+    hasher: Arc<Mutex<Hasher>>,
+}
+
+impl HashTransform {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        Self {
+            hasher: Arc::new(Mutex::new(Hasher::new(algorithm))),
+        }
+    }
+
+    /// The hash of everything that passed through so far.
+    pub fn checksum(&self) -> Checksum {
+        self.hasher.lock().unwrap().clone().finish()
+    }
+}
+
+impl ChunkTransform for HashTransform {
+    fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+        self.hasher.lock().unwrap().update(&chunk);
+        Ok(chunk)
+    }
+}
+
+/// Gzip-compresses the data, producing one gzip stream across all chunks.
+/// Needs the `compression` feature.
+///
+/// Put it before any encryption stage: encrypted data doesn't compress.
+#[cfg(feature = "compression")]
+pub struct GzipTransform {
+    encoder: flate2::write::GzEncoder<Vec<u8>>,
+}
+
+#[cfg(feature = "compression")]
+impl GzipTransform {
+    /// `level` goes from `0` (none) to `9` (best), like
+    /// [`compression_level`](crate::StreamShareBuilder::compression_level).
+    pub fn new(level: u32) -> Self {
+        Self {
+            encoder: flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(level)),
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+impl ChunkTransform for GzipTransform {
+    fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+        io::Write::write_all(&mut self.encoder, &chunk)?;
+        Ok(std::mem::take(self.encoder.get_mut()).into())
+    }
+
+    fn finish(&mut self) -> io::Result<Bytes> {
+        self.encoder.try_finish()?;
+        Ok(std::mem::take(self.encoder.get_mut()).into())
+    }
+}
+
+/// Encrypts the data with ChaCha20-Poly1305, so the server only ever sees
+/// ciphertext. Needs the `encryption` feature.
+///
+/// The output starts with a random 32-byte salt, from which each upload
+/// derives its own key, so one key can safely encrypt many files. Then
+/// every chunk follows as a sealed frame: a big-endian `u32` length, the
+/// ciphertext and a 16-byte tag. An empty frame marks the end, so a
+/// truncated download is detected. Restore the data with
+/// [`decrypt`](Self::decrypt).
+#[cfg(feature = "encryption")]
+pub struct EncryptTransform {
+    key: [u8; 32],
+    sealing: Option<ring::aead::LessSafeKey>,
+    counter: u64,
+}
+
+#[cfg(feature = "encryption")]
+const ENCRYPTION_SALT_LEN: usize = 32;
+
+#[cfg(feature = "encryption")]
+impl EncryptTransform {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self {
+            key,
+            sealing: None,
+            counter: 0,
+        }
+    }
+
+    /// Decrypts the complete output of an [`EncryptTransform`] with the same
+    /// key, e.g. a downloaded file. Fails with
+    /// [`io::ErrorKind::InvalidData`] if the data was altered, truncated or
+    /// encrypted with another key.
+    pub fn decrypt(key: &[u8; 32], data: &[u8]) -> io::Result<Vec<u8>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid encrypted data");
+        if data.len() < ENCRYPTION_SALT_LEN {
+            return Err(invalid());
+        }
+        let (salt, mut rest) = data.split_at(ENCRYPTION_SALT_LEN);
+        let opening = derive_key(key, salt);
+
+        let mut plain = Vec::with_capacity(data.len());
+        let mut counter = 0;
+        loop {
+            let len = rest
+                .get(..4)
+                .map(|len| u32::from_be_bytes(len.try_into().unwrap()) as usize)
+                .ok_or_else(invalid)?;
+            let frame = rest.get(4..4 + len).ok_or_else(invalid)?;
+            rest = &rest[4 + len..];
+            let last = rest.is_empty();
+            let mut frame = frame.to_vec();
+            let opened = opening
+                .open_in_place(
+                    frame_nonce(counter, last),
+                    ring::aead::Aad::empty(),
+                    &mut frame,
+                )
+                .map_err(|_| invalid())?;
+            // Only the end marker is sealed as the last frame, so a cut-off
+            // upload fails to open here.
+            if last {
+                return if opened.is_empty() {
+                    Ok(plain)
+                } else {
+                    Err(invalid())
+                };
+            }
+            plain.extend_from_slice(opened);
+            counter += 1;
+        }
+    }
+
+    fn seal(&mut self, data: &[u8], last: bool) -> io::Result<Bytes> {
+        let mut out = BytesMut::new();
+        let sealing = match &self.sealing {
+            Some(sealing) => sealing,
+            None => {
+                let mut salt = [0u8; ENCRYPTION_SALT_LEN];
+                ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut salt)
+                    .map_err(|_| io::Error::other("No random salt available"))?;
+                out.extend_from_slice(&salt);
+                self.sealing.insert(derive_key(&self.key, &salt))
+            }
+        };
+
+        let mut frame = data.to_vec();
+        sealing
+            .seal_in_place_append_tag(
+                frame_nonce(self.counter, last),
+                ring::aead::Aad::empty(),
+                &mut frame,
+            )
+            .map_err(|_| io::Error::other("Encryption failed"))?;
+        self.counter += 1;
+        out.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        out.extend_from_slice(&frame);
+        Ok(out.freeze())
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl ChunkTransform for EncryptTransform {
+    fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+        self.seal(&chunk, false)
+    }
+
+    fn finish(&mut self) -> io::Result<Bytes> {
+        self.seal(&[], true)
+    }
+}
+
+/// The per-upload key for `salt`.
+#[cfg(feature = "encryption")]
+fn derive_key(key: &[u8; 32], salt: &[u8]) -> ring::aead::LessSafeKey {
+    let prk = ring::hkdf::Salt::new(ring::hkdf::HKDF_SHA256, salt).extract(key);
+    let okm = prk
+        .expand(&[b"streamshare chunk"], &ring::aead::CHACHA20_POLY1305)
+        .expect("32 bytes are a valid HKDF output length");
+    ring::aead::LessSafeKey::new(ring::aead::UnboundKey::from(okm))
+}
+
+/// Frame counter followed by whether it's the end marker, in the style of
+/// the STREAM construction.
+#[cfg(feature = "encryption")]
+fn frame_nonce(counter: u64, last: bool) -> ring::aead::Nonce {
+    let mut nonce = [0u8; ring::aead::NONCE_LEN];
+    nonce[3..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    ring::aead::Nonce::assume_unique_for_key(nonce)
+}
+
+/// Runs `chunk` through `transforms` in order.
+fn apply(transforms: &mut [Box<dyn ChunkTransform>], mut chunk: Bytes) -> io::Result<Bytes> {
+    for transform in transforms {
+        if chunk.is_empty() {
+            break;
+        }
+        chunk = transform.transform(chunk)?;
+    }
+    Ok(chunk)
+}
+
+/// Reads `reader` in chunks and runs them through the pipeline.
+struct Pipeline<R> {
+    reader: R,
+    transforms: Vec<Box<dyn ChunkTransform>>,
+    buffer: Vec<u8>,
+    read: Arc<AtomicU64>,
+    finished: bool,
+}
+
+impl<R: AsyncRead + Unpin> Pipeline<R> {
+    async fn next(&mut self) -> io::Result<Option<Bytes>> {
+        while !self.finished {
+            let n = read_chunk(&mut self.reader, &mut self.buffer, true).await?;
+            let out = if n > 0 {
+                self.read.fetch_add(n as u64, Ordering::Relaxed);
+                apply(
+                    &mut self.transforms,
+                    Bytes::copy_from_slice(&self.buffer[..n]),
+                )?
+            } else {
+                // Each stage's leftovers still pass through the stages after it.
+                self.finished = true;
+                let mut out = BytesMut::new();
+                for i in 0..self.transforms.len() {
+                    let rest = self.transforms[i].finish()?;
+                    out.extend_from_slice(&apply(&mut self.transforms[i + 1..], rest)?);
+                }
+                out.freeze()
+            };
+            if !out.is_empty() {
+                return Ok(Some(out));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl StreamShare {
+    /// Uploads everything read from `reader` after running each chunk
+    /// through `transforms`, in order.
+    ///
+    /// The server stores the output of the last stage, so a download returns
+    /// those bytes; undo the stages in reverse order to get the original,
+    /// e.g. `EncryptTransform::decrypt` and then gunzip for
+    /// `[GzipTransform, EncryptTransform]`. As the transformed size isn't
+    /// known up front, none is sent with the create request. The callback
+    /// receives the bytes read from `reader` and `size`, or `0` without one.
+    ///
+    /// ```no_run
+    /// use streamshare::{ChunkTransform, HashAlgorithm, HashTransform, StreamShare};
+    ///
+    /// # async fn run() -> Result<(), streamshare::StreamShareError> {
+    /// let client = StreamShare::builder().build()?;
+    /// let file = tokio::fs::File::open("notes.txt").await?;
+    /// let hash = HashTransform::new(HashAlgorithm::Sha256);
+    /// let transforms: Vec<Box<dyn ChunkTransform>> = vec![Box::new(hash.clone())];
+    /// client
+    ///     .upload_reader_transformed("notes.txt", file, None, transforms, |_, _| {})
+    ///     .await?;
+    /// println!("{}", hash.checksum().digest);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn upload_reader_transformed<R, F>(
+        &self,
+        file_name: &str,
+        reader: R,
+        size: Option<u64>,
+        transforms: Vec<Box<dyn ChunkTransform>>,
+        mut callback: F,
+    ) -> Result<UploadResult, StreamShareError>
+    where
+        R: AsyncRead + Unpin,
+        F: FnMut(u64, u64),
+    {
+        let read = Arc::new(AtomicU64::new(0));
+        let pipeline = Pipeline {
+            reader,
+            transforms,
+            buffer: vec![0u8; self.chunk_size],
+            read: read.clone(),
+            finished: false,
+        };
+        let reader = StreamReader::new(Box::pin(stream::try_unfold(
+            pipeline,
+            |mut pipeline| async move {
+                Ok::<_, io::Error>(pipeline.next().await?.map(|out| (out, pipeline)))
+            },
+        )));
+
+        self.upload_stream(
+            file_name,
+            UploadSource::Reader(reader),
+            None,
+            None,
+            &UploadOptions::default(),
+            |_, _, _| callback(read.load(Ordering::Relaxed), size.unwrap_or(0)),
+        )
+        .await
+    }
+}
@@ -0,0 +1,166 @@
+//! The HTTP layer behind create, update, delete and info requests,
+//! replaceable to record, rewrite or fake them. Uploads and downloads keep
+//! using `reqwest` and the WebSocket directly.
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Method, StatusCode};
+
+use crate::StreamShareError;
+
+/// A request as the client would send it, auth headers included.
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    /// Full URL including the query string.
+    pub url: String,
+    pub headers: HeaderMap,
+    /// Empty for requests without a body.
+    pub body: Bytes,
+}
+
+/// A response with its body read in full.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+/// Sends the plain HTTP requests of [`create`](crate::StreamShare::create_raw),
+/// [`update`](crate::StreamShare::update), [`delete`](crate::StreamShare::delete),
+/// [`admin_delete`](crate::StreamShare::admin_delete) and
+/// [`info`](crate::StreamShare::info).
+///
+/// Set one with
+/// [`http_transport`](crate::StreamShareBuilder::http_transport); the
+/// default is a [`ReqwestTransport`]. Waiting out `503` responses and the
+/// per-host connection limit stay with the client, so an implementation only
+/// makes a single attempt.
+///
+/// ```no_run
+/// use futures::future::BoxFuture;
+/// use streamshare::{HttpRequest, HttpResponse, HttpTransport, ReqwestTransport};
+/// use streamshare::{StreamShare, StreamShareError};
+///
+/// struct Logged(ReqwestTransport);
+///
+/// impl HttpTransport for Logged {
+///     fn execute(
+///         &self,
+///         request: HttpRequest,
+///     ) -> BoxFuture<'_, Result<HttpResponse, StreamShareError>> {
+///         println!("{} {}", request.method, request.url);
+///         self.0.execute(request)
+///     }
+/// }
+///
+/// let client = StreamShare::builder()
+///     .http_transport(Logged(ReqwestTransport::new(reqwest::Client::new())))
+///     .build()?;
+/// # Ok::<(), StreamShareError>(())
+/// ```
+pub trait HttpTransport: Send + Sync {
+    fn execute(
+        &self,
+        request: HttpRequest,
+    ) -> BoxFuture<'_, Result<HttpResponse, StreamShareError>>;
+}
+
+/// Sends requests with a `reqwest` [`Client`].
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+impl HttpTransport for ReqwestTransport {
+    fn execute(
+        &self,
+        request: HttpRequest,
+    ) -> BoxFuture<'_, Result<HttpResponse, StreamShareError>> {
+        Box::pin(async move {
+            let mut builder = self
+                .client
+                .request(request.method, request.url)
+                .headers(request.headers);
+            if !request.body.is_empty() {
+                builder = builder.body(request.body);
+            }
+            let res = builder.send().await?;
+            Ok(HttpResponse {
+                status: res.status(),
+                headers: res.headers().clone(),
+                body: res.bytes().await?,
+            })
+        })
+    }
+}
+
+/// Sends requests through a `reqwest-middleware` stack. Used for create,
+/// update, delete and info requests when
+/// [`middleware`](crate::StreamShareBuilder::middleware) is set.
+#[cfg(feature = "middleware")]
+#[derive(Debug, Clone)]
+pub struct MiddlewareTransport {
+    client: reqwest_middleware::ClientWithMiddleware,
+}
+
+#[cfg(feature = "middleware")]
+impl MiddlewareTransport {
+    pub fn new(client: reqwest_middleware::ClientWithMiddleware) -> Self {
+        Self { client }
+    }
+}
+
+#[cfg(feature = "middleware")]
+impl HttpTransport for MiddlewareTransport {
+    fn execute(
+        &self,
+        request: HttpRequest,
+    ) -> BoxFuture<'_, Result<HttpResponse, StreamShareError>> {
+        Box::pin(async move {
+            let mut builder = self
+                .client
+                .request(request.method, request.url)
+                .headers(request.headers);
+            if !request.body.is_empty() {
+                builder = builder.body(request.body);
+            }
+            let res = builder.send().await?;
+            Ok(HttpResponse {
+                status: res.status(),
+                headers: res.headers().clone(),
+                body: res.bytes().await?,
+            })
+        })
+    }
+}
+
+impl HttpRequest {
+    pub(crate) fn from_reqwest(request: &reqwest::Request) -> Self {
+        Self {
+            method: request.method().clone(),
+            url: request.url().to_string(),
+            headers: request.headers().clone(),
+            body: request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map(Bytes::copy_from_slice)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl HttpResponse {
+    /// Parses the body as JSON.
+    pub(crate) fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, StreamShareError> {
+        Ok(serde_json::from_slice(&self.body).map_err(std::io::Error::from)?)
+    }
+}
@@ -0,0 +1,233 @@
+//! Builds every URL the client talks to, so scheme, host and path handling
+//! live in one place.
+
+use crate::StreamShareError;
+
+/// Whether the client talks to the server over TLS.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scheme {
+    /// `https` for requests and `wss` for uploads.
+    #[default]
+    Https,
+    /// Plain `http` and `ws`, e.g. for a local server.
+    Http,
+}
+
+impl Scheme {
+    pub(crate) fn http(self) -> &'static str {
+        match self {
+            Scheme::Https => "https",
+            Scheme::Http => "http",
+        }
+    }
+
+    pub(crate) fn ws(self) -> &'static str {
+        match self {
+            Scheme::Https => "wss",
+            Scheme::Http => "ws",
+        }
+    }
+}
+
+/// `true` is [`Scheme::Https`], matching
+/// [`secure`](crate::StreamShareBuilder::secure).
+impl From<bool> for Scheme {
+    fn from(secure: bool) -> Self {
+        if secure {
+            Scheme::Https
+        } else {
+            Scheme::Http
+        }
+    }
+}
+
+/// Where requests of one kind go: the HTTP API or the upload WebSocket.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Endpoint<'a> {
+    pub(crate) scheme: &'static str,
+    /// Host with an optional port, e.g. `example.com:8080` or `[::1]:8080`.
+    pub(crate) host: &'a str,
+    /// Prefix for every path, e.g. `/gateway`. Empty for none.
+    pub(crate) base_path: &'a str,
+}
+
+impl Endpoint<'_> {
+    /// Joins `path`, which starts with `/`, onto the endpoint.
+    pub(crate) fn url(&self, path: &str) -> String {
+        format!(
+            "{}://{}{}{}",
+            self.scheme,
+            self.host,
+            self.base_path.trim_end_matches('/'),
+            path
+        )
+    }
+
+    fn segments(&self, segments: &[&str]) -> Result<String, StreamShareError> {
+        let mut path = String::new();
+        for segment in segments {
+            path.push('/');
+            path.push_str(&encode_segment(segment)?);
+        }
+        Ok(self.url(&path))
+    }
+}
+
+pub(crate) fn create_url(api: &Endpoint) -> String {
+    api.url("/api/create")
+}
+
+pub(crate) fn upload_ws_url(
+    ws: &Endpoint,
+    file_identifier: &str,
+) -> Result<String, StreamShareError> {
+    ws.segments(&["api", "upload", file_identifier])
+}
+
+pub(crate) fn download_url(
+    api: &Endpoint,
+    file_identifier: &str,
+) -> Result<String, StreamShareError> {
+    api.segments(&["download", file_identifier])
+}
+
+pub(crate) fn delete_url(
+    api: &Endpoint,
+    file_identifier: &str,
+    deletion_token: &str,
+) -> Result<String, StreamShareError> {
+    api.segments(&["api", "delete", file_identifier, deletion_token])
+}
+
+pub(crate) fn update_url(
+    api: &Endpoint,
+    file_identifier: &str,
+    deletion_token: &str,
+) -> Result<String, StreamShareError> {
+    api.segments(&["api", "update", file_identifier, deletion_token])
+}
+
+pub(crate) fn admin_delete_url(
+    api: &Endpoint,
+    file_identifier: &str,
+) -> Result<String, StreamShareError> {
+    api.segments(&["api", "admin", "delete", file_identifier])
+}
+
+/// Percent-encodes everything but RFC 3986 unreserved characters, so an
+/// identifier can't add path segments or a query.
+///
+/// Empty and dot-only segments are rejected: URL parsing resolves `.` and
+/// `..` even when percent-encoded, so they would leave the intended path.
+fn encode_segment(segment: &str) -> Result<String, StreamShareError> {
+    if segment.is_empty() || segment == "." || segment == ".." {
+        return Err(StreamShareError::InvalidSegment {
+            segment: segment.to_string(),
+        });
+    }
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint<'a>(scheme: &'static str, host: &'a str, base_path: &'a str) -> Endpoint<'a> {
+        Endpoint {
+            scheme,
+            host,
+            base_path,
+        }
+    }
+
+    #[test]
+    fn builds_plain_urls() {
+        let api = endpoint("https", "streamshare.wireway.ch", "");
+        let ws = endpoint("wss", "streamshare.wireway.ch", "");
+        assert_eq!(
+            create_url(&api),
+            "https://streamshare.wireway.ch/api/create"
+        );
+        assert_eq!(
+            upload_ws_url(&ws, "abc123").unwrap(),
+            "wss://streamshare.wireway.ch/api/upload/abc123"
+        );
+        assert_eq!(
+            download_url(&api, "abc123").unwrap(),
+            "https://streamshare.wireway.ch/download/abc123"
+        );
+        assert_eq!(
+            delete_url(&api, "abc123", "token").unwrap(),
+            "https://streamshare.wireway.ch/api/delete/abc123/token"
+        );
+    }
+
+    #[test]
+    fn encodes_special_characters() {
+        let api = endpoint("http", "localhost:8080", "");
+        assert_eq!(
+            download_url(&api, "a/b?c#d e").unwrap(),
+            "http://localhost:8080/download/a%2Fb%3Fc%23d%20e"
+        );
+        assert_eq!(
+            delete_url(&api, "ü", "x%y").unwrap(),
+            "http://localhost:8080/api/delete/%C3%BC/x%25y"
+        );
+        assert_eq!(
+            download_url(&api, "v1.2_beta-~").unwrap(),
+            "http://localhost:8080/download/v1.2_beta-~"
+        );
+    }
+
+    #[test]
+    fn keeps_ipv6_hosts() {
+        let api = endpoint("http", "[::1]:8080", "");
+        let ws = endpoint("ws", "[::1]:8080", "");
+        assert_eq!(create_url(&api), "http://[::1]:8080/api/create");
+        assert_eq!(
+            upload_ws_url(&ws, "abc").unwrap(),
+            "ws://[::1]:8080/api/upload/abc"
+        );
+    }
+
+    #[test]
+    fn joins_base_paths() {
+        for base_path in ["/gateway", "/gateway/"] {
+            let api = endpoint("https", "example.com", base_path);
+            assert_eq!(create_url(&api), "https://example.com/gateway/api/create");
+            assert_eq!(
+                download_url(&api, "abc").unwrap(),
+                "https://example.com/gateway/download/abc"
+            );
+            assert_eq!(
+                admin_delete_url(&api, "abc").unwrap(),
+                "https://example.com/gateway/api/admin/delete/abc"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_segments_that_leave_the_path() {
+        let api = endpoint("https", "example.com", "");
+        for segment in ["", ".", ".."] {
+            assert!(matches!(
+                download_url(&api, segment),
+                Err(StreamShareError::InvalidSegment { .. })
+            ));
+            assert!(matches!(
+                delete_url(&api, "abc", segment),
+                Err(StreamShareError::InvalidSegment { .. })
+            ));
+        }
+        assert!(download_url(&api, "...").is_ok());
+        assert!(download_url(&api, ".hidden").is_ok());
+    }
+}
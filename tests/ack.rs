@@ -0,0 +1,108 @@
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use streamshare::testing::{MockAck, MockServer};
+use streamshare::{BinaryAck, StreamShareError};
+
+const DATA: &[u8] = &[7u8; 5000];
+
+async fn upload(server: &MockServer, binary_ack: BinaryAck) -> Result<String, StreamShareError> {
+    let client = server
+        .builder()
+        .chunk_size(1024)
+        .binary_ack(binary_ack)
+        .build()
+        .unwrap();
+    let result = client
+        .upload_bytes("data.bin", Bytes::from_static(DATA), |_, _| {})
+        .await?;
+    Ok(result.file_identifier)
+}
+
+#[tokio::test]
+async fn text_acks_are_the_default() {
+    let server = MockServer::start().await;
+
+    let id = upload(&server, BinaryAck::default()).await.unwrap();
+
+    assert_eq!(server.file(&id).unwrap().data, DATA);
+}
+
+#[tokio::test]
+async fn binary_offset_acks_are_accepted() {
+    let server = MockServer::start().await;
+    server.set_ack(MockAck::BinaryOffset);
+
+    let mut progress = Vec::new();
+    let client = server
+        .builder()
+        .chunk_size(1024)
+        .binary_ack(BinaryAck::OffsetBe)
+        .build()
+        .unwrap();
+    let result = client
+        .upload_bytes("data.bin", Bytes::from_static(DATA), |uploaded, _| {
+            progress.push(uploaded)
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(server.file(&result.file_identifier).unwrap().data, DATA);
+    assert_eq!(progress, [1024, 2048, 3072, 4096, 5000]);
+}
+
+#[tokio::test]
+async fn binary_acks_fail_unless_enabled() {
+    let server = MockServer::start().await;
+    server.set_ack(MockAck::BinaryOffset);
+
+    let error = upload(&server, BinaryAck::Reject).await.unwrap_err();
+
+    assert!(matches!(error, StreamShareError::UnexpectedMessage(_)));
+}
+
+#[tokio::test]
+async fn offsets_beyond_what_was_sent_fail() {
+    let server = MockServer::start().await;
+    server.set_ack(MockAck::BinaryOffset);
+
+    // Read as little-endian, the big-endian offsets are far too large.
+    let error = upload(&server, BinaryAck::OffsetLe).await.unwrap_err();
+
+    assert!(matches!(error, StreamShareError::UnexpectedMessage(_)));
+}
+
+#[tokio::test]
+async fn slow_down_frames_pause_before_the_next_chunk() {
+    let server = MockServer::start().await;
+    server.set_slow_down(Some("THROTTLE:100"));
+    let client = server
+        .builder()
+        .chunk_size(1024)
+        .slow_down_token("THROTTLE")
+        .build()
+        .unwrap();
+
+    let started = Instant::now();
+    let result = client
+        .upload_bytes("data.bin", Bytes::from_static(DATA), |_, _| {})
+        .await
+        .unwrap();
+
+    // Five chunks, with a pause before each of the last four.
+    assert!(started.elapsed() >= Duration::from_millis(400));
+    assert_eq!(server.file(&result.file_identifier).unwrap().data, DATA);
+}
+
+#[tokio::test]
+async fn slow_down_frames_are_unexpected_by_default() {
+    let server = MockServer::start().await;
+    server.set_slow_down(Some("THROTTLE:100"));
+
+    let result = upload(&server, BinaryAck::default()).await;
+
+    assert!(matches!(
+        result,
+        Err(StreamShareError::ChunkRejected { .. })
+    ));
+}
@@ -0,0 +1,78 @@
+//! Compares how much a reader upload allocates against `upload_bytes`.
+//!
+//! The counting allocator is process-wide, so this file holds a single test
+//! to keep other uploads from running alongside it.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bytes::Bytes;
+use streamshare::testing::MockServer;
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size.saturating_sub(layout.size()), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const SIZE: usize = 8 * 1024 * 1024;
+
+#[tokio::test]
+async fn upload_bytes_skips_the_per_chunk_copy() {
+    let server = MockServer::start().await;
+    let client = server.client();
+    let data = Bytes::from(vec![7u8; SIZE]);
+
+    // Warm up the connection pool and lazily initialised state so both
+    // measured uploads start from the same place.
+    client
+        .upload_bytes("warmup.bin", data.clone(), |_, _| {})
+        .await
+        .unwrap();
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let result = client
+        .upload_reader("reader.bin", &data[..], Some(SIZE as u64), |_, _| {})
+        .await
+        .unwrap();
+    let reader = ALLOCATED.load(Ordering::Relaxed) - before;
+    assert_eq!(
+        server.file(&result.file_identifier).unwrap().data.len(),
+        SIZE
+    );
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let result = client
+        .upload_bytes("bytes.bin", data.clone(), |_, _| {})
+        .await
+        .unwrap();
+    let shared = ALLOCATED.load(Ordering::Relaxed) - before;
+    assert_eq!(
+        server.file(&result.file_identifier).unwrap().data.len(),
+        SIZE
+    );
+
+    // The reader path copies every chunk out of its read buffer once; the
+    // Bytes path hands slices of the input straight to the socket.
+    assert!(
+        reader >= shared + SIZE,
+        "reader upload allocated {reader} bytes, upload_bytes {shared}"
+    );
+}
@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use streamshare::testing::MockServer;
+use streamshare::{DeleteOutcome, StreamShareError};
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("streamshare-batch-{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn exhausted_budget_fails_remaining_downloads() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("exhausted");
+    let items: Vec<(String, String)> = (0..4)
+        .map(|i| {
+            let (id, _) = server.insert_file(&format!("{}.txt", i), "data");
+            (id, format!("{}/", dir.display()))
+        })
+        .collect();
+    let client = server
+        .builder()
+        .max_maintenance_wait(Duration::from_secs(60))
+        .batch_retry_budget(2)
+        .build()
+        .unwrap();
+    server.set_unavailable(usize::MAX);
+
+    let results = client
+        .download_many(&items, false, 1, |_, _, _, _| {})
+        .await;
+
+    assert!(results
+        .iter()
+        .all(|result| matches!(result, Err(StreamShareError::RetryBudgetExhausted))));
+    // The first try and two retries of the first item; the others never start.
+    assert_eq!(server.recorder().requests().len(), 3);
+}
+
+#[tokio::test]
+async fn retries_within_budget_succeed() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("within");
+    let items: Vec<(String, String)> = (0..3)
+        .map(|i| {
+            let (id, _) = server.insert_file(&format!("{}.txt", i), "data");
+            (id, format!("{}/", dir.display()))
+        })
+        .collect();
+    let client = server
+        .builder()
+        .max_maintenance_wait(Duration::from_secs(60))
+        .batch_retry_budget(2)
+        .build()
+        .unwrap();
+    server.set_unavailable(2);
+
+    let results = client
+        .download_many(&items, false, 1, |_, _, _, _| {})
+        .await;
+
+    for (i, result) in results.iter().enumerate() {
+        let path = result.as_ref().unwrap();
+        assert_eq!(path, &dir.join(format!("{}.txt", i)));
+        assert_eq!(std::fs::read(path).unwrap(), b"data");
+    }
+}
+
+#[tokio::test]
+async fn budget_is_shared_by_uploads() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("uploads");
+    let paths: Vec<String> = (0..3)
+        .map(|i| {
+            let path = dir.join(format!("{}.txt", i));
+            std::fs::write(&path, "data").unwrap();
+            path.to_str().unwrap().to_string()
+        })
+        .collect();
+    let client = server
+        .builder()
+        .max_maintenance_wait(Duration::from_secs(60))
+        .batch_retry_budget(1)
+        .build()
+        .unwrap();
+    server.set_unavailable(usize::MAX);
+
+    let results = client.upload_many(&paths, 1, |_, _, _, _| {}).await;
+
+    assert!(results
+        .iter()
+        .all(|result| matches!(result, Err(StreamShareError::RetryBudgetExhausted))));
+    assert_eq!(server.file_count(), 0);
+}
+
+#[tokio::test]
+async fn uploads_and_deletes_many() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("roundtrip");
+    let paths: Vec<String> = (0..3)
+        .map(|i| {
+            let path = dir.join(format!("{}.txt", i));
+            std::fs::write(&path, vec![b'x'; 1000 * (i + 1)]).unwrap();
+            path.to_str().unwrap().to_string()
+        })
+        .collect();
+    let client = server.client();
+
+    let mut overall = 0;
+    let results = client
+        .upload_many(&paths, 2, |_, _, _, total| overall = total)
+        .await;
+    assert_eq!(overall, 6000);
+    let items: Vec<(String, String)> = results
+        .into_iter()
+        .map(|result| {
+            let result = result.unwrap();
+            (result.file_identifier, result.deletion_token)
+        })
+        .collect();
+    for (i, (file_identifier, _)) in items.iter().enumerate() {
+        assert_eq!(
+            server.file(file_identifier).unwrap().data.len(),
+            1000 * (i + 1)
+        );
+    }
+
+    let deleted = client.delete_many(&items, 2).await;
+    assert!(deleted
+        .iter()
+        .all(|outcome| matches!(outcome, Ok(DeleteOutcome::Deleted))));
+    assert_eq!(server.file_count(), 0);
+}
+
+#[tokio::test]
+async fn uploads_respect_the_per_host_connection_limit() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("per-host");
+    let paths: Vec<String> = (0..6)
+        .map(|i| {
+            let path = dir.join(format!("{}.bin", i));
+            std::fs::write(&path, vec![1u8; 32 * 1024]).unwrap();
+            path.display().to_string()
+        })
+        .collect();
+    server.set_bandwidth(Some(256 * 1024));
+    let client = server
+        .builder()
+        .max_connections_per_host(2)
+        .build()
+        .unwrap();
+
+    let results = client.upload_many(&paths, 6, |_, _, _, _| {}).await;
+
+    assert!(results.iter().all(Result::is_ok));
+    assert_eq!(server.peak_open_uploads(), 2);
+}
+
+#[tokio::test]
+async fn uploads_without_a_limit_run_in_parallel() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("unlimited");
+    let paths: Vec<String> = (0..4)
+        .map(|i| {
+            let path = dir.join(format!("{}.bin", i));
+            std::fs::write(&path, vec![1u8; 32 * 1024]).unwrap();
+            path.display().to_string()
+        })
+        .collect();
+    server.set_bandwidth(Some(256 * 1024));
+
+    let results = server
+        .client()
+        .upload_many(&paths, 4, |_, _, _, _| {})
+        .await;
+
+    assert!(results.iter().all(Result::is_ok));
+    assert!(server.peak_open_uploads() > 2);
+}
+
+#[tokio::test]
+async fn downloads_hold_their_connection_slot_until_the_body_is_read() {
+    let server = MockServer::start().await;
+    let ids: Vec<String> = (0..5)
+        .map(|i| {
+            server
+                .insert_file(&format!("{}.bin", i), vec![i as u8; 64 * 1024])
+                .0
+        })
+        .collect();
+    server.set_response_bandwidth(Some(256 * 1024));
+    let client = server
+        .builder()
+        .max_connections_per_host(2)
+        .build()
+        .unwrap();
+
+    let results = futures::future::join_all(ids.iter().map(|id| {
+        let client = &client;
+        async move {
+            let mut data = Vec::new();
+            client.download_to(id, &mut data).await.map(|_| data)
+        }
+    }))
+    .await;
+
+    for (i, result) in results.into_iter().enumerate() {
+        assert_eq!(result.unwrap(), vec![i as u8; 64 * 1024]);
+    }
+    assert_eq!(server.peak_open_requests(), 2);
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "crc32")]
+
+use streamshare::testing::MockServer;
+use streamshare::{hash_file, HashAlgorithm};
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("streamshare-checksum-{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn crc32_round_trips() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("round-trip");
+    let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let source = dir.join("source.bin");
+    std::fs::write(&source, &data).unwrap();
+    let client = server
+        .builder()
+        .hash_algorithm(HashAlgorithm::Crc32)
+        .compute_checksum(true)
+        .verify_on_disk(true)
+        .build()
+        .unwrap();
+
+    let result = client
+        .upload(&source.display().to_string(), |_, _| {})
+        .await
+        .unwrap();
+    let download_dir = dir.join("downloaded");
+    std::fs::create_dir_all(&download_dir).unwrap();
+    client
+        .download(
+            &result.file_identifier,
+            &format!("{}/", download_dir.display()),
+            false,
+        )
+        .await
+        .unwrap();
+
+    let uploaded = result.checksum.unwrap();
+    assert_eq!(uploaded.algorithm, HashAlgorithm::Crc32);
+    assert_eq!(uploaded.digest, format!("{:08x}", crc32fast::hash(&data)));
+    let downloaded = hash_file(
+        &download_dir.join("source.bin").display().to_string(),
+        HashAlgorithm::Crc32,
+    )
+    .await
+    .unwrap();
+    assert_eq!(downloaded, uploaded.digest);
+}
+
+#[tokio::test]
+async fn crc32_is_sent_under_its_name() {
+    let server = MockServer::start().await;
+    let client = server
+        .builder()
+        .hash_algorithm(HashAlgorithm::Crc32)
+        .send_checksum(true)
+        .build()
+        .unwrap();
+
+    client
+        .upload_bytes("data.bin", vec![9u8; 4096].into(), |_, _| {})
+        .await
+        .unwrap();
+
+    let create = &server.recorder().requests_to("/api/create")[0];
+    assert_eq!(
+        create.json().unwrap()["crc32"],
+        format!("{:08x}", crc32fast::hash(&[9u8; 4096]))
+    );
+}
@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use streamshare::chunk_count;
+use streamshare::testing::MockServer;
+
+#[test]
+fn counts_exact_multiples_remainders_and_zero() {
+    assert_eq!(chunk_count(0, 1024), 0);
+    assert_eq!(chunk_count(1024, 1024), 1);
+    assert_eq!(chunk_count(4096, 1024), 4);
+    assert_eq!(chunk_count(1, 1024), 1);
+    assert_eq!(chunk_count(4097, 1024), 5);
+    assert_eq!(chunk_count(u64::MAX, 1), u64::MAX);
+}
+
+#[tokio::test]
+async fn matches_the_frames_of_an_upload() {
+    let server = MockServer::start().await;
+    let client = server.builder().chunk_size(1000).build().unwrap();
+
+    for size in [0, 1000, 3000, 3001] {
+        let result = client
+            .upload_bytes("data.bin", Bytes::from(vec![0u8; size]), |_, _| {})
+            .await
+            .unwrap();
+        let file = server.file(&result.file_identifier).unwrap();
+        assert_eq!(file.chunks.len() as u64, chunk_count(size as u64, 1000));
+        if let Some(last) = file.chunks.last() {
+            assert_eq!(last.len, size - (file.chunks.len() - 1) * 1000);
+        }
+    }
+}
+
+const MAX_CHUNK: usize = 256 * 1024;
+
+/// Chunk lengths of an adaptive upload of `size` bytes with `deadline` as
+/// overall timeout, leaving out the final one, which may be short.
+async fn adaptive_chunks(server: &MockServer, size: usize, deadline: Duration) -> Vec<usize> {
+    let client = server
+        .builder()
+        .chunk_size(MAX_CHUNK)
+        .overall_timeout(deadline)
+        .adaptive_chunking(true)
+        .build()
+        .unwrap();
+
+    let result = client
+        .upload_bytes("data.bin", Bytes::from(vec![0u8; size]), |_, _| {})
+        .await
+        .unwrap();
+
+    let file = server.file(&result.file_identifier).unwrap();
+    assert_eq!(file.data.len(), size);
+    let mut lens: Vec<usize> = file.chunks.iter().map(|chunk| chunk.len).collect();
+    lens.pop();
+    lens
+}
+
+#[tokio::test]
+async fn adaptive_chunks_grow_on_a_fast_link() {
+    let server = MockServer::start().await;
+
+    let lens = adaptive_chunks(&server, 2 * 1024 * 1024, Duration::from_secs(60)).await;
+
+    assert_eq!(lens[0], MAX_CHUNK / 4);
+    assert!(lens.windows(2).all(|pair| pair[1] >= pair[0]), "{:?}", lens);
+    assert_eq!(*lens.last().unwrap(), MAX_CHUNK);
+}
+
+#[tokio::test]
+async fn adaptive_chunks_shrink_on_a_slow_link() {
+    let server = MockServer::start().await;
+    server.set_bandwidth(Some(200 * 1024));
+
+    let lens = adaptive_chunks(&server, 256 * 1024, Duration::from_secs(3)).await;
+
+    assert_eq!(lens[0], MAX_CHUNK / 4);
+    assert!(lens[1..].iter().all(|&len| len < lens[0]), "{:?}", lens);
+}
+
+#[tokio::test]
+async fn adaptive_chunks_follow_a_changing_link() {
+    let server = MockServer::start().await;
+    server.set_bandwidth(Some(200 * 1024));
+
+    let (lens, ()) = tokio::join!(
+        adaptive_chunks(&server, 2 * 1024 * 1024, Duration::from_secs(3)),
+        async {
+            tokio::time::sleep(Duration::from_millis(600)).await;
+            server.set_bandwidth(None);
+        }
+    );
+
+    // Shrinks while the link is slow, then grows back once it is fast.
+    let smallest = (0..lens.len()).min_by_key(|&i| lens[i]).unwrap();
+    assert!(lens[smallest] < lens[0], "{:?}", lens);
+    assert!(lens[smallest..].contains(&MAX_CHUNK), "{:?}", lens);
+}
+
+#[tokio::test]
+async fn fixed_chunks_without_adaptive_chunking() {
+    let server = MockServer::start().await;
+    server.set_bandwidth(Some(200 * 1024));
+    let client = server
+        .builder()
+        .chunk_size(64 * 1024)
+        .overall_timeout(Duration::from_secs(3))
+        .build()
+        .unwrap();
+
+    let result = client
+        .upload_bytes("data.bin", Bytes::from(vec![0u8; 256 * 1024]), |_, _| {})
+        .await
+        .unwrap();
+
+    let file = server.file(&result.file_identifier).unwrap();
+    assert!(file.chunks.iter().all(|chunk| chunk.len == 64 * 1024));
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "compression")]
+
+use std::io::Read;
+
+use bytes::Bytes;
+use streamshare::testing::MockServer;
+use streamshare::StreamShareError;
+
+fn gunzip(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    flate2::read::GzDecoder::new(data)
+        .read_to_end(&mut out)
+        .unwrap();
+    out
+}
+
+#[tokio::test]
+async fn level_above_nine_fails_build() {
+    let server = MockServer::start().await;
+    let result = server
+        .builder()
+        .compress(true)
+        .compression_level(10)
+        .build();
+
+    assert!(matches!(result, Err(StreamShareError::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn higher_level_compresses_more() {
+    let server = MockServer::start().await;
+    let data: Vec<u8> = (0..64 * 1024).map(|i| (i % 7) as u8).collect();
+
+    let mut stored = Vec::new();
+    for level in [0, 9] {
+        let client = server
+            .builder()
+            .compress(true)
+            .compression_level(level)
+            .build()
+            .unwrap();
+        let result = client
+            .upload_bytes("data.bin", Bytes::from(data.clone()), |_, _| {})
+            .await
+            .unwrap();
+        let file = server.file(&result.file_identifier).unwrap();
+        assert_eq!(file.name, "data.bin.gz");
+        assert_eq!(gunzip(&file.data), data);
+        stored.push(file.data.len());
+    }
+
+    assert!(stored[1] < stored[0] / 10, "sizes {:?}", stored);
+}
+
+#[tokio::test]
+async fn checksums_are_rejected_with_compression() {
+    let server = MockServer::start().await;
+    let send = server.builder().compress(true).send_checksum(true).build();
+    let compute = server
+        .builder()
+        .compress(true)
+        .compute_checksum(true)
+        .build();
+
+    assert!(matches!(send, Err(StreamShareError::InvalidConfig(_))));
+    assert!(matches!(compute, Err(StreamShareError::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn unchecked_client_fails_before_creating() {
+    let server = MockServer::start().await;
+    let client = server
+        .builder()
+        .compress(true)
+        .send_checksum(true)
+        .build_unchecked();
+
+    let error = client
+        .upload_bytes("data.bin", Bytes::from_static(b"hello"), |_, _| {})
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, StreamShareError::InvalidConfig(_)));
+    assert!(server.recorder().requests_to("/api/create").is_empty());
+}
@@ -0,0 +1,468 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use streamshare::testing::{MockEtag, MockServer};
+use streamshare::{ConflictDecision, DownloadOutcome, StreamShareError, Validator};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("streamshare-download-{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn dir_arg(dir: &std::path::Path) -> String {
+    format!("{}/", dir.display())
+}
+
+#[tokio::test]
+async fn failed_download_leaves_no_partial_file() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("partial");
+    let (id, _) = server.insert_file("data.bin", vec![1u8; 64 * 1024]);
+    server.set_download_cutoff(Some(1000));
+
+    let result = server.client().download(&id, &dir_arg(&dir), false).await;
+
+    assert!(result.is_err());
+    assert!(!dir.join("data.bin").exists());
+}
+
+#[tokio::test]
+async fn failed_download_keeps_the_file_it_replaced() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("replaced");
+    std::fs::write(dir.join("data.bin"), "old").unwrap();
+    let (id, _) = server.insert_file("data.bin", vec![1u8; 64 * 1024]);
+    server.set_download_cutoff(Some(1000));
+
+    let result = server.client().download(&id, &dir_arg(&dir), true).await;
+
+    assert!(result.is_err());
+    assert!(dir.join("data.bin").exists());
+}
+
+#[tokio::test]
+async fn download_without_content_length_reports_unknown_total() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("chunked");
+    let data = vec![3u8; 100_000];
+    let (id, _) = server.insert_file("data.bin", data.clone());
+    server.set_chunked_downloads(true);
+
+    let mut progress = Vec::new();
+    server
+        .client()
+        .download_with_progress(&id, &dir_arg(&dir), false, |written, total| {
+            progress.push((written, total))
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(dir.join("data.bin")).unwrap(), data);
+    assert!(progress.iter().all(|&(_, total)| total == 0));
+    assert_eq!(progress.last().unwrap().0, data.len() as u64);
+}
+
+#[tokio::test]
+async fn extra_bytes_abort_with_size_mismatch() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("overshoot");
+    let (id, _) = server.insert_file("data.bin", vec![5u8; 10_000]);
+    server.set_download_overshoot(100);
+    // The chunked body has no Content-Length, so the size comes from a
+    // HEAD before the download.
+    let client = server.builder().download_precheck(true).build().unwrap();
+
+    let error = client
+        .download(&id, &dir_arg(&dir), false)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(
+        error,
+        StreamShareError::SizeMismatch { expected: 10_000, actual } if actual > 10_000
+    ));
+    assert!(!dir.join("data.bin").exists());
+}
+
+#[tokio::test]
+async fn long_file_names_are_shortened_keeping_the_extension() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("long-name");
+    let name = format!("{}.txt", "a".repeat(300));
+    let (id, _) = server.insert_file(&name, "data");
+
+    server
+        .client()
+        .download(&id, &dir_arg(&dir), false)
+        .await
+        .unwrap();
+
+    let saved: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert_eq!(saved.len(), 1);
+    assert!(saved[0].len() <= 255, "{} bytes", saved[0].len());
+    assert!(saved[0].starts_with("aaaa") && saved[0].ends_with(".txt"));
+    assert_eq!(std::fs::read(dir.join(&saved[0])).unwrap(), b"data");
+}
+
+#[tokio::test]
+async fn shortening_respects_character_boundaries() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("long-utf8");
+    let name = format!("{}.txt", "é".repeat(200));
+
+    let path = server
+        .client()
+        .resolve_download_path(&dir_arg(&dir), &name, "id", false)
+        .unwrap();
+
+    let saved = path.file_name().unwrap().to_str().unwrap();
+    assert!(saved.len() <= 255);
+    assert!(saved.starts_with('é') && saved.ends_with(".txt"));
+}
+
+async fn conditional_round_trip(server: &MockServer, name: &str) -> DownloadOutcome {
+    let dir = temp_dir(name);
+    let (id, _) = server.insert_file("data.bin", vec![4u8; 1024]);
+    let client = server.client();
+
+    let first = client
+        .download_conditional(&id, &dir_arg(&dir), true, None)
+        .await
+        .unwrap();
+    let DownloadOutcome::Downloaded { etag, .. } = first else {
+        panic!("first download returned NotModified");
+    };
+    let validator = Validator::ETag(etag.expect("no etag returned"));
+
+    client
+        .download_conditional(&id, &dir_arg(&dir), true, Some(&validator))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn strong_etag_round_trips() {
+    let server = MockServer::start().await;
+    server.set_etag(Some(MockEtag::Strong));
+
+    let outcome = conditional_round_trip(&server, "strong-etag").await;
+
+    assert!(matches!(outcome, DownloadOutcome::NotModified));
+    let requests = server.recorder().requests();
+    let sent = requests.last().unwrap().header("if-none-match").unwrap();
+    assert!(sent.starts_with('"'));
+}
+
+#[tokio::test]
+async fn weak_etag_round_trips() {
+    let server = MockServer::start().await;
+    server.set_etag(Some(MockEtag::Weak));
+
+    let outcome = conditional_round_trip(&server, "weak-etag").await;
+
+    assert!(matches!(outcome, DownloadOutcome::NotModified));
+    let requests = server.recorder().requests();
+    let sent = requests.last().unwrap().header("if-none-match").unwrap();
+    assert!(sent.starts_with("W/\""));
+}
+
+#[tokio::test]
+async fn weak_etag_matches_a_strong_one_on_a_full_response() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("weak-vs-strong");
+    let (id, _) = server.insert_file("data.bin", vec![4u8; 1024]);
+    server.set_etag(Some(MockEtag::Strong));
+    server.set_ignore_if_none_match(true);
+    let client = server.client();
+
+    let DownloadOutcome::Downloaded { etag, .. } = client
+        .download_conditional(&id, &dir_arg(&dir), true, None)
+        .await
+        .unwrap()
+    else {
+        panic!("first download returned NotModified");
+    };
+    let validator = Validator::ETag(format!("W/{}", etag.unwrap()));
+
+    let outcome = client
+        .download_conditional(&id, &dir_arg(&dir), true, Some(&validator))
+        .await
+        .unwrap();
+
+    assert!(matches!(outcome, DownloadOutcome::NotModified));
+}
+
+#[tokio::test]
+async fn different_etag_downloads_again() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("different-etag");
+    let (id, _) = server.insert_file("data.bin", vec![4u8; 1024]);
+    server.set_etag(Some(MockEtag::Weak));
+
+    let outcome = server
+        .client()
+        .download_conditional(
+            &id,
+            &dir_arg(&dir),
+            true,
+            Some(&Validator::ETag("W/\"stale\"".into())),
+        )
+        .await
+        .unwrap();
+
+    let DownloadOutcome::Downloaded { path, etag, .. } = outcome else {
+        panic!("stale etag returned NotModified");
+    };
+    assert_eq!(std::fs::read(path).unwrap(), vec![4u8; 1024]);
+    assert!(etag.unwrap().starts_with("W/\""));
+}
+
+async fn download_unnamed(
+    name: &str,
+    fallback_extension: Option<Option<&str>>,
+) -> (String, Vec<String>) {
+    let server = MockServer::start().await;
+    let dir = temp_dir(name);
+    let (id, _) = server.insert_file("data.bin", "data");
+    server.set_omit_file_names(true);
+    let mut builder = server.builder();
+    if let Some(fallback_extension) = fallback_extension {
+        builder = builder.fallback_extension(fallback_extension);
+    }
+
+    builder
+        .build()
+        .unwrap()
+        .download(&id, &dir_arg(&dir), false)
+        .await
+        .unwrap();
+
+    let saved = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    (id, saved)
+}
+
+#[tokio::test]
+async fn unnamed_downloads_default_to_unknown() {
+    let (id, saved) = download_unnamed("fallback-default", None).await;
+
+    assert_eq!(saved, vec![format!("{}.unknown", id)]);
+}
+
+#[tokio::test]
+async fn unnamed_downloads_use_a_custom_extension() {
+    let (id, saved) = download_unnamed("fallback-custom", Some(Some("bin"))).await;
+
+    assert_eq!(saved, vec![format!("{}.bin", id)]);
+}
+
+#[tokio::test]
+async fn unnamed_downloads_can_have_no_extension() {
+    let (id, saved) = download_unnamed("fallback-none", Some(None)).await;
+
+    assert_eq!(saved, vec![id]);
+}
+
+async fn download_over_existing(
+    name: &str,
+    on_conflict: impl Fn(&std::path::Path) -> ConflictDecision + Send + Sync + 'static,
+) -> (PathBuf, Result<(), StreamShareError>) {
+    let server = MockServer::start().await;
+    let dir = temp_dir(name);
+    std::fs::write(dir.join("data.bin"), "old").unwrap();
+    let (id, _) = server.insert_file("data.bin", "new");
+    let client = server.builder().on_conflict(on_conflict).build().unwrap();
+
+    let result = client.download(&id, &dir_arg(&dir), false).await;
+    (dir, result)
+}
+
+#[tokio::test]
+async fn conflict_replace_overwrites() {
+    let (dir, result) =
+        download_over_existing("conflict-replace", |_| ConflictDecision::Replace).await;
+
+    result.unwrap();
+    assert_eq!(std::fs::read(dir.join("data.bin")).unwrap(), b"new");
+}
+
+#[tokio::test]
+async fn conflict_skip_keeps_the_existing_file() {
+    let (dir, result) = download_over_existing("conflict-skip", |_| ConflictDecision::Skip).await;
+
+    let Err(StreamShareError::Skipped { path }) = result else {
+        panic!("expected Skipped, got {:?}", result);
+    };
+    assert_eq!(path, dir.join("data.bin"));
+    assert_eq!(std::fs::read(dir.join("data.bin")).unwrap(), b"old");
+}
+
+#[tokio::test]
+async fn conflict_rename_asks_again_for_taken_names() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("conflict-rename");
+    std::fs::write(dir.join("data.bin"), "old").unwrap();
+    std::fs::write(dir.join("data-1.bin"), "taken").unwrap();
+    let (id, _) = server.insert_file("data.bin", "new");
+    let asked = Arc::new(AtomicUsize::new(0));
+    let counter = asked.clone();
+    let client = server
+        .builder()
+        .on_conflict(move |path| {
+            let n = counter.fetch_add(1, Ordering::Relaxed) + 1;
+            ConflictDecision::Rename(path.with_file_name(format!("data-{}.bin", n)))
+        })
+        .build()
+        .unwrap();
+
+    client.download(&id, &dir_arg(&dir), false).await.unwrap();
+
+    assert_eq!(asked.load(Ordering::Relaxed), 2);
+    assert_eq!(std::fs::read(dir.join("data.bin")).unwrap(), b"old");
+    assert_eq!(std::fs::read(dir.join("data-1.bin")).unwrap(), b"taken");
+    assert_eq!(std::fs::read(dir.join("data-2.bin")).unwrap(), b"new");
+}
+
+#[tokio::test]
+async fn conflicts_fail_without_a_callback() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("conflict-none");
+    std::fs::write(dir.join("data.bin"), "old").unwrap();
+    let (id, _) = server.insert_file("data.bin", "new");
+
+    let result = server.client().download(&id, &dir_arg(&dir), false).await;
+
+    assert!(matches!(result, Err(StreamShareError::FileExists { .. })));
+    assert_eq!(std::fs::read(dir.join("data.bin")).unwrap(), b"old");
+}
+
+#[tokio::test]
+async fn empty_file_names_fall_back_to_the_identifier() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("empty-name");
+    let (id, _) = server.insert_file("", "data");
+
+    server
+        .client()
+        .download(&id, &dir_arg(&dir), false)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        std::fs::read(dir.join(format!("{}.unknown", id))).unwrap(),
+        b"data"
+    );
+}
+
+/// Reassembles the pieces of [`StreamShare::download_segments`].
+async fn collect_segments(
+    client: &streamshare::StreamShare,
+    id: &str,
+    segments: usize,
+) -> Result<Vec<u8>, StreamShareError> {
+    use futures::TryStreamExt;
+
+    let mut pieces: Vec<(u64, bytes::Bytes)> = client
+        .download_segments(id, segments)
+        .await?
+        .try_collect()
+        .await?;
+    pieces.sort_by_key(|(offset, _)| *offset);
+    Ok(pieces.into_iter().flat_map(|(_, data)| data).collect())
+}
+
+#[tokio::test]
+async fn segments_respect_the_per_host_connection_limit() {
+    let server = MockServer::start().await;
+    let data: Vec<u8> = (0..256 * 1024u32).map(|i| i as u8).collect();
+    let (id, _) = server.insert_file("data.bin", data.clone());
+    server.set_response_bandwidth(Some(512 * 1024));
+    let client = server
+        .builder()
+        .max_connections_per_host(2)
+        .build()
+        .unwrap();
+
+    let downloaded = collect_segments(&client, &id, 4).await.unwrap();
+
+    assert_eq!(downloaded, data);
+    assert_eq!(server.peak_open_requests(), 2);
+}
+
+#[tokio::test]
+async fn segments_wait_out_maintenance() {
+    let server = MockServer::start().await;
+    let data: Vec<u8> = (0..256 * 1024u32).map(|i| i as u8).collect();
+    let (id, _) = server.insert_file("data.bin", data.clone());
+    server.set_unavailable_downloads(2);
+    let client = server
+        .builder()
+        .max_maintenance_wait(std::time::Duration::from_secs(60))
+        .build()
+        .unwrap();
+
+    let downloaded = collect_segments(&client, &id, 4).await.unwrap();
+
+    assert_eq!(downloaded, data);
+    let ranges = server
+        .recorder()
+        .requests_to(&format!("/download/{}", id))
+        .into_iter()
+        .filter(|request| request.header("range").is_some())
+        .count();
+    assert_eq!(ranges, 6);
+}
+
+#[tokio::test]
+async fn parallel_download_respects_the_per_host_connection_limit() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("parallel-limit");
+    let data: Vec<u8> = (0..256 * 1024u32).map(|i| i as u8).collect();
+    let (id, _) = server.insert_file("data.bin", data.clone());
+    server.set_response_bandwidth(Some(512 * 1024));
+    let client = server
+        .builder()
+        .max_connections_per_host(2)
+        .build()
+        .unwrap();
+
+    client
+        .download_parallel(&id, &dir_arg(&dir), false, 4, |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(dir.join("data.bin")).unwrap(), data);
+    assert_eq!(server.peak_open_requests(), 2);
+}
+
+#[tokio::test]
+async fn ranged_download_waits_out_maintenance() {
+    let server = MockServer::start().await;
+    let dir = temp_dir("ranged-maintenance");
+    let (id, _) = server.insert_file("data.bin", "0123456789");
+    server.set_unavailable_downloads(1);
+    let client = server
+        .builder()
+        .max_maintenance_wait(std::time::Duration::from_secs(60))
+        .build()
+        .unwrap();
+    let path = dir.join("part.bin");
+    let mut file = tokio::fs::File::create(&path).await.unwrap();
+    file.set_len(4).await.unwrap();
+
+    client
+        .download_into_at(&id, &mut file, 0, 3..7, |_, _| {})
+        .await
+        .unwrap();
+
+    drop(file);
+    assert_eq!(std::fs::read(&path).unwrap(), b"3456");
+}
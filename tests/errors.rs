@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use streamshare::{StreamShare, StreamShareError, TlsVersion};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// A server that answers every connection with `response`, or never answers
+/// if it's `None`, returning its `host:port`.
+async fn serve_raw(response: Option<&'static [u8]>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buffer = [0u8; 4096];
+                let _ = stream.read(&mut buffer).await;
+                match response {
+                    Some(response) => {
+                        let _ = stream.write_all(response).await;
+                    }
+                    None => tokio::time::sleep(Duration::from_secs(60)).await,
+                }
+            });
+        }
+    });
+    addr
+}
+
+fn client(server_url: String) -> StreamShare {
+    StreamShare::builder()
+        .server_url(server_url)
+        .secure(false)
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn refused_connection_is_a_connect_error() {
+    let addr = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+
+    let error = client(addr).usage().await.unwrap_err();
+
+    assert!(
+        matches!(error, StreamShareError::ConnectError(_)),
+        "{error:?}"
+    );
+}
+
+#[tokio::test]
+async fn silent_server_is_a_timeout() {
+    let addr = serve_raw(None).await;
+    let client = StreamShare::builder()
+        .server_url(addr)
+        .secure(false)
+        .read_timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+
+    let error = client.usage().await.unwrap_err();
+
+    assert!(
+        matches!(error, StreamShareError::HttpTimeout(_)),
+        "{error:?}"
+    );
+}
+
+#[tokio::test]
+async fn malformed_json_is_a_decode_error() {
+    let addr = serve_raw(Some(
+        b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 5\r\n\r\n{nope",
+    ))
+    .await;
+
+    let error = client(addr).usage().await.unwrap_err();
+
+    assert!(matches!(error, StreamShareError::Decode(_)), "{error:?}");
+}
+
+#[tokio::test]
+async fn plaintext_answer_to_tls_is_a_tls_error() {
+    let addr = serve_raw(Some(
+        b"HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\n\r\n",
+    ))
+    .await;
+    let client = StreamShare::builder().server_url(addr).build().unwrap();
+
+    let error = client.usage().await.unwrap_err();
+
+    assert!(matches!(error, StreamShareError::TlsError(_)), "{error:?}");
+}
+
+#[tokio::test]
+async fn rustls_failures_are_tls_errors_too() {
+    let addr = serve_raw(Some(
+        b"HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\n\r\n",
+    ))
+    .await;
+    let client = StreamShare::builder()
+        .server_url(addr)
+        .min_tls_version(TlsVersion::Tls12)
+        .build()
+        .unwrap();
+
+    let error = client.usage().await.unwrap_err();
+
+    assert!(matches!(error, StreamShareError::TlsError(_)), "{error:?}");
+}
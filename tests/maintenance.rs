@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+use streamshare::testing::MockServer;
+use streamshare::StreamShareError;
+
+#[tokio::test]
+async fn zero_wait_does_not_retry() {
+    let server = MockServer::start().await;
+    let client = server.client();
+    server.set_unavailable(usize::MAX);
+
+    let error = client.delete("file", "token").await.unwrap_err();
+
+    assert!(matches!(error, StreamShareError::ServerUnavailable { .. }));
+    assert_eq!(server.recorder().requests().len(), 1);
+}
+
+#[tokio::test]
+async fn retry_after_zero_counts_as_a_second() {
+    let server = MockServer::start().await;
+    let client = server
+        .builder()
+        .max_maintenance_wait(Duration::from_secs(2))
+        .build()
+        .unwrap();
+    server.set_unavailable(usize::MAX);
+
+    let started = Instant::now();
+    let error = client.delete("file", "token").await.unwrap_err();
+
+    assert!(matches!(error, StreamShareError::ServerUnavailable { .. }));
+    // The first try and two retries, each a second after the last.
+    assert_eq!(server.recorder().requests().len(), 3);
+    assert!(started.elapsed() >= Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn retries_until_the_server_is_back() {
+    let server = MockServer::start().await;
+    let (id, token) = server.insert_file("a.txt", "data");
+    let client = server
+        .builder()
+        .max_maintenance_wait(Duration::from_secs(60))
+        .build()
+        .unwrap();
+    server.set_unavailable(2);
+
+    client.delete(&id, &token).await.unwrap();
+
+    assert_eq!(server.recorder().requests().len(), 3);
+    assert_eq!(server.file_count(), 0);
+}
+
+#[tokio::test]
+async fn parallel_download_fallback_waits_out_maintenance() {
+    let server = MockServer::start().await;
+    let dir = std::env::temp_dir().join("streamshare-maintenance-fallback");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let (id, _) = server.insert_file("a.txt", "data");
+    let client = server
+        .builder()
+        .max_maintenance_wait(Duration::from_secs(60))
+        .build()
+        .unwrap();
+    server.set_unavailable_downloads(1);
+
+    client
+        .download_parallel(&id, &format!("{}/", dir.display()), false, 1, |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(dir.join("a.txt")).unwrap(), b"data");
+    let gets = server
+        .recorder()
+        .requests()
+        .into_iter()
+        .filter(|request| request.method == "GET")
+        .count();
+    assert_eq!(gets, 2);
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "middleware")]
+
+use futures::future::BoxFuture;
+use http::Extensions;
+use reqwest::{Request, Response};
+use reqwest_middleware::{ClientBuilder, Next};
+use streamshare::testing::MockServer;
+
+/// Marks every request it passes on.
+fn tag<'a>(
+    mut request: Request,
+    extensions: &'a mut Extensions,
+    next: Next<'a>,
+) -> BoxFuture<'a, reqwest_middleware::Result<Response>> {
+    request
+        .headers_mut()
+        .insert("x-middleware", "seen".parse().unwrap());
+    Box::pin(next.run(request, extensions))
+}
+
+#[tokio::test]
+async fn warm_up_goes_through_middleware() {
+    let server = MockServer::start().await;
+    let middleware = ClientBuilder::new(reqwest::Client::new()).with(tag).build();
+    let client = server.builder().middleware(middleware).build().unwrap();
+
+    client.warm_up().await.unwrap();
+
+    let warm_up = &server.recorder().requests_to("/")[0];
+    assert_eq!(warm_up.method, "HEAD");
+    assert_eq!(warm_up.header("x-middleware"), Some("seen"));
+}
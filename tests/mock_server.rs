@@ -0,0 +1,79 @@
+use bytes::Bytes;
+use streamshare::testing::MockServer;
+use streamshare::DeleteOutcome;
+
+#[tokio::test]
+async fn upload_download_and_delete_round_trip() {
+    let server = MockServer::start().await;
+    let client = server.builder().chunk_size(1024).build().unwrap();
+    let data: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+
+    let result = client
+        .upload_bytes("data.bin", Bytes::from(data.clone()), |_, _| {})
+        .await
+        .unwrap();
+    let file = server.file(&result.file_identifier).unwrap();
+    assert_eq!(file.name, "data.bin");
+    assert_eq!(file.size, Some(5000));
+    assert!(file.complete);
+    assert_eq!(file.chunks.len(), 5);
+
+    let mut downloaded = Vec::new();
+    client
+        .download_to(&result.file_identifier, &mut downloaded)
+        .await
+        .unwrap();
+    assert_eq!(downloaded, data);
+
+    let outcome = client
+        .delete(&result.file_identifier, &result.deletion_token)
+        .await
+        .unwrap();
+    assert_eq!(outcome, DeleteOutcome::Deleted);
+    assert_eq!(server.file_count(), 0);
+}
+
+#[tokio::test]
+async fn wrong_token_and_missing_files_are_refused() {
+    let server = MockServer::start().await;
+    let client = server.client();
+    let (id, _) = server.insert_file("a.txt", "data");
+
+    assert!(client.delete(&id, "wrong").await.is_err());
+    assert_eq!(
+        client.delete("missing", "token").await.unwrap(),
+        DeleteOutcome::AlreadyAbsent
+    );
+    assert!(client
+        .download_to("missing", &mut Vec::new())
+        .await
+        .is_err());
+    assert_eq!(server.file_count(), 1);
+}
+
+#[tokio::test]
+async fn recorder_sees_requests_and_frames() {
+    let server = MockServer::start().await;
+    let recorder = server.recorder();
+    let client = server.builder().chunk_size(2).build().unwrap();
+
+    client
+        .upload_bytes("a.txt", Bytes::from_static(b"hello"), |_, _| {})
+        .await
+        .unwrap();
+
+    let create = &recorder.requests_to("/api/create")[0];
+    assert_eq!(create.method, "POST");
+    assert_eq!(create.json().unwrap()["name"], "a.txt");
+    assert_eq!(recorder.binary_frames_sent(), 3);
+    let acks = recorder
+        .frames()
+        .into_iter()
+        .filter(|frame| !frame.from_client)
+        .count();
+    assert_eq!(acks, 3);
+
+    recorder.clear();
+    assert!(recorder.requests().is_empty());
+    assert!(recorder.frames().is_empty());
+}
@@ -0,0 +1,27 @@
+use bytes::Bytes;
+use streamshare::testing::MockServer;
+
+#[tokio::test]
+async fn verification_after_an_upload_reuses_the_connection() {
+    let server = MockServer::start().await;
+    let client = server.client();
+
+    let result = client
+        .upload_bytes("data.bin", Bytes::from_static(b"hello"), |_, _| {})
+        .await
+        .unwrap();
+    // The create request and the upload WebSocket.
+    assert_eq!(server.connection_count(), 2);
+
+    let info = client.info(&result.file_identifier).await.unwrap();
+    assert_eq!(info.size, Some(5));
+    let mut downloaded = Vec::new();
+    client
+        .download_to(&result.file_identifier, &mut downloaded)
+        .await
+        .unwrap();
+    assert_eq!(downloaded, b"hello");
+
+    // Both went over the pooled connection of the create request.
+    assert_eq!(server.connection_count(), 2);
+}
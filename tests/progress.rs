@@ -0,0 +1,35 @@
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use streamshare::testing::MockServer;
+
+#[tokio::test]
+async fn progress_stays_monotonic_while_chunks_are_resent() {
+    let server = MockServer::start().await;
+    server.set_nack_every(Some(3));
+    let retries = Arc::new(Mutex::new(0));
+    let sink = retries.clone();
+    let client = server
+        .builder()
+        .chunk_size(1000)
+        .on_complete(move |metrics| *sink.lock().unwrap() = metrics.retries)
+        .build()
+        .unwrap();
+    let data: Vec<u8> = (0..20_500u32).map(|i| i as u8).collect();
+
+    let mut progress = Vec::new();
+    let result = client
+        .upload_bytes("data.bin", Bytes::from(data.clone()), |uploaded, total| {
+            progress.push((uploaded, total))
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(server.file(&result.file_identifier).unwrap().data, data);
+    assert!(*retries.lock().unwrap() > 0);
+    assert!(progress.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert!(progress.iter().all(|&(uploaded, total)| uploaded <= total));
+    // One report per accepted chunk, however often it was sent.
+    assert_eq!(progress.len(), 21);
+    assert_eq!(progress.last(), Some(&(20_500, 20_500)));
+}
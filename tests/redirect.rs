@@ -0,0 +1,58 @@
+use streamshare::testing::MockServer;
+
+#[tokio::test]
+async fn downloads_follow_their_own_redirect_limit() {
+    let server = MockServer::start().await;
+    let (id, _) = server.insert_file("a.txt", "data");
+    server.set_download_redirects(1);
+    let client = server
+        .builder()
+        .max_redirects(0)
+        .download_max_redirects(1)
+        .build()
+        .unwrap();
+
+    let mut downloaded = Vec::new();
+    client.download_to(&id, &mut downloaded).await.unwrap();
+
+    assert_eq!(downloaded, b"data");
+    assert_eq!(
+        server
+            .recorder()
+            .requests_to(&format!("/download/{}", id))
+            .len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn too_many_download_redirects_fail() {
+    let server = MockServer::start().await;
+    let (id, _) = server.insert_file("a.txt", "data");
+    server.set_download_redirects(2);
+    let client = server
+        .builder()
+        .max_redirects(5)
+        .download_max_redirects(1)
+        .build()
+        .unwrap();
+
+    assert!(client.download_to(&id, &mut Vec::new()).await.is_err());
+    assert_eq!(
+        server
+            .recorder()
+            .requests_to(&format!("/download/{}", id))
+            .len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn downloads_use_max_redirects_by_default() {
+    let server = MockServer::start().await;
+    let (id, _) = server.insert_file("a.txt", "data");
+    server.set_download_redirects(1);
+    let client = server.builder().max_redirects(0).build().unwrap();
+
+    assert!(client.download_to(&id, &mut Vec::new()).await.is_err());
+}
@@ -0,0 +1,57 @@
+use streamshare::testing::MockServer;
+use streamshare::StreamShareError;
+
+fn source_url(server: &MockServer, data: Vec<u8>) -> String {
+    let (id, _) = server.insert_file("source.bin", data);
+    format!("http://{}/download/{}", server.server_url(), id)
+}
+
+#[tokio::test]
+async fn relay_sends_the_source_content_length() {
+    let server = MockServer::start().await;
+    let url = source_url(&server, vec![7u8; 100 * 1024]);
+    let mut totals = Vec::new();
+
+    let result = server
+        .client()
+        .relay(&url, "copy.bin", |_, total| totals.push(total))
+        .await
+        .unwrap();
+
+    let file = server.file(&result.file_identifier).unwrap();
+    assert_eq!(file.size, Some(100 * 1024));
+    assert_eq!(file.data, vec![7u8; 100 * 1024]);
+    assert!(!totals.is_empty());
+    assert!(totals.iter().all(|&total| total == 100 * 1024));
+}
+
+#[tokio::test]
+async fn relay_without_content_length_has_unknown_size() {
+    let server = MockServer::start().await;
+    let url = source_url(&server, vec![7u8; 100 * 1024]);
+    server.set_chunked_downloads(true);
+    let mut totals = Vec::new();
+
+    let result = server
+        .client()
+        .relay(&url, "copy.bin", |_, total| totals.push(total))
+        .await
+        .unwrap();
+
+    let file = server.file(&result.file_identifier).unwrap();
+    assert_eq!(file.size, None);
+    assert_eq!(file.data, vec![7u8; 100 * 1024]);
+    assert!(!totals.is_empty());
+    assert!(totals.iter().all(|&total| total == 0));
+}
+
+#[tokio::test]
+async fn truncated_source_fails_the_relay() {
+    let server = MockServer::start().await;
+    let url = source_url(&server, vec![7u8; 100 * 1024]);
+    server.set_download_cutoff(Some(10 * 1024));
+
+    let result = server.client().relay(&url, "copy.bin", |_, _| {}).await;
+
+    assert!(matches!(result, Err(StreamShareError::ReadError { .. })));
+}
@@ -0,0 +1,96 @@
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use streamshare::testing::MockServer;
+use streamshare::{StreamShare, TransferKind, TransferMetrics};
+
+/// A client that records everything passed to `on_complete`.
+fn recording(server: &MockServer) -> (StreamShare, Arc<Mutex<Vec<TransferMetrics>>>) {
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let sink = reported.clone();
+    let client = server
+        .builder()
+        .chunk_size(1024)
+        .on_complete(move |metrics| sink.lock().unwrap().push(*metrics))
+        .build()
+        .unwrap();
+    (client, reported)
+}
+
+fn temp_dir(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("streamshare-metrics-{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    format!("{}/", dir.display())
+}
+
+#[tokio::test]
+async fn upload_reports_bytes_and_chunks() {
+    let server = MockServer::start().await;
+    let (client, reported) = recording(&server);
+
+    client
+        .upload_bytes("data.bin", Bytes::from(vec![1u8; 4096]), |_, _| {})
+        .await
+        .unwrap();
+
+    let reported = reported.lock().unwrap();
+    assert_eq!(reported.len(), 1);
+    assert_eq!(reported[0].kind, TransferKind::Upload);
+    assert!(reported[0].success);
+    assert_eq!(reported[0].bytes, 4096);
+    assert_eq!(reported[0].chunks, 4);
+}
+
+#[tokio::test]
+async fn failed_create_is_reported() {
+    let server = MockServer::start().await;
+    let (client, reported) = recording(&server);
+    server.set_unavailable(usize::MAX);
+
+    client
+        .upload_bytes("data.bin", Bytes::from_static(b"hello"), |_, _| {})
+        .await
+        .unwrap_err();
+
+    let reported = reported.lock().unwrap();
+    assert_eq!(reported.len(), 1);
+    assert_eq!(reported[0].kind, TransferKind::Upload);
+    assert!(!reported[0].success);
+    assert_eq!(reported[0].bytes, 0);
+    assert_eq!(server.recorder().binary_frames_sent(), 0);
+}
+
+#[tokio::test]
+async fn refused_download_is_reported() {
+    let server = MockServer::start().await;
+    let (client, reported) = recording(&server);
+
+    client
+        .download("missing", &temp_dir("refused"), false)
+        .await
+        .unwrap_err();
+
+    let reported = reported.lock().unwrap();
+    assert_eq!(reported.len(), 1);
+    assert_eq!(reported[0].kind, TransferKind::Download);
+    assert!(!reported[0].success);
+}
+
+#[tokio::test]
+async fn download_reports_bytes() {
+    let server = MockServer::start().await;
+    let (client, reported) = recording(&server);
+    let (id, _) = server.insert_file("data.txt", vec![2u8; 3000]);
+
+    client
+        .download(&id, &temp_dir("download"), false)
+        .await
+        .unwrap();
+
+    let reported = reported.lock().unwrap();
+    assert_eq!(reported.len(), 1);
+    assert_eq!(reported[0].kind, TransferKind::Download);
+    assert!(reported[0].success);
+    assert_eq!(reported[0].bytes, 3000);
+}
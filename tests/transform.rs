@@ -0,0 +1,85 @@
+#![cfg(all(feature = "compression", feature = "encryption"))]
+
+use std::io::Read;
+
+use streamshare::testing::MockServer;
+use streamshare::{
+    Checksum, ChunkTransform, EncryptTransform, GzipTransform, HashAlgorithm, HashTransform,
+};
+
+const KEY: [u8; 32] = [42; 32];
+
+fn input() -> Vec<u8> {
+    (0..200_000u32)
+        .flat_map(|i| (i % 1000).to_le_bytes())
+        .collect()
+}
+
+fn gunzip(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    flate2::read::GzDecoder::new(data)
+        .read_to_end(&mut out)
+        .unwrap();
+    out
+}
+
+/// Uploads `data` through gzip and encryption and returns what the server
+/// stored and the file's identifier.
+async fn upload_encrypted(server: &MockServer, data: &[u8]) -> (Vec<u8>, String, Checksum) {
+    let client = server.builder().chunk_size(16 * 1024).build().unwrap();
+    let hash = HashTransform::new(HashAlgorithm::Sha256);
+    let transforms: Vec<Box<dyn ChunkTransform>> = vec![
+        Box::new(GzipTransform::new(6)),
+        Box::new(EncryptTransform::new(KEY)),
+        Box::new(hash.clone()),
+    ];
+    let result = client
+        .upload_reader_transformed("data.bin", data, None, transforms, |_, _| {})
+        .await
+        .unwrap();
+    let stored = server.file(&result.file_identifier).unwrap().data;
+    (stored, result.file_identifier, hash.checksum())
+}
+
+#[tokio::test]
+async fn gzip_then_encrypt_round_trips() {
+    let server = MockServer::start().await;
+    let data = input();
+    let (_, id, checksum) = upload_encrypted(&server, &data).await;
+
+    let mut downloaded = Vec::new();
+    server
+        .client()
+        .download_to(&id, &mut downloaded)
+        .await
+        .unwrap();
+
+    // Hashed last, so the checksum covers the stored ciphertext.
+    let mut expected = HashTransform::new(HashAlgorithm::Sha256);
+    expected.transform(downloaded.clone().into()).unwrap();
+    assert_eq!(checksum.digest, expected.checksum().digest);
+    let compressed = EncryptTransform::decrypt(&KEY, &downloaded).unwrap();
+    assert!(compressed.len() < data.len() / 4);
+    assert_eq!(gunzip(&compressed), data);
+}
+
+#[tokio::test]
+async fn truncated_download_fails_to_decrypt() {
+    let server = MockServer::start().await;
+    let (stored, _, _) = upload_encrypted(&server, &input()).await;
+
+    // Without the 20-byte end marker every remaining frame is intact, and
+    // cut off mid-frame the last one is incomplete; both must be caught.
+    for cut in [20, 1000] {
+        let (id, _) = server.insert_file("cut.bin", &stored[..stored.len() - cut]);
+        let mut downloaded = Vec::new();
+        server
+            .client()
+            .download_to(&id, &mut downloaded)
+            .await
+            .unwrap();
+
+        let error = EncryptTransform::decrypt(&KEY, &downloaded).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use streamshare::testing::MockServer;
+use streamshare::{
+    DeleteOutcome, HttpRequest, HttpResponse, HttpTransport, ReqwestTransport, StreamShare,
+    StreamShareError,
+};
+
+/// Passes requests on to `reqwest`, remembering `METHOD path` of each.
+struct Recording {
+    inner: ReqwestTransport,
+    seen: Arc<Mutex<Vec<String>>>,
+}
+
+impl HttpTransport for Recording {
+    fn execute(
+        &self,
+        request: HttpRequest,
+    ) -> BoxFuture<'_, Result<HttpResponse, StreamShareError>> {
+        let path = reqwest::Url::parse(&request.url)
+            .unwrap()
+            .path()
+            .to_string();
+        self.seen
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", request.method, path));
+        self.inner.execute(request)
+    }
+}
+
+fn recording(server: &MockServer) -> (StreamShare, Arc<Mutex<Vec<String>>>) {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let client = server
+        .builder()
+        .admin_key("admin")
+        .max_maintenance_wait(Duration::from_secs(60))
+        .http_transport(Recording {
+            inner: ReqwestTransport::new(reqwest::Client::new()),
+            seen: seen.clone(),
+        })
+        .build()
+        .unwrap();
+    (client, seen)
+}
+
+#[tokio::test]
+async fn admin_delete_goes_through_the_transport() {
+    let server = MockServer::start().await;
+    let (client, seen) = recording(&server);
+    server.set_unavailable(1);
+
+    // The mock has no admin endpoint, so the retried request gets a 404.
+    let outcome = client.admin_delete("file").await.unwrap();
+
+    assert_eq!(outcome, DeleteOutcome::AlreadyAbsent);
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [
+            "DELETE /api/admin/delete/file",
+            "DELETE /api/admin/delete/file"
+        ]
+    );
+    let request = &server.recorder().requests()[0];
+    assert_eq!(request.header("authorization"), Some("Bearer admin"));
+}
+
+#[tokio::test]
+async fn update_goes_through_the_transport() {
+    let server = MockServer::start().await;
+    let (client, seen) = recording(&server);
+    let path = std::env::temp_dir().join("streamshare-transport-update.txt");
+    std::fs::write(&path, "new").unwrap();
+    server.set_unavailable(1);
+
+    let error = client
+        .update("file", "token", path.to_str().unwrap(), |_, _| {})
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, StreamShareError::UpdateFailed { .. }));
+    assert_eq!(
+        *seen.lock().unwrap(),
+        ["POST /api/update/file/token", "POST /api/update/file/token"]
+    );
+}
@@ -0,0 +1,173 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use streamshare::testing::MockServer;
+use streamshare::{StreamShareError, UploadOptions};
+
+#[tokio::test]
+async fn expiry_skips_direct_upload() {
+    let server = MockServer::start().await;
+    let client = server.builder().small_file_threshold(1024).build().unwrap();
+    let options = UploadOptions {
+        expires_in: Some(Duration::from_secs(3600)),
+        ..Default::default()
+    };
+
+    let result = client
+        .upload_reader_with_options("small.txt", &b"hello"[..], Some(5), &options, |_, _| {})
+        .await
+        .unwrap();
+
+    let recorder = server.recorder();
+    assert!(recorder.requests_to("/api/upload").is_empty());
+    let create = &recorder.requests_to("/api/create")[0];
+    assert_eq!(create.json().unwrap()["expiresIn"], 3600);
+    assert!(result.expires_at.is_some());
+    assert_eq!(server.file(&result.file_identifier).unwrap().data, b"hello");
+}
+
+#[tokio::test]
+async fn small_upload_goes_direct() {
+    let server = MockServer::start().await;
+    let client = server.builder().small_file_threshold(1024).build().unwrap();
+
+    let result = client
+        .upload_reader("small.txt", &b"hello"[..], Some(5), |_, _| {})
+        .await
+        .unwrap();
+
+    let recorder = server.recorder();
+    assert_eq!(recorder.requests_to("/api/upload").len(), 1);
+    assert!(recorder.requests_to("/api/create").is_empty());
+    assert_eq!(server.file(&result.file_identifier).unwrap().data, b"hello");
+}
+
+#[tokio::test]
+async fn cancel_before_the_first_chunk_sends_nothing() {
+    let server = MockServer::start().await;
+    let options = UploadOptions {
+        abort: Some(Arc::new(AtomicBool::new(true))),
+        ..Default::default()
+    };
+
+    let result = server
+        .client()
+        .upload_reader_with_options(
+            "data.bin",
+            &[1u8; 4096][..],
+            Some(4096),
+            &options,
+            |_, _| {},
+        )
+        .await;
+
+    assert!(matches!(result, Err(StreamShareError::Cancelled)));
+    assert!(server.recorder().requests().is_empty());
+    assert_eq!(server.file_count(), 0);
+}
+
+#[tokio::test]
+async fn cancel_during_create_returns_promptly() {
+    let server = MockServer::start().await;
+    let client = server
+        .builder()
+        .max_maintenance_wait(Duration::from_secs(3600))
+        .build()
+        .unwrap();
+    server.set_unavailable(usize::MAX);
+    let abort = Arc::new(AtomicBool::new(false));
+    let options = UploadOptions {
+        abort: Some(abort.clone()),
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        abort.store(true, Ordering::Relaxed);
+    });
+
+    let started = Instant::now();
+    let result = client
+        .upload_reader_with_options(
+            "data.bin",
+            &[1u8; 4096][..],
+            Some(4096),
+            &options,
+            |_, _| {},
+        )
+        .await;
+
+    assert!(matches!(result, Err(StreamShareError::Cancelled)));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(!server.recorder().requests_to("/api/create").is_empty());
+    assert_eq!(server.recorder().binary_frames_sent(), 0);
+}
+
+#[tokio::test]
+async fn custom_origin_reaches_the_handshake() {
+    let server = MockServer::start().await;
+    let client = server
+        .builder()
+        .origin("https://share.example.com")
+        .build()
+        .unwrap();
+
+    let result = client
+        .upload_reader("data.bin", &[1u8; 4096][..], Some(4096), |_, _| {})
+        .await
+        .unwrap();
+
+    let path = format!("/api/upload/{}", result.file_identifier);
+    let handshake = &server.recorder().requests_to(&path)[0];
+    assert_eq!(
+        handshake.header("origin"),
+        Some("https://share.example.com")
+    );
+}
+
+#[tokio::test]
+async fn no_origin_is_sent_by_default() {
+    let server = MockServer::start().await;
+
+    let result = server
+        .client()
+        .upload_reader("data.bin", &[1u8; 4096][..], Some(4096), |_, _| {})
+        .await
+        .unwrap();
+
+    let path = format!("/api/upload/{}", result.file_identifier);
+    let handshake = &server.recorder().requests_to(&path)[0];
+    assert_eq!(handshake.header("origin"), None);
+}
+
+fn empty_file(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("streamshare-upload-{}.txt", name));
+    std::fs::write(&path, b"").unwrap();
+    path.display().to_string()
+}
+
+#[tokio::test]
+async fn reject_empty_fails_before_sending() {
+    let server = MockServer::start().await;
+    let path = empty_file("reject-empty");
+    let client = server.builder().reject_empty(true).build().unwrap();
+
+    let result = client.upload(&path, |_, _| {}).await;
+
+    let Err(StreamShareError::EmptyFile { path: empty }) = result else {
+        panic!("expected EmptyFile, got {:?}", result);
+    };
+    assert_eq!(empty, std::path::PathBuf::from(&path));
+    assert!(server.recorder().requests().is_empty());
+}
+
+#[tokio::test]
+async fn empty_files_upload_by_default() {
+    let server = MockServer::start().await;
+    let path = empty_file("allow-empty");
+
+    let result = server.client().upload(&path, |_, _| {}).await.unwrap();
+
+    let file = server.file(&result.file_identifier).unwrap();
+    assert!(file.data.is_empty());
+}